@@ -103,7 +103,7 @@ dfx canister call aptos_route multi_rpc_config '()' --network $NETWORK
 
 # if required, update key type
 # dfx canister call aptos_route query_key_type "($KEYTYPE)" --network $NETWORK
-# dfx canister call aptos_route update_key_type "($KEYTYPE)" --network $NETWORK
+# dfx canister call aptos_route update_key_type "($KEYTYPE, \"${new_route_address}\")" --network $NETWORK
 
 # if required, update provider
 # provider=variant{Mainnet}
@@ -116,7 +116,9 @@ dfx canister call aptos_route add_aptos_port "(
 
 dfx canister call aptos_route aptos_ports '()' --network $NETWORK
 
-dfx canister call aptos_route update_key_type '(variant {Native})' --network $NETWORK
+# the native key requires the canister built with `--features native-key`,
+# confirm the switch with the new route address
+dfx canister call aptos_route update_key_type "($KEYTYPE, \"${aptos_route_address}\")" --network $NETWORK
 dfx canister call aptos_route query_key_type '()' --network $NETWORK
 
 
@@ -3,31 +3,29 @@ pub const GET_SUPPLY_SIZE_ESTIMATE: u64 = 1024;
 pub const GET_EPOCH_INFO_SIZE_ESTIMATE: u64 = 56;
 
 pub const NODES_IN_SUBNET: u32 = 34;
+
+pub const ACCOUNT_RESPONSE_SIZE_ESTIMATE: u64 = 1000;
+pub const VIEW_RESPONSE_SIZE_ESTIMATE: u64 = 1000;
+pub const SUBMIT_TX_RESPONSE_SIZE_ESTIMATE: u64 = 5000;
+/// The signed mint tx with the ed25519 authenticator, only used to estimate the outcall cost.
+pub const SIGNED_TX_SIZE_ESTIMATE: u64 = 512;
+
+pub const FA_METADATA_TYPE: &str = "0x1::fungible_asset::Metadata";
+pub const FA_METADATA_SIZE_ESTIMATE: u64 = 2 * 1024;
 // https://internetcomputer.org/docs/current/references/t-sigs-how-it-works/#fees-for-the-t-schnorr-production-key
 // pub const EDDSA_SIGN_COST: u128 = 26_153_846_153;
 pub const EDDSA_SIGN_COST: u128 = 26_200_000_000;
+// https://internetcomputer.org/docs/current/references/t-ecdsa-how-it-works/#fees-for-the-t-ecdsa-production-key
+pub const ECDSA_SIGN_COST: u128 = 26_200_000_000;
 
 // HTTP outcall cost calculation
 // See https://internetcomputer.org/docs/current/developer-docs/gas-cost#special-features
 
-// pub const INGRESS_OVERHEAD_BYTES: u128 = 100;
-// pub const INGRESS_MESSAGE_RECEIVED_COST: u128 = 1_200_000;
-// pub const INGRESS_MESSAGE_BYTE_RECEIVED_COST: u128 = 2_000;
-pub const INGRESS_OVERHEAD_BYTES: u128 = 100;
-pub const INGRESS_MESSAGE_RECEIVED_COST: u128 = 1_200_000;
-pub const INGRESS_MESSAGE_BYTE_RECEIVED_COST: u128 = 2_000;
-
 pub const HTTP_OUTCALL_REQUEST_BASE_COST: u128 = 3_000_000;
 pub const HTTP_OUTCALL_REQUEST_PER_NODE_COST: u128 = 60_000;
 pub const HTTP_OUTCALL_REQUEST_COST_PER_BYTE: u128 = 400;
 pub const HTTP_OUTCALL_RESPONSE_COST_PER_BYTE: u128 = 800;
 
-// /// Minimum number of bytes charged for a URL; improves consistency of costs between providers
-pub const RPC_URL_COST_BYTES: u32 = 256;
-
-// /// Additional cost of operating the canister per subnet node
-pub const CANISTER_OVERHEAD: u128 = 1_000_000;
-
 /// This constant is our approximation of the expected header size.
 /// The HTTP standard doesn't define any limit, and many implementations limit
 /// the headers size to 8 KiB. We chose a lower limit because headers observed on most providers
@@ -50,6 +48,14 @@ pub const TRANSACTION_RESPONSE_SIZE_ESTIMATE: u64 = 15_000;
 
 pub const TRANSACTION_STATUS_RESPONSE_SIZE_ESTIMATE: u64 = 256;
 
+/// The ledger info is read from the response headers, the body is dropped by the transform.
+pub const LEDGER_INFO_RESPONSE_SIZE_ESTIMATE: u64 = 1024;
+
+/// The ledger values are rounded down to these granularities before consensus,
+/// because each replica reads the ledger info at a slightly different time.
+pub const LEDGER_VERSION_GRANULARITY: u64 = 1_000;
+pub const LEDGER_TIMESTAMP_GRANULARITY_USECS: u64 = 60_000_000;
+
 /// a tx includes tansfer + burn+ memo  should be around 5000 bytes long.
 pub const TX_MEMO_RESP_SIZE_ESTIMATE: u64 = 5 * 1024;
 
@@ -3,8 +3,8 @@ pub mod aptos_providers;
 pub mod constants;
 pub mod error;
 pub mod rest_client;
+pub mod pricing;
 pub mod types;
-pub mod utils;
 pub use types::*;
 pub mod request;
 pub mod response;
@@ -0,0 +1,302 @@
+use candid::CandidType;
+use ic_cdk::api::management_canister::http_request::{
+    CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
+use serde::{Deserialize, Serialize};
+
+use super::constants::*;
+use super::request::{build_rest_req, AtosRequest, RestReq};
+use crate::ck_eddsa::KeyType;
+use crate::config::read_config;
+use crate::multisig::{self, SignerSource};
+
+/// The estimated cost of one outcall made by `RestClient::call`
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutcallEstimate {
+    pub request: String,
+    pub request_bytes: u64,
+    pub max_response_bytes: u64,
+    pub cycles: u128,
+}
+
+/// The estimated cost of handling one request, the tx status may be polled more than once
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RequestCostEstimate {
+    pub request_type: String,
+    pub outcalls: Vec<OutcallEstimate>,
+    pub sign_cycles: u128,
+    pub total: u128,
+}
+
+/// The outcall argument sent by `RestClient::call`, the headers are added to the response limit
+pub fn http_request_arg(
+    mut req: RestReq,
+    max_response_bytes: u64,
+    transform: Option<TransformContext>,
+    forward: &Option<String>,
+) -> CanisterHttpRequestArgument {
+    let transform = transform.unwrap_or(TransformContext::from_name(
+        "cleanup_response".to_owned(),
+        vec![],
+    ));
+
+    // add forward address
+    if let Some(forward) = forward {
+        req.headers.push(HttpHeader {
+            name: FORWARD_KEY.to_string(),
+            value: forward.to_owned(),
+        });
+    }
+
+    CanisterHttpRequestArgument {
+        url: req.url.to_string(),
+        max_response_bytes: Some(max_response_bytes + HEADER_SIZE_LIMIT),
+        method: req.method,
+        headers: req.headers,
+        body: req.body,
+        transform: Some(transform),
+    }
+}
+
+/// The size of the `http_request` call as charged by the management canister
+pub fn request_bytes(arg: &CanisterHttpRequestArgument) -> u128 {
+    // 12 is "http_request".len().
+    candid::utils::encode_args((arg,))
+        .expect("Failed to encode arguments.")
+        .len() as u128
+        + 12
+}
+
+/// Calculates the baseline cost of sending a JSON-RPC request using HTTP outcalls.
+pub fn http_request_required_cycles(
+    arg: &CanisterHttpRequestArgument,
+    nodes_in_subnet: u32,
+) -> u128 {
+    let max_response_bytes = match arg.max_response_bytes {
+        Some(ref n) => *n as u128,
+        None => 2 * 1024 * 1024, // default 2MiB
+    };
+    let nodes_in_subnet = nodes_in_subnet as u128;
+
+    // The coefficients can be found in [this page](https://internetcomputer.org/docs/current/developer-docs/production/computation-and-storage-costs).
+    // header_len = header_1.name + header_1.value + ... + header_n.name + header_n.value
+    // request_size = url.len + transform.name.len + transform.context.len + body.len + header_len
+    // http_outcall_cost = per_call_cost + per_request_byte_cost * request_size + per_response_byte_cost * max_response_size
+    // scaling_factor = subnet_size / 13
+    // total_cost = scaling_factor * http_outcall_cost
+
+    (HTTP_OUTCALL_REQUEST_BASE_COST
+        + HTTP_OUTCALL_REQUEST_PER_NODE_COST * nodes_in_subnet
+        + HTTP_OUTCALL_REQUEST_COST_PER_BYTE * request_bytes(arg)
+        + HTTP_OUTCALL_RESPONSE_COST_PER_BYTE * max_response_bytes)
+        * nodes_in_subnet
+}
+
+pub fn estimate_outcall(
+    request: &str,
+    arg: &CanisterHttpRequestArgument,
+    nodes_in_subnet: u32,
+) -> OutcallEstimate {
+    OutcallEstimate {
+        request: request.to_string(),
+        request_bytes: request_bytes(arg) as u64,
+        max_response_bytes: arg.max_response_bytes.unwrap_or_default(),
+        cycles: http_request_required_cycles(arg, nodes_in_subnet),
+    }
+}
+
+/// the cycles paid to sign one tx with the route key
+pub fn sign_cycles(key_type: &KeyType) -> u128 {
+    match key_type {
+        KeyType::ChainKey => EDDSA_SIGN_COST,
+        KeyType::Native(_) => 0,
+        KeyType::ChainKeySecp256k1 => ECDSA_SIGN_COST,
+        // only the chain key signer of the canister is paid, the others sign for free
+        KeyType::MultiEd25519 => multisig::multisig_config()
+            .map(|config| {
+                config
+                    .signers
+                    .iter()
+                    .filter(|signer| matches!(signer.source, SignerSource::ChainKey))
+                    .count() as u128
+                    * EDDSA_SIGN_COST
+            })
+            .unwrap_or_default(),
+    }
+}
+
+// the body is sized like a signed mint tx, the real one depends on the payload
+fn sample_submit_req() -> RestReq {
+    let provider = read_config(|s| s.get().rpc_provider.to_owned());
+    RestReq {
+        method: HttpMethod::POST,
+        headers: vec![HttpHeader {
+            name: "Content-Type".to_string(),
+            value: "application/x.aptos.signed_transaction+bcs".to_string(),
+        }],
+        url: format!("{}/{}/transactions", provider.url(), APTOS_API_VERSION),
+        body: Some(vec![0; SIGNED_TX_SIZE_ESTIMATE as usize]),
+    }
+}
+
+fn estimate_atos_request(
+    name: &str,
+    req: AtosRequest,
+    forward: &Option<String>,
+    nodes_in_subnet: u32,
+) -> OutcallEstimate {
+    let max_response_bytes = req.max_response_bytes();
+    let arg = http_request_arg(build_rest_req(req), max_response_bytes, None, forward);
+    estimate_outcall(name, &arg, nodes_in_subnet)
+}
+
+/// price the outcalls and the signatures of each request type without sending them
+pub fn estimate_request_costs() -> Vec<RequestCostEstimate> {
+    let (nodes_in_subnet, forward, key_type, rpc_list) = read_config(|s| {
+        (
+            s.get().nodes_in_subnet,
+            s.get().forward.to_owned(),
+            s.get().key_type.to_owned(),
+            s.get().multi_rpc_config.rpc_list.to_owned(),
+        )
+    });
+    let address = "0x1".to_string();
+    let tx_hash = format!("0x{}", "0".repeat(64));
+
+    let get_account = estimate_atos_request(
+        "get_account",
+        AtosRequest::GetAccount {
+            address: address.to_owned(),
+        },
+        &forward,
+        nodes_in_subnet,
+    );
+    let submit_tx = estimate_outcall(
+        "summit_tx",
+        &http_request_arg(
+            sample_submit_req(),
+            SUBMIT_TX_RESPONSE_SIZE_ESTIMATE,
+            None,
+            &forward,
+        ),
+        nodes_in_subnet,
+    );
+    let get_tx = |url: Option<String>| {
+        estimate_atos_request(
+            "get_transaction_by_hash",
+            AtosRequest::GetTransactionByHash {
+                txn_hash: tx_hash.to_owned(),
+                url: url.to_owned(),
+            },
+            &url,
+            nodes_in_subnet,
+        )
+    };
+    let get_ledger = |url: Option<String>| {
+        estimate_atos_request(
+            "get_ledger_state",
+            AtosRequest::GetLedgerInfo {
+                url: url.to_owned(),
+            },
+            &url,
+            nodes_in_subnet,
+        )
+    };
+
+    let tx_outcalls = vec![
+        get_account.to_owned(),
+        submit_tx,
+        get_tx(forward.to_owned()),
+    ];
+    let verify_outcalls = rpc_list
+        .iter()
+        .map(|url| get_tx(Some(url.to_owned())))
+        .chain(rpc_list.iter().map(|url| get_ledger(Some(url.to_owned()))))
+        .collect();
+    let update_token_outcalls = vec![estimate_atos_request(
+        "get_fa_metadata",
+        AtosRequest::GetAccountResource {
+            address: address.to_owned(),
+            resource_type: FA_METADATA_TYPE.to_string(),
+        },
+        &forward,
+        nodes_in_subnet,
+    )];
+    let refresh_account_outcalls = vec![
+        get_account,
+        estimate_atos_request(
+            "get_account_balance",
+            AtosRequest::GetAccountBalance {
+                address,
+                asset_type: "0x1::aptos_coin::AptosCoin".to_string(),
+            },
+            &forward,
+            nodes_in_subnet,
+        ),
+    ];
+
+    let sign_cycles = sign_cycles(&key_type);
+    [
+        ("MintToken", tx_outcalls.to_owned(), sign_cycles),
+        ("CreateToken", tx_outcalls.to_owned(), sign_cycles),
+        ("UpdateMeta", tx_outcalls.to_owned(), sign_cycles),
+        ("AddMinter", tx_outcalls, sign_cycles),
+        ("GenerateTicket", verify_outcalls, 0),
+        ("UpdateToken", update_token_outcalls, 0),
+        ("RefreshRouteAccount", refresh_account_outcalls, 0),
+    ]
+    .into_iter()
+    .map(
+        |(request_type, outcalls, sign_cycles)| RequestCostEstimate {
+            request_type: request_type.to_string(),
+            total: outcalls.iter().map(|o| o.cycles).sum::<u128>() + sign_cycles,
+            outcalls,
+            sign_cycles,
+        },
+    )
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arg(body_len: usize, max_response_bytes: u64) -> CanisterHttpRequestArgument {
+        http_request_arg(
+            RestReq {
+                method: HttpMethod::POST,
+                headers: vec![],
+                url: "https://fullnode.mainnet.aptoslabs.com/v1/transactions".to_string(),
+                body: Some(vec![0; body_len]),
+            },
+            max_response_bytes,
+            None,
+            &None,
+        )
+    }
+
+    #[test]
+    fn cost_scales_with_subnet_size() {
+        let arg = arg(400, 5000);
+        let small = http_request_required_cycles(&arg, 13);
+        let large = http_request_required_cycles(&arg, 34);
+        assert!(small < large);
+        // the per node part grows with the square of the subnet size
+        assert!(large > small * 34 / 13);
+    }
+
+    #[test]
+    fn cost_counts_the_encoded_request() {
+        let small = arg(0, 5000);
+        let large = arg(1000, 5000);
+        assert!(request_bytes(&large) >= request_bytes(&small) + 1000);
+        assert_eq!(
+            http_request_required_cycles(&large, 13) - http_request_required_cycles(&small, 13),
+            HTTP_OUTCALL_REQUEST_COST_PER_BYTE
+                * (request_bytes(&large) - request_bytes(&small))
+                * 13
+        );
+        // the headers are added to the response limit
+        assert_eq!(small.max_response_bytes, Some(5000 + HEADER_SIZE_LIMIT));
+    }
+}
@@ -11,6 +11,11 @@ use serde::Serialize;
 use super::constants::APTOS_API_VERSION;
 use super::constants::FORWARD_KEY;
 use super::constants::IDEMPOTENCY_KEY;
+use super::constants::{
+    ACCOUNT_RESPONSE_SIZE_ESTIMATE, FA_METADATA_SIZE_ESTIMATE, LEDGER_INFO_RESPONSE_SIZE_ESTIMATE,
+    SUBMIT_TX_RESPONSE_SIZE_ESTIMATE, TRANSACTION_RESPONSE_SIZE_ESTIMATE,
+    VIEW_RESPONSE_SIZE_ESTIMATE,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum AtosRequest {
@@ -21,6 +26,10 @@ pub enum AtosRequest {
         address: String,
         asset_type: String,
     },
+    GetAccountResource {
+        address: String,
+        resource_type: String,
+    },
     GetFaObj {
         view_func: String,
         token_id: String,
@@ -32,6 +41,9 @@ pub enum AtosRequest {
         txn_hash: String,
         url: Option<String>,
     },
+    GetLedgerInfo {
+        url: Option<String>,
+    },
 }
 
 impl fmt::Display for AtosRequest {
@@ -42,17 +54,38 @@ impl fmt::Display for AtosRequest {
                 address,
                 asset_type,
             } => format!("/accounts/{}/balance/{}", address, asset_type),
+            AtosRequest::GetAccountResource {
+                address,
+                resource_type,
+            } => format!("/accounts/{}/resource/{}", address, resource_type),
             AtosRequest::SubmitTransaction { .. } => format!("/transactions"),
             AtosRequest::GetTransactionByHash { txn_hash, .. } => {
                 format!("/transactions/by_hash/{}", txn_hash)
             }
             AtosRequest::GetFaObj { .. } => format!("/view"),
+            AtosRequest::GetLedgerInfo { .. } => format!("/"),
         };
 
         write!(f, "{method}")
     }
 }
 
+impl AtosRequest {
+    /// the response size limit of the outcall, the headers are added by `RestClient::call`
+    pub fn max_response_bytes(&self) -> u64 {
+        match self {
+            AtosRequest::GetAccount { .. } | AtosRequest::GetAccountBalance { .. } => {
+                ACCOUNT_RESPONSE_SIZE_ESTIMATE
+            }
+            AtosRequest::GetAccountResource { .. } => FA_METADATA_SIZE_ESTIMATE,
+            AtosRequest::GetFaObj { .. } => VIEW_RESPONSE_SIZE_ESTIMATE,
+            AtosRequest::SubmitTransaction { .. } => SUBMIT_TX_RESPONSE_SIZE_ESTIMATE,
+            AtosRequest::GetTransactionByHash { .. } => TRANSACTION_RESPONSE_SIZE_ESTIMATE,
+            AtosRequest::GetLedgerInfo { .. } => LEDGER_INFO_RESPONSE_SIZE_ESTIMATE,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct RestReq {
     pub method: HttpMethod,
@@ -102,6 +135,27 @@ pub fn build_rest_req(req: AtosRequest) -> RestReq {
                 body: None,
             }
         }
+        AtosRequest::GetAccountResource {
+            address,
+            resource_type,
+        } => {
+            let headers = vec![HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            }];
+            RestReq {
+                method: HttpMethod::GET,
+                headers,
+                url: format!(
+                    "{}/{}/accounts/{}/resource/{}",
+                    provider.url(),
+                    APTOS_API_VERSION,
+                    address,
+                    resource_type
+                ),
+                body: None,
+            }
+        }
         AtosRequest::SubmitTransaction { txn } => {
             let headers = vec![HttpHeader {
                 name: "Content-Type".to_string(),
@@ -138,6 +192,24 @@ pub fn build_rest_req(req: AtosRequest) -> RestReq {
                 body: None,
             }
         }
+        AtosRequest::GetLedgerInfo { url } => {
+            let mut headers = vec![HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            }];
+            if let Some(url) = url {
+                headers.push(HttpHeader {
+                    name: FORWARD_KEY.to_string(),
+                    value: url,
+                });
+            }
+            RestReq {
+                method: HttpMethod::GET,
+                headers,
+                url: format!("{}/{}", provider.url(), APTOS_API_VERSION),
+                body: None,
+            }
+        }
         AtosRequest::GetFaObj {
             view_func,
             token_id,
@@ -1,15 +1,18 @@
 #![allow(unused)]
 use crate::aptos_client::constants::{
-    FORWARD_KEY, HEADER_SIZE_LIMIT, IDEMPOTENCY_KEY, TRANSACTION_RESPONSE_SIZE_ESTIMATE,
+    ACCOUNT_RESPONSE_SIZE_ESTIMATE, FA_METADATA_SIZE_ESTIMATE, FA_METADATA_TYPE, IDEMPOTENCY_KEY,
+    LEDGER_INFO_RESPONSE_SIZE_ESTIMATE, SUBMIT_TX_RESPONSE_SIZE_ESTIMATE,
+    TRANSACTION_RESPONSE_SIZE_ESTIMATE, VIEW_RESPONSE_SIZE_ESTIMATE,
 };
 use crate::aptos_client::error::AptosRouteError;
+use crate::aptos_client::pricing;
 use crate::aptos_client::request::{self, build_rest_req};
-use crate::aptos_client::utils::get_http_request_cost;
 use crate::config::{mutate_config, read_config};
 use crate::constants::{
     DEFAULT_GAS_BUDGET, MINT_WITH_TICKET_FUNC, UPDATE_DESC_FUNC, UPDATE_ICON_FUNC,
     UPDATE_NAME_FUNC, UPDATE_SYMBOL_FUNC,
 };
+use crate::cycles::{CostKind, CostScope};
 use crate::ic_log::{DEBUG, ERROR};
 
 use crate::service::forward;
@@ -45,7 +48,7 @@ use super::aptos_providers::Provider;
 use super::request::RestReq;
 use super::response::Response;
 use super::types::{parse_error, parse_state, AptosResult};
-use super::{Account, State};
+use super::{Account, FaMetadata, MoveResource, State};
 pub use aptos_api_types::PendingTransaction;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -102,6 +105,8 @@ pub struct RestClient {
     pub provider: Provider,
     pub nodes_in_subnet: Option<u32>,
     pub forward: Option<String>,
+    // the outcall cycles are attributed to it
+    pub scope: CostScope,
 }
 
 impl RestClient {
@@ -117,9 +122,15 @@ impl RestClient {
             provider,
             forward,
             nodes_in_subnet: Some(nodes_in_subnet),
+            scope: CostScope::default(),
         }
     }
 
+    pub fn with_scope(mut self, scope: CostScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
     pub fn with_nodes_in_subnet(mut self, nodes_in_subnet: u32) -> Self {
         self.nodes_in_subnet = Some(nodes_in_subnet);
         self
@@ -149,41 +160,27 @@ impl RestClient {
     ///
     pub async fn call(
         &self,
-        mut req: RestReq,
+        req: RestReq,
         max_response_bytes: u64,
         transform: Option<TransformContext>,
         forward: &Option<String>,
     ) -> AptosResult<HttpResponse> {
-        let transform = transform.unwrap_or(TransformContext::from_name(
-            "cleanup_response".to_owned(),
-            vec![],
-        ));
-
-        // add forward address
-        if let Some(forward) = forward {
-            req.headers.push(HttpHeader {
-                name: FORWARD_KEY.to_string(),
-                value: forward.to_owned(),
-            });
-        }
-
-        let request = CanisterHttpRequestArgument {
-            url: req.url.to_string(),
-            max_response_bytes: Some(max_response_bytes + HEADER_SIZE_LIMIT),
-            // max_response_bytes: None,
-            method: req.method,
-            headers: req.headers,
-            body: req.body,
-            transform: Some(transform),
-        };
-
-        let cycles = get_http_request_cost(
-            request.body.as_ref().map_or(0, |b| b.len() as u64),
-            request.max_response_bytes.unwrap_or(2 * 1024 * 1024), // default 2Mb
-        );
+        let request = pricing::http_request_arg(req, max_response_bytes, transform, forward);
+        let nodes_in_subnet = self
+            .nodes_in_subnet
+            .unwrap_or_else(|| read_config(|s| s.get().nodes_in_subnet));
+        let cycles = pricing::http_request_required_cycles(&request, nodes_in_subnet);
 
         let start = api::time();
-        match http_request(request, cycles).await {
+        let result = http_request(request, cycles).await;
+        // the unused cycles are refunded, the rest are charged even if the outcall fails
+        crate::cycles::record(
+            &self.scope,
+            CostKind::HttpOutcall,
+            cycles,
+            api::call::msg_cycles_refunded128(),
+        );
+        match result {
             Ok((response,)) => {
                 let end = api::time();
                 let elapsed = (end - start) / 1_000_000_000;
@@ -222,7 +219,9 @@ impl RestClient {
         let mut req = build_rest_req(request::AtosRequest::GetAccount { address });
         log!(DEBUG, "[rpc_client::get_account] request: {:?} ", req);
 
-        let response = self.call(req, 1000, None, &self.forward).await?;
+        let response = self
+            .call(req, ACCOUNT_RESPONSE_SIZE_ESTIMATE, None, &self.forward)
+            .await?;
         match self.json::<Account>(response) {
             Ok(response) => Ok(response.into_inner()),
             Err(e) => {
@@ -247,7 +246,9 @@ impl RestClient {
             req
         );
 
-        let response = self.call(req, 1000, None, &self.forward).await?;
+        let response = self
+            .call(req, ACCOUNT_RESPONSE_SIZE_ESTIMATE, None, &self.forward)
+            .await?;
         log!(
             DEBUG,
             "[rpc_client::get_account_balance] response: {:?} ",
@@ -273,7 +274,9 @@ impl RestClient {
         });
         log!(DEBUG, "[rpc_client::get_fa_obj] request: {:?} ", req);
 
-        let response = self.call(req, 1000, None, &self.forward).await?;
+        let response = self
+            .call(req, VIEW_RESPONSE_SIZE_ESTIMATE, None, &self.forward)
+            .await?;
         match self.json::<Vec<String>>(response) {
             Ok(response) => Ok(response.into_inner()),
             Err(e) => {
@@ -283,13 +286,39 @@ impl RestClient {
         }
     }
 
+    /// read the `0x1::fungible_asset::Metadata` resource stored at the fa obj address
+    pub async fn get_fa_metadata(&self, fa_obj: String) -> AptosResult<FaMetadata> {
+        let mut req = build_rest_req(request::AtosRequest::GetAccountResource {
+            address: fa_obj,
+            resource_type: FA_METADATA_TYPE.to_string(),
+        });
+        log!(DEBUG, "[rpc_client::get_fa_metadata] request: {:?} ", req);
+
+        let response = self
+            .call(req, FA_METADATA_SIZE_ESTIMATE, None, &self.forward)
+            .await?;
+        match self.json::<MoveResource<FaMetadata>>(response) {
+            Ok(response) => Ok(response.into_inner().data),
+            Err(e) => {
+                log!(
+                    DEBUG,
+                    "[rpc_client::get_fa_metadata] response error: {:?}",
+                    e
+                );
+                Err(e.into())
+            }
+        }
+    }
+
     pub async fn summit_tx(&self, txn: &SignedTransaction) -> AptosResult<PendingTransaction> {
         let mut req = build_rest_req(request::AtosRequest::SubmitTransaction {
             txn: txn.to_owned(),
         });
         log!(DEBUG, "[rpc_client::summit_tx] request: {:?} ", req);
 
-        let response = self.call(req, 5000, None, &self.forward).await?;
+        let response = self
+            .call(req, SUBMIT_TX_RESPONSE_SIZE_ESTIMATE, None, &self.forward)
+            .await?;
         log!(DEBUG, "[rpc_client::summit_tx] response: {:?} ", response);
         match self.json::<PendingTransaction>(response) {
             Ok(response) => Ok(response.into_inner()),
@@ -331,6 +360,29 @@ impl RestClient {
         }
     }
 
+    /// get the ledger info from the response headers of `GET /v1`,
+    /// the fast moving values are rounded down by the `cleanup_ledger_response` transform
+    pub async fn get_ledger_state(&self, url: &Option<String>) -> AptosResult<State> {
+        let mut req = build_rest_req(request::AtosRequest::GetLedgerInfo {
+            url: url.to_owned(),
+        });
+        log!(DEBUG, "[rpc_client::get_ledger_state] request: {:?} ", req);
+
+        let transform = TransformContext::from_name("cleanup_ledger_response".to_owned(), vec![]);
+        let response = self
+            .call(
+                req,
+                LEDGER_INFO_RESPONSE_SIZE_ESTIMATE,
+                Some(transform),
+                url,
+            )
+            .await?;
+        let response = self.check_response(response)?;
+        let state = parse_state(&response)?;
+        log!(DEBUG, "[rpc_client::get_ledger_state] state: {:?} ", state);
+        Ok(state)
+    }
+
     fn check_response(&self, response: HttpResponse) -> AptosResult<HttpResponse> {
         // Check if status is within 200-299.
         let status_code: u16 = response.status.to_owned().0.try_into().map_err(|_| {
@@ -8,10 +8,12 @@ use serde::{Deserialize, Serialize};
 use crate::{
     config::read_config,
     constants::{
-        BURN_TOKEN_FUNC, COLLECT_FEE_FUNC, CREATE_FUNGIBLE_ASSET, MINT_WITH_TICKET_FUNC,
-        REMOVE_TICKET_FUNC, TRANSFER_COINS, UPDATE_META_FUNC,
+        ADD_MINTER_FUNC, BURN_TOKEN_FUNC, COLLECT_FA_FEE_FUNC, COLLECT_FEE_FUNC,
+        CREATE_FUNGIBLE_ASSET, MINT_WITH_TICKET_FUNC, REMOVE_TICKET_FUNC, TRANSFER_COINS,
+        UPDATE_META_FUNC,
     },
     ic_log::DEBUG,
+    multisig,
     state::read_state,
 };
 use anyhow::Result;
@@ -214,24 +216,36 @@ pub async fn get_signed_tx(
         "[tx_builder::get_signed_tx] contact_func: {:?} ",
         contact_func
     );
+    let payload = TransactionPayload::EntryFunction(EntryFunction::new(
+        ModuleId::new(contact_func.package, contact_func.module),
+        contact_func.func,
+        contact_func.type_args,
+        contact_func.args,
+    ));
+    get_signed_payload_tx(from_account, payload, options).await
+}
 
+/// sign the payload with the latest sequence number of the account
+pub async fn get_signed_payload_tx(
+    from_account: &mut LocalAccount,
+    payload: TransactionPayload,
+    options: Option<TxOptions>,
+) -> Result<SignedTransaction> {
     let options = options.unwrap_or_default();
     // get current timestamp and conver to second
     let now_s = api::time() / 1_000_000_000;
     let seq_num = from_account.update_seq_from_chain().await?;
     log!(
         DEBUG,
-        "[tx_builder::get_signed_tx] latest seq number: {} ",
+        "[tx_builder::get_signed_payload_tx] latest seq number: {} ",
         seq_num
     );
+    // the multisig tx stays valid while the offline shares are collected
+    let key_type = read_config(|c| c.get().key_type.to_owned());
+    let timeout_secs = multisig::tx_timeout_secs(&key_type, options.timeout_secs);
     let transaction_builder = TransactionBuilder::new(
-        TransactionPayload::EntryFunction(EntryFunction::new(
-            ModuleId::new(contact_func.package, contact_func.module),
-            contact_func.func,
-            contact_func.type_args,
-            contact_func.args,
-        )),
-        now_s + options.timeout_secs,
+        payload,
+        now_s + timeout_secs,
         ChainId::new(options.chain_id),
     )
     .sender(from_account.address())
@@ -240,7 +254,7 @@ pub async fn get_signed_tx(
     .gas_unit_price(options.gas_unit_price);
     let signed_txn = from_account
         .sign_with_transaction_builder(transaction_builder)
-        .await;
+        .await?;
     Ok(signed_txn)
 }
 
@@ -348,6 +362,20 @@ pub async fn get_contract_func(req: &ReqType) -> Result<ContractFunc> {
                 args,
             })
         }
+        ReqType::CollectFAFee(req) => {
+            let module_id = Identifier::new(port_info.module)?;
+            let func_id = Identifier::new(COLLECT_FA_FEE_FUNC)?;
+            let type_args = vec![];
+            let fa_obj = AccountAddress::from_str(&req.fa_obj)?;
+            let args = vec![bcs::to_bytes(&fa_obj)?, bcs::to_bytes(&req.fee_amount)?];
+            Ok(ContractFunc {
+                package: port_address,
+                module: module_id,
+                func: func_id,
+                type_args,
+                args,
+            })
+        }
         ReqType::RemoveTicket(ticket_id) => {
             let module_id = Identifier::new(port_info.module)?;
             let func_id = Identifier::new(REMOVE_TICKET_FUNC)?;
@@ -361,6 +389,20 @@ pub async fn get_contract_func(req: &ReqType) -> Result<ContractFunc> {
                 args,
             })
         }
+        ReqType::AddMinter(minter) => {
+            let module_id = Identifier::new(port_info.module)?;
+            let func_id = Identifier::new(ADD_MINTER_FUNC)?;
+            let type_args = vec![];
+            let minter = AccountAddress::from_str(minter)?;
+            let args = vec![bcs::to_bytes(&minter)?];
+            Ok(ContractFunc {
+                package: port_address,
+                module: module_id,
+                func: func_id,
+                type_args,
+                args,
+            })
+        }
         ReqType::TransferApt(req) => {
             let package = AccountAddress::ONE;
             let module = Identifier::new("aptos_account")?;
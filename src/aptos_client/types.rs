@@ -1,9 +1,10 @@
 #![allow(unused)]
-use crate::config::{mutate_config, read_config, NATIVE_KEY_TYPE};
+use crate::config::{mutate_config, read_config};
 use crate::constants::{
     DEFAULT_GAS_BUDGET, MINT_WITH_TICKET_FUNC, UPDATE_DESC_FUNC, UPDATE_ICON_FUNC,
     UPDATE_NAME_FUNC, UPDATE_SYMBOL_FUNC,
 };
+use crate::cycles::CostScope;
 use crate::handler::handle_tx::{build_and_send_tx, update_tx_status};
 use crate::ic_log::{DEBUG, ERROR};
 
@@ -25,8 +26,13 @@ use ic_cdk::api::management_canister::http_request::{
 };
 
 use aptos_crypto::ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature};
+use aptos_crypto::multi_ed25519::MultiEd25519PublicKey;
+use aptos_crypto::secp256k1_ecdsa;
 
-use aptos_types::transaction::{authenticator::AuthenticationKey, SignatureCheckedTransaction};
+use aptos_types::transaction::authenticator::{
+    AccountAuthenticator, AnyPublicKey, AnySignature, AuthenticationKey, SingleKeyAuthenticator,
+};
+use aptos_types::transaction::SignatureCheckedTransaction;
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::Storable;
 use move_core_types::account_address::AccountAddress;
@@ -41,11 +47,14 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use crate::ck_eddsa::{self, KeyType};
+use crate::multisig;
 use anyhow::{Context, Result};
 pub use aptos_api_types::deserialize_from_string;
 use aptos_api_types::{Address, U64};
 
-use super::constants::DEVNET_CHAIN_ID;
+use super::constants::{
+    DEVNET_CHAIN_ID, LEDGER_TIMESTAMP_GRANULARITY_USECS, LEDGER_VERSION_GRANULARITY,
+};
 use super::error::AptosRouteError;
 use super::rest_client::RestClient;
 use super::tx_builder::TransactionBuilder;
@@ -58,10 +67,19 @@ pub type AptosResult<T> = Result<T, AptosRouteError>;
 pub enum LocalAccountAuthenticator {
     NativeKey(AccountKey),
     ChainKey(AccountKey),
+    // the secp256k1 key from threshold ecdsa, signs with the `SingleKey` authenticator
+    ChainKeySecp256k1(AccountKey),
+    // the signature shares of the signers are combined by `multisig::sign_transaction`
+    MultiEd25519(AccountKey),
 }
 
 impl LocalAccountAuthenticator {
-    pub async fn sign_transaction(&self, txn: RawTransaction) -> SignedTransaction {
+    pub async fn sign_transaction(
+        &self,
+        txn: RawTransaction,
+        req_id: Option<&String>,
+        scope: &CostScope,
+    ) -> AptosResult<SignedTransaction> {
         match self {
             LocalAccountAuthenticator::NativeKey(account_key) => {
                 let msg = signing_message(&txn).unwrap();
@@ -70,19 +88,19 @@ impl LocalAccountAuthenticator {
                     "[LocalAccountAuthenticator::NativeKey::sign_transaction] signing_message: {:?} ",
                     msg
                 );
-                let seed = read_state(|s| {
-                    s.seeds
-                        .get(&NATIVE_KEY_TYPE.to_string())
-                        .unwrap_or_else(|| {
-                            panic!("No key with name {:?}", &NATIVE_KEY_TYPE.to_string())
-                        })
-                });
-
-                let sig_bytes = ck_eddsa::sign(msg.to_vec(), KeyType::Native(seed.to_vec()))
-                    .await
-                    .unwrap();
-                let signature = Ed25519Signature::try_from(sig_bytes.as_slice()).unwrap();
-                SignedTransaction::new(txn, account_key.public_key.clone(), signature)
+                // the native key may be disabled or not created yet
+                let key_type = ck_eddsa::native_key()
+                    .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))?;
+
+                let sig_bytes = ck_eddsa::sign_with_path(
+                    msg.to_vec(),
+                    key_type,
+                    account_key.derivation_path.to_owned(),
+                    scope,
+                )
+                .await
+                .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))?;
+                account_key.signed_transaction(txn, &sig_bytes)
             }
             LocalAccountAuthenticator::ChainKey(account_key) => {
                 //TODO：
@@ -95,11 +113,38 @@ impl LocalAccountAuthenticator {
                 );
                 // devnet chain id is 174
                 // let msg_hash = ck_eddsa::sha256(&msg);
-                let sig_bytes = ck_eddsa::sign(msg.to_vec(), KeyType::ChainKey)
+                let sig_bytes = ck_eddsa::sign_with_path(
+                    msg.to_vec(),
+                    KeyType::ChainKey,
+                    account_key.derivation_path.to_owned(),
+                    scope,
+                )
+                .await
+                .unwrap();
+                Ok(account_key.signed_transaction(txn, &sig_bytes).unwrap())
+            }
+            LocalAccountAuthenticator::ChainKeySecp256k1(account_key) => {
+                let msg = signing_message(&txn).unwrap();
+                log!(
+                    DEBUG,
+                    "[LocalAccountAuthenticator::ChainKeySecp256k1] signing_message: {:?} ",
+                    msg
+                );
+                let sig_bytes = ck_eddsa::sign_with_path(
+                    msg.to_vec(),
+                    KeyType::ChainKeySecp256k1,
+                    account_key.derivation_path.to_owned(),
+                    scope,
+                )
+                .await
+                .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))?;
+                account_key.signed_transaction(txn, &sig_bytes)
+            }
+            // waits for the offline shares if the online signers don't reach the threshold
+            LocalAccountAuthenticator::MultiEd25519(_) => {
+                multisig::sign_transaction(txn, req_id, scope)
                     .await
-                    .unwrap();
-                let signature = Ed25519Signature::try_from(sig_bytes.as_slice()).unwrap();
-                SignedTransaction::new(txn, account_key.public_key.clone(), signature)
+                    .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))
             }
         }
     }
@@ -123,32 +168,79 @@ pub struct LocalAccount {
     auth: LocalAccountAuthenticator,
     /// Latest known sequence number of the account, it can be different from validator.
     sequence_number: AtomicU64,
+    /// The scope the outcalls and the signatures of the account are paid for.
+    scope: CostScope,
+    /// The tx req signed by the account, the multisig session is kept for it.
+    req_id: Option<String>,
 }
 impl LocalAccount {
-    pub async fn local_account() -> AptosResult<LocalAccount> {
+    pub async fn local_account(scope: CostScope) -> AptosResult<LocalAccount> {
         let key_type = read_config(|c| c.get().key_type.to_owned());
         let account_key = AccountKey::account_key(key_type.to_owned()).await?;
-        let address = account_key.authentication_key().account_address();
+        let address = route_address(&account_key)?;
+        let auth_key = account_key.authentication_key();
         let auth = match key_type {
             KeyType::ChainKey => LocalAccountAuthenticator::ChainKey(account_key),
             KeyType::Native(_) => LocalAccountAuthenticator::NativeKey(account_key),
+            KeyType::ChainKeySecp256k1 => LocalAccountAuthenticator::ChainKeySecp256k1(account_key),
+            KeyType::MultiEd25519 => LocalAccountAuthenticator::MultiEd25519(account_key),
         };
 
-        let client = RestClient::client();
+        let client = RestClient::client().with_scope(scope.to_owned());
         let account = client.get_account(format!("{}", address), None).await?;
         log!(
             DEBUG,
             "[types::LocalAccount::local_account] get_account ret: {:?}",
             account
         );
+        check_auth_key(&address, &auth_key, &account.authentication_key)?;
         // let tx_seq = read_config(|s| s.get().seqs.to_owned()).tx_seq;
         let sequence_number = AtomicU64::new(account.sequence_number);
         Ok(Self {
             address,
             auth,
             sequence_number,
+            scope,
+            req_id: None,
+        })
+    }
+    /// the account of the route pool, it signs with its own derivation path
+    pub async fn pool_account(index: u32, scope: CostScope) -> AptosResult<LocalAccount> {
+        let key_type = read_config(|c| c.get().key_type.to_owned());
+        let account_key = AccountKey::pool_account_key(index)?;
+        let auth_key = account_key.authentication_key();
+        let address = auth_key.account_address();
+        let auth = match key_type {
+            KeyType::ChainKey => LocalAccountAuthenticator::ChainKey(account_key),
+            KeyType::Native(_) => LocalAccountAuthenticator::NativeKey(account_key),
+            KeyType::ChainKeySecp256k1 => LocalAccountAuthenticator::ChainKeySecp256k1(account_key),
+            KeyType::MultiEd25519 => LocalAccountAuthenticator::MultiEd25519(account_key),
+        };
+
+        let client = RestClient::client().with_scope(scope.to_owned());
+        let account = client.get_account(format!("{}", address), None).await?;
+        log!(
+            DEBUG,
+            "[types::LocalAccount::pool_account] account {} get_account ret: {:?}",
+            index,
+            account
+        );
+        check_auth_key(&address, &auth_key, &account.authentication_key)?;
+        let sequence_number = AtomicU64::new(account.sequence_number);
+        Ok(Self {
+            address,
+            auth,
+            sequence_number,
+            scope,
+            req_id: None,
         })
     }
+
+    pub fn with_req_id(mut self, req_id: &String) -> Self {
+        self.req_id = Some(req_id.to_owned());
+        self
+    }
+
     pub fn address(&self) -> AccountAddress {
         self.address
     }
@@ -170,7 +262,7 @@ impl LocalAccount {
         //         s.get().forward.to_owned(),
         //     )
         // });
-        let client = RestClient::client();
+        let client = RestClient::client().with_scope(self.scope.to_owned());
         let account = client
             .get_account(format!("{}", self.address), None)
             .await?;
@@ -183,14 +275,16 @@ impl LocalAccount {
         Ok(account.sequence_number)
     }
 
-    pub async fn sign_transaction(&self, txn: RawTransaction) -> SignedTransaction {
-        self.auth.sign_transaction(txn).await
+    pub async fn sign_transaction(&self, txn: RawTransaction) -> AptosResult<SignedTransaction> {
+        self.auth
+            .sign_transaction(txn, self.req_id.as_ref(), &self.scope)
+            .await
     }
 
     pub async fn sign_with_transaction_builder(
         &self,
         builder: TransactionBuilder,
-    ) -> SignedTransaction {
+    ) -> AptosResult<SignedTransaction> {
         // let new_seq = self.increment_sequence_number()
         let raw_txn = builder
             .sender(self.address())
@@ -199,6 +293,38 @@ impl LocalAccount {
         self.sign_transaction(raw_txn).await
     }
 }
+/// the default route account address, it differs from the one derived from the key
+/// once the authentication key is rotated
+pub fn route_address(account_key: &AccountKey) -> AptosResult<AccountAddress> {
+    match read_config(|c| c.get().route_address.to_owned()) {
+        Some(address) => AccountAddress::from_str(&address)
+            .map_err(|e| AptosRouteError::AccountKeyError(e.into())),
+        None => Ok(account_key.authentication_key().account_address()),
+    }
+}
+
+/// the txs signed by the stale key always fail, e.g. the key config changed without rotation
+fn check_auth_key(
+    address: &AccountAddress,
+    local: &AuthenticationKey,
+    onchain: &AuthenticationKey,
+) -> AptosResult<()> {
+    if local != onchain {
+        log!(
+            ERROR,
+            "[types::check_auth_key] the auth key of {} is {}, but the route key is {}",
+            address,
+            onchain,
+            local
+        );
+        return Err(AptosRouteError::AccountKeyError(anyhow::anyhow!(
+            "the route key doesn't match the auth key of {}",
+            address
+        )));
+    }
+    Ok(())
+}
+
 pub fn get_apt_primary_store_address(address: AccountAddress) -> AccountAddress {
     let mut bytes = address.to_vec();
     bytes.append(&mut AccountAddress::ONE.to_vec());
@@ -206,50 +332,156 @@ pub fn get_apt_primary_store_address(address: AccountAddress) -> AccountAddress
     AccountAddress::from_bytes(aptos_crypto::hash::HashValue::sha3_256_of(&bytes).to_vec()).unwrap()
 }
 
+/// The public key of the route account, ed25519 for the schnorr and the native keys,
+/// secp256k1 for the ecdsa key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoutePublicKey {
+    Ed25519(Ed25519PublicKey),
+    Secp256k1Ecdsa(secp256k1_ecdsa::PublicKey),
+    MultiEd25519(MultiEd25519PublicKey),
+}
+
+impl RoutePublicKey {
+    pub fn from_bytes(key_type: &KeyType, bytes: &[u8]) -> AptosResult<RoutePublicKey> {
+        match key_type {
+            KeyType::ChainKey | KeyType::Native(_) => Ed25519PublicKey::try_from(bytes)
+                .map(RoutePublicKey::Ed25519)
+                .map_err(|e| AptosRouteError::AccountKeyError(e.into())),
+            KeyType::ChainKeySecp256k1 => secp256k1_ecdsa::PublicKey::try_from(bytes)
+                .map(RoutePublicKey::Secp256k1Ecdsa)
+                .map_err(|e| AptosRouteError::AccountKeyError(e.into())),
+            KeyType::MultiEd25519 => MultiEd25519PublicKey::try_from(bytes)
+                .map(RoutePublicKey::MultiEd25519)
+                .map_err(|e| AptosRouteError::AccountKeyError(e.into())),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RoutePublicKey::Ed25519(public_key) => public_key.to_bytes().to_vec(),
+            RoutePublicKey::Secp256k1Ecdsa(public_key) => public_key.to_bytes().to_vec(),
+            RoutePublicKey::MultiEd25519(public_key) => public_key.to_bytes(),
+        }
+    }
+
+    // the secp256k1 key is only supported by the `SingleKey` scheme
+    pub fn authentication_key(&self) -> AuthenticationKey {
+        match self {
+            RoutePublicKey::Ed25519(public_key) => AuthenticationKey::ed25519(public_key),
+            RoutePublicKey::Secp256k1Ecdsa(public_key) => {
+                AuthenticationKey::any_key(AnyPublicKey::secp256k1_ecdsa(public_key.to_owned()))
+            }
+            RoutePublicKey::MultiEd25519(public_key) => {
+                AuthenticationKey::multi_ed25519(public_key)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AccountKey {
     // just for native key type
     private_key: Option<Ed25519PrivateKey>,
-    public_key: Ed25519PublicKey,
+    public_key: RoutePublicKey,
     authentication_key: AuthenticationKey,
+    derivation_path: Vec<ByteBuf>,
 }
 impl AccountKey {
     pub async fn account_key(key_type: KeyType) -> AptosResult<AccountKey> {
+        let chain_id = read_config(|c| c.get().chain_id.to_owned());
+        let derivation_path = ck_eddsa::derivation_path(&chain_id, None);
         match key_type {
-            KeyType::ChainKey => {
-                let public_key_bytes = ck_eddsa::public_key_ed25519(key_type).await.unwrap();
-                let public_key = Ed25519PublicKey::try_from(public_key_bytes.as_slice())
-                    .map_err(|e| AptosRouteError::AccountKeyError(e.into()))?;
-                let authentication_key = AuthenticationKey::ed25519(&public_key);
+            KeyType::ChainKey | KeyType::ChainKeySecp256k1 | KeyType::MultiEd25519 => {
+                let public_key_bytes = ck_eddsa::route_public_key(key_type.to_owned())
+                    .await
+                    .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))?;
+                let public_key = RoutePublicKey::from_bytes(&key_type, &public_key_bytes)?;
+                let authentication_key = public_key.authentication_key();
 
                 Ok(Self {
                     private_key: None,
                     public_key,
                     authentication_key,
+                    derivation_path,
                 })
             }
             // just for test
             KeyType::Native(seed) => {
+                ck_eddsa::check_native_key()
+                    .map_err(|e| AptosRouteError::AccountKeyError(anyhow::anyhow!(e)))?;
                 let seed_32_bytes =
                     <[u8; 32]>::try_from(&seed[0..32]).expect("seed should be >= 32 bytes");
                 let private_key = Ed25519PrivateKey::try_from(&seed_32_bytes[..])
                     .map_err(|e| AptosRouteError::AccountKeyError(e.into()))?;
-                let public_key = Ed25519PublicKey::from(&private_key);
-                let authentication_key = AuthenticationKey::ed25519(&public_key);
+                let public_key = RoutePublicKey::Ed25519(Ed25519PublicKey::from(&private_key));
+                let authentication_key = public_key.authentication_key();
 
                 Ok(Self {
                     private_key: Some(private_key),
                     public_key,
                     authentication_key,
+                    derivation_path,
                 })
             }
         }
     }
 
-    pub fn public_key(&self) -> &Ed25519PublicKey {
+    /// the key of the route pool account, the public key is derived when the account is added
+    pub fn pool_account_key(index: u32) -> AptosResult<AccountKey> {
+        let route_account = read_state(|s| s.route_accounts.get(&index)).ok_or(
+            AptosRouteError::AccountKeyError(anyhow::anyhow!(
+                "not found the route account: {}",
+                index
+            )),
+        )?;
+        let (key_type, chain_id) =
+            read_config(|c| (c.get().key_type.to_owned(), c.get().chain_id.to_owned()));
+        let public_key = RoutePublicKey::from_bytes(&key_type, &route_account.public_key)?;
+        let authentication_key = public_key.authentication_key();
+        Ok(Self {
+            private_key: None,
+            public_key,
+            authentication_key,
+            derivation_path: ck_eddsa::derivation_path(&chain_id, Some(index)),
+        })
+    }
+
+    pub fn public_key(&self) -> &RoutePublicKey {
         &self.public_key
     }
 
+    /// the signed tx with the authenticator of the key scheme, the secp256k1 signature
+    /// goes with the `SingleKey` authenticator
+    pub fn signed_transaction(
+        &self,
+        txn: RawTransaction,
+        signature: &[u8],
+    ) -> AptosResult<SignedTransaction> {
+        match &self.public_key {
+            RoutePublicKey::Ed25519(public_key) => {
+                let signature = Ed25519Signature::try_from(signature)
+                    .map_err(|e| AptosRouteError::AccountKeyError(e.into()))?;
+                Ok(SignedTransaction::new(
+                    txn,
+                    public_key.to_owned(),
+                    signature,
+                ))
+            }
+            RoutePublicKey::Secp256k1Ecdsa(public_key) => {
+                let signature = secp256k1_ecdsa::Signature::try_from(signature)
+                    .map_err(|e| AptosRouteError::AccountKeyError(e.into()))?;
+                let authenticator = AccountAuthenticator::single_key(SingleKeyAuthenticator::new(
+                    AnyPublicKey::secp256k1_ecdsa(public_key.to_owned()),
+                    AnySignature::secp256k1_ecdsa(signature),
+                ));
+                Ok(SignedTransaction::new_single_sender(txn, authenticator))
+            }
+            RoutePublicKey::MultiEd25519(_) => Err(AptosRouteError::AccountKeyError(
+                anyhow::anyhow!("the multisig signature is combined from the signer shares"),
+            )),
+        }
+    }
+
     pub fn authentication_key(&self) -> AuthenticationKey {
         self.authentication_key
     }
@@ -294,6 +526,24 @@ pub struct Account {
     pub sequence_number: u64,
 }
 
+/// the resource returned by `GET /accounts/{address}/resource/{resource_type}`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MoveResource<T> {
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub data: T,
+}
+
+/// the `0x1::fungible_asset::Metadata` resource of the fa obj
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, CandidType)]
+pub struct FaMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub icon_uri: String,
+    pub project_uri: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct EventHandle {
     counter: U64,
@@ -341,10 +591,28 @@ impl State {
         // for header in headers {
         //     header_map.insert(header.name.as_str(), header.value.as_str());
         // }
+        // the header names from the http outcall may be lowercase
         let header_map: HashMap<_, _> = headers
             .iter()
-            .map(|h| (h.name.as_str(), h.value.as_str()))
+            .map(|h| (h.name.to_lowercase(), h.value.as_str()))
             .collect();
+        let header_map: HashMap<_, _> = [
+            X_APTOS_CHAIN_ID,
+            X_APTOS_LEDGER_VERSION,
+            X_APTOS_LEDGER_TIMESTAMP,
+            X_APTOS_EPOCH,
+            X_APTOS_LEDGER_OLDEST_VERSION,
+            X_APTOS_BLOCK_HEIGHT,
+            X_APTOS_OLDEST_BLOCK_HEIGHT,
+            X_APTOS_CURSOR,
+        ]
+        .into_iter()
+        .filter_map(|name| {
+            header_map
+                .get(&name.to_lowercase())
+                .map(|value| (name, *value))
+        })
+        .collect();
 
         let maybe_chain_id = header_map
             .get(X_APTOS_CHAIN_ID)
@@ -413,6 +681,46 @@ impl State {
 
         Ok(state)
     }
+
+    /// Rounds down the fast moving ledger values, so that the replicas can reach
+    /// consensus on the ledger info returned by the http outcall.
+    pub fn rounded(&self) -> Self {
+        let round = |value: u64, granularity: u64| value - value % granularity;
+        Self {
+            chain_id: self.chain_id,
+            epoch: self.epoch,
+            version: round(self.version, LEDGER_VERSION_GRANULARITY),
+            timestamp_usecs: round(self.timestamp_usecs, LEDGER_TIMESTAMP_GRANULARITY_USECS),
+            oldest_ledger_version: round(self.oldest_ledger_version, LEDGER_VERSION_GRANULARITY),
+            oldest_block_height: round(self.oldest_block_height, LEDGER_VERSION_GRANULARITY),
+            block_height: round(self.block_height, LEDGER_VERSION_GRANULARITY),
+            cursor: None,
+        }
+    }
+
+    pub fn to_headers(&self) -> Vec<HttpHeader> {
+        [
+            (X_APTOS_CHAIN_ID, self.chain_id.to_string()),
+            (X_APTOS_EPOCH, self.epoch.to_string()),
+            (X_APTOS_LEDGER_VERSION, self.version.to_string()),
+            (X_APTOS_LEDGER_TIMESTAMP, self.timestamp_usecs.to_string()),
+            (
+                X_APTOS_LEDGER_OLDEST_VERSION,
+                self.oldest_ledger_version.to_string(),
+            ),
+            (X_APTOS_BLOCK_HEIGHT, self.block_height.to_string()),
+            (
+                X_APTOS_OLDEST_BLOCK_HEIGHT,
+                self.oldest_block_height.to_string(),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| HttpHeader {
+            name: name.to_string(),
+            value,
+        })
+        .collect()
+    }
 }
 
 pub fn parse_state(response: &HttpResponse) -> AptosResult<State> {
@@ -484,6 +792,7 @@ pub struct MintTokenReq {
     pub token_id: String,
     pub fa_obj: String,
     pub recipient: String,
+    // the fa amount is u64 in move, it's widened to u128 for the decimals scaling only
     pub mint_acmount: u64,
 }
 
@@ -500,6 +809,12 @@ pub struct TransferReq {
     pub amount: u64,
 }
 
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollectFAFeeReq {
+    pub fa_obj: String,
+    pub fee_amount: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, CandidType, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ReqType {
     CreateToken(CreateTokenReq),
@@ -509,6 +824,19 @@ pub enum ReqType {
     CollectFee(u64),
     RemoveTicket(String),
     TransferApt(TransferReq),
+    CollectFAFee(CollectFAFeeReq),
+    // authorize the route pool account to mint on the port
+    AddMinter(String),
+}
+
+impl ReqType {
+    /// the ticket the req is created for
+    pub fn ticket_id(&self) -> Option<String> {
+        match self {
+            ReqType::MintToken(mint_token_req) => Some(mint_token_req.ticket_id.to_owned()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, CandidType, PartialEq, Eq)]
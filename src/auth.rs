@@ -5,10 +5,24 @@ use serde::{Deserialize, Serialize};
 
 use crate::config::{mutate_config, read_config};
 
-#[derive(CandidType, Copy, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[derive(
+    CandidType, Copy, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum Permission {
     Query,
     Update,
+    // approve or reject the mints above the approval threshold, the admin and the
+    // controllers don't have it unless it's granted to them
+    Approve,
+}
+
+pub fn has_perm(caller: &str, perm: Permission) -> bool {
+    read_config(|s| {
+        s.get()
+            .caller_perms
+            .get(caller)
+            .is_some_and(|perms| perms.contains(&perm))
+    })
 }
 
 pub fn is_admin() -> Result<(), String> {
@@ -23,6 +37,18 @@ pub fn is_admin() -> Result<(), String> {
     })
 }
 
+pub fn is_hub() -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    read_config(|s| {
+        if s.get().hub_principal == caller {
+            Ok(())
+        } else {
+            ic_cdk::eprintln!("{:?} Not Hub!", caller.to_string());
+            Err("Not Hub!".into())
+        }
+    })
+}
+
 pub fn auth_update() -> Result<(), String> {
     let caller = ic_cdk::api::caller();
     ic_cdk::println!("auth update for caller: {:?}", caller.to_string());
@@ -33,7 +59,7 @@ pub fn auth_update() -> Result<(), String> {
                 .get()
                 .caller_perms
                 .get(&caller.to_string())
-                .is_some_and(|perm| *perm == Permission::Update)
+                .is_some_and(|perms| perms.contains(&Permission::Update))
         {
             ic_cdk::eprintln!("{:?} Unauthorized!", caller.to_string());
             Err("Unauthorized!".into())
@@ -59,10 +85,35 @@ pub fn auth_query() -> Result<(), String> {
     })
 }
 
+// only the principals granted `Approve` explicitly, so that the admin identity and a
+// controller identity of the same operator can't make two approvals
+pub fn auth_approve() -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if has_perm(&caller.to_string(), Permission::Approve) {
+        Ok(())
+    } else {
+        ic_cdk::eprintln!("{:?} Not Approver!", caller.to_string());
+        Err("Not Approver!".into())
+    }
+}
+
 pub fn set_perms(caller: String, perm: Permission) {
     mutate_config(|s| {
         let mut config = s.get().to_owned();
-        config.caller_perms.insert(caller.to_string(), perm);
+        config.caller_perms.entry(caller).or_default().insert(perm);
+        s.set(config);
+    })
+}
+
+pub fn remove_perms(caller: String, perm: Permission) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        if let Some(perms) = config.caller_perms.get_mut(&caller) {
+            perms.remove(&perm);
+            if perms.is_empty() {
+                config.caller_perms.remove(&caller);
+            }
+        }
         s.set(config);
     })
 }
@@ -0,0 +1,130 @@
+use crate::aptos_client::constants::ECDSA_SIGN_COST;
+use crate::cycles::{self, CostKind, CostScope};
+
+use aptos_crypto::hash::HashValue;
+use candid::Principal;
+use ic_management_canister_types::{
+    EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgs, EcdsaPublicKeyResult, SignWithEcdsaArgs,
+    SignWithEcdsaResult,
+};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use serde_bytes::ByteBuf;
+
+/// Fetches the secp256k1 public key from the ecdsa canister, in the uncompressed
+/// sec1 form used by the aptos `Secp256k1Ecdsa` public key
+pub async fn pub_key_secp256k1(
+    key_name: String,
+    derivation_path: Vec<ByteBuf>,
+) -> Result<Vec<u8>, String> {
+    let res: Result<(EcdsaPublicKeyResult,), _> = ic_cdk::call(
+        Principal::management_canister(),
+        "ecdsa_public_key",
+        (EcdsaPublicKeyArgs {
+            canister_id: None,
+            derivation_path: derivation_path
+                .iter()
+                .map(|p| p.clone().into_vec())
+                .collect(),
+            key_id: EcdsaKeyId {
+                curve: EcdsaCurve::Secp256k1,
+                name: key_name,
+            },
+        },),
+    )
+    .await;
+    let (res,) = res.map_err(|(code, msg)| {
+        format!("failed to get the ecdsa public key: {:?}, {}", code, msg)
+    })?;
+    uncompressed_public_key(&res.public_key)
+}
+
+/// Signs a message with a secp256k1 key, aptos verifies the sha3-256 digest of the
+/// message and only accepts the low-s signature
+pub async fn sign_with_ecdsa(
+    key_name: String,
+    derivation_path: Vec<ByteBuf>,
+    message: Vec<u8>,
+    scope: &CostScope,
+) -> Result<Vec<u8>, String> {
+    let res: Result<(SignWithEcdsaResult,), _> = ic_cdk::api::call::call_with_payment(
+        Principal::management_canister(),
+        "sign_with_ecdsa",
+        (SignWithEcdsaArgs {
+            message_hash: HashValue::sha3_256_of(&message).to_vec(),
+            derivation_path: derivation_path
+                .iter()
+                .map(|p| p.clone().into_vec())
+                .collect(),
+            key_id: EcdsaKeyId {
+                curve: EcdsaCurve::Secp256k1,
+                name: key_name,
+            },
+        },),
+        ECDSA_SIGN_COST as u64,
+    )
+    .await;
+    cycles::record(
+        scope,
+        CostKind::SignWithEcdsa,
+        ECDSA_SIGN_COST,
+        ic_cdk::api::call::msg_cycles_refunded128(),
+    );
+    let (res,) =
+        res.map_err(|(code, msg)| format!("failed to sign with ecdsa: {:?}, {}", code, msg))?;
+    normalize_signature(&res.signature)
+}
+
+pub fn uncompressed_public_key(sec1: &[u8]) -> Result<Vec<u8>, String> {
+    let public_key = k256::PublicKey::from_sec1_bytes(sec1)
+        .map_err(|e| format!("invalid secp256k1 public key: {}", e))?;
+    Ok(public_key.to_encoded_point(false).as_bytes().to_vec())
+}
+
+pub fn normalize_signature(signature: &[u8]) -> Result<Vec<u8>, String> {
+    let signature = k256::ecdsa::Signature::from_slice(signature)
+        .map_err(|e| format!("invalid secp256k1 signature: {}", e))?;
+    Ok(signature
+        .normalize_s()
+        .unwrap_or(signature)
+        .to_bytes()
+        .to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::{Signature, SigningKey};
+
+    #[test]
+    fn public_key_is_uncompressed() {
+        let signing_key = SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let compressed = signing_key.verifying_key().to_encoded_point(true);
+        let public_key = uncompressed_public_key(compressed.as_bytes()).unwrap();
+        assert_eq!(public_key.len(), 65);
+        assert_eq!(public_key[0], 0x04);
+        assert_eq!(
+            public_key,
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+        );
+    }
+
+    #[test]
+    fn signature_is_low_s() {
+        let signing_key = SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let digest = HashValue::sha3_256_of(b"aptos route").to_vec();
+        let signature: Signature = signing_key.sign_prehash(&digest).unwrap();
+        // the high-s form of the same signature
+        let (r, s) = signature.split_scalars();
+        let high_s = Signature::from_scalars(r, -*s).unwrap();
+        assert!(high_s.normalize_s().is_some());
+
+        let normalized = normalize_signature(&high_s.to_bytes()).unwrap();
+        let normalized = Signature::from_slice(&normalized).unwrap();
+        assert!(normalized.normalize_s().is_none());
+        assert_eq!(normalized, signature.normalize_s().unwrap_or(signature));
+    }
+}
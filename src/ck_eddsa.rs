@@ -1,6 +1,11 @@
-use crate::aptos_client::constants::EDDSA_SIGN_COST;
-use crate::config::read_config;
-use crate::ic_log::DEBUG;
+use crate::aptos_client::aptos_providers::Provider;
+use crate::aptos_client::constants::{EDDSA_SIGN_COST, MAINNET_CHAIN_ID};
+use crate::aptos_client::RoutePublicKey;
+use crate::ck_ecdsa;
+use crate::config::{read_config, NATIVE_KEY_TYPE};
+use crate::cycles::{self, CostKind, CostScope};
+use crate::ic_log::{DEBUG, INFO, WARNING};
+use crate::multisig;
 // use crate::ic_sui::ck_eddsa::KeyType;
 use crate::state::{mutate_state, read_state};
 
@@ -8,6 +13,7 @@ use aptos_crypto::ed25519::{Ed25519PrivateKey, Ed25519PublicKey};
 
 use candid::Principal;
 use candid::{CandidType, Deserialize};
+use hmac::{Hmac, Mac};
 use ic_canister_log::log;
 // use ic_crypto_ed25519::DerivationPath;
 use ic_management_canister_types::{
@@ -24,12 +30,28 @@ use std::borrow::Cow;
 use std::vec;
 
 #[derive(
-    Default, Hash, Eq, Ord, PartialEq, PartialOrd, CandidType, Deserialize, Serialize, Debug, Clone,
+    Default, Hash, Eq, Ord, PartialEq, PartialOrd, CandidType, Deserialize, Serialize, Clone,
 )]
 pub enum KeyType {
     #[default]
     ChainKey,
     Native(Vec<u8>),
+    // the secp256k1 key from threshold ecdsa
+    ChainKeySecp256k1,
+    // the k-of-n account of the signers in the multisig config
+    MultiEd25519,
+}
+
+// the native seed must not show up in the logs
+impl std::fmt::Debug for KeyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyType::ChainKey => write!(f, "ChainKey"),
+            KeyType::Native(seed) => write!(f, "Native({})", &hash_with_sha256(seed)[..8]),
+            KeyType::ChainKeySecp256k1 => write!(f, "ChainKeySecp256k1"),
+            KeyType::MultiEd25519 => write!(f, "MultiEd25519"),
+        }
+    }
 }
 
 impl Storable for KeyType {
@@ -45,50 +67,319 @@ impl Storable for KeyType {
     const BOUND: Bound = Bound::Unbounded;
 }
 
-// cache the sui route address to save the cycles
-pub async fn public_key_ed25519(key_type: KeyType) -> Result<Vec<u8>, String> {
-    let address = read_state(|s| s.route_addresses.get(&key_type));
-    log!(
-        DEBUG,
-        "[rpc_client::public_key_ed25519] key type: {:?} and value from state: {:?} ",
-        key_type,
+/// The cache key of the route public key, the key is derived again once the key type,
+/// the key name or the derivation path (e.g. the chain id) changes
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RouteKeyId {
+    // `ChainKey` or `Native:<seed fingerprint>`, the native seed is never exposed
+    pub key_type: String,
+    pub key_name: String,
+    pub derivation_path: Vec<Vec<u8>>,
+}
+
+impl Storable for RouteKeyId {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize RouteKeyId");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize RouteKeyId")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl RouteKeyId {
+    pub fn new(key_type: &KeyType, key_name: &str, derivation_path: &[ByteBuf]) -> Self {
+        let key_type = match key_type {
+            KeyType::ChainKey => "ChainKey".to_string(),
+            KeyType::Native(seed) => format!("Native:{}", &hash_with_sha256(seed)[..8]),
+            KeyType::ChainKeySecp256k1 => "ChainKeySecp256k1".to_string(),
+            // the multisig key changes with its signers
+            KeyType::MultiEd25519 => format!(
+                "MultiEd25519:{}",
+                multisig::multisig_config()
+                    .map(|config| config.fingerprint())
+                    .unwrap_or_default()
+            ),
+        };
+        Self {
+            key_type,
+            key_name: key_name.to_string(),
+            derivation_path: derivation_path.iter().map(|p| p.to_vec()).collect(),
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RouteKeyRecord {
+    pub public_key: Vec<u8>,
+    // the address derived from the public key
+    pub address: String,
+    pub derived_at: u64,
+}
+
+impl Storable for RouteKeyRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize RouteKeyRecord");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize RouteKeyRecord")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RouteKeyInfo {
+    pub key_id: RouteKeyId,
+    pub public_key: Vec<u8>,
+    pub derived_address: String,
+    // the rotated route account keeps its original address
+    pub account_address: String,
+    pub derived_at: u64,
+    // used by the default route account now
+    pub current: bool,
+}
+
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum KeyAction {
+    SwitchKeyType,
+    SwitchKeyName,
+    RotationStarted,
+    RotationCompleted,
+    RotationFailed,
+}
+
+/// The audit record of the route key change
+#[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct KeyAuditRecord {
+    pub caller: String,
+    pub action: KeyAction,
+    pub from: RouteKeyId,
+    pub to: RouteKeyId,
+    // the route account address after the change
+    pub address: String,
+    pub at: u64,
+}
+
+impl Storable for KeyAuditRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize KeyAuditRecord");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize KeyAuditRecord")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+pub fn record_key_audit(action: KeyAction, from: RouteKeyId, to: RouteKeyId, address: String) {
+    let record = KeyAuditRecord {
+        caller: ic_cdk::caller().to_string(),
+        action,
+        from,
+        to,
         address,
+        at: crate::clock::now(),
+    };
+    log!(
+        INFO,
+        "[ck_eddsa::record_key_audit] route key changed: {:?}",
+        record
     );
+    mutate_state(|s| {
+        let seq = s.key_audits.last_key_value().map_or(0, |(seq, _)| seq + 1);
+        s.key_audits.insert(seq, record);
+    });
+}
+
+/// the native key signs with the seed kept in the canister state, it is only allowed
+/// in the builds with the `native-key` feature and never on the mainnet
+pub fn check_native_key() -> Result<(), String> {
+    if !cfg!(feature = "native-key") {
+        return Err("the native key is not enabled in this build".to_string());
+    }
+    let (chain_id, provider) =
+        read_config(|s| (s.get().tx_opt.chain_id, s.get().rpc_provider.to_owned()));
+    if chain_id == MAINNET_CHAIN_ID || provider == Provider::Mainnet {
+        log!(
+            WARNING,
+            "[ck_eddsa::check_native_key] refused the native key on the mainnet"
+        );
+        return Err("the native key is not allowed on the mainnet".to_string());
+    }
+    Ok(())
+}
+
+/// the native key with the seed from the state, checked by `check_native_key`
+pub fn native_key() -> Result<KeyType, String> {
+    check_native_key()?;
+    read_state(|s| s.seeds.get(&NATIVE_KEY_TYPE.to_string()))
+        .map(|seed| KeyType::Native(seed.to_vec()))
+        .ok_or(format!("No key with name {:?}", NATIVE_KEY_TYPE))
+}
+
+/// the current and historic route keys and their addresses
+pub fn route_keys() -> Vec<RouteKeyInfo> {
+    let current = current_route_key_id();
+    let route_address = read_config(|s| s.get().route_address.to_owned());
+    read_state(|s| {
+        s.route_keys
+            .iter()
+            .map(|(key_id, record)| {
+                let is_current = key_id == current;
+                let account_address = match &route_address {
+                    Some(address) if is_current => address.to_owned(),
+                    _ => record.address.to_owned(),
+                };
+                RouteKeyInfo {
+                    key_id,
+                    public_key: record.public_key,
+                    derived_address: record.address,
+                    account_address,
+                    derived_at: record.derived_at,
+                    current: is_current,
+                }
+            })
+            .collect()
+    })
+}
 
-    match address {
-        Some(address) => Ok(address),
-        // create new address
-        None => {
-            let (chain_id, schnorr_key_name) = read_config(|s| {
-                (
-                    s.get().chain_id.to_owned(),
-                    s.get().schnorr_key_name.to_owned(),
-                    // s.get().sui_route_address.get(&key_type).cloned(),
-                )
-            });
-            let derived_path = vec![ByteBuf::from(chain_id.as_bytes())];
-            let pk = pub_key_ed25519(key_type.to_owned(), schnorr_key_name, derived_path).await;
-            //save the new address
-            mutate_state(|s| {
-                s.route_addresses.insert(key_type, pk.to_owned());
-            });
-            Ok(pk)
+/// the cache key of the default route account with the current config
+pub fn current_route_key_id() -> RouteKeyId {
+    let (key_type, chain_id, schnorr_key_name) = read_config(|s| {
+        (
+            s.get().key_type.to_owned(),
+            s.get().chain_id.to_owned(),
+            s.get().schnorr_key_name.to_owned(),
+        )
+    });
+    RouteKeyId::new(
+        &key_type,
+        &schnorr_key_name,
+        &derivation_path(&chain_id, None),
+    )
+}
+
+// cache the route public key to save the cycles
+pub async fn route_public_key(key_type: KeyType) -> Result<Vec<u8>, String> {
+    let (chain_id, schnorr_key_name) = read_config(|s| {
+        (
+            s.get().chain_id.to_owned(),
+            s.get().schnorr_key_name.to_owned(),
+        )
+    });
+    cached_public_key(key_type, schnorr_key_name, derivation_path(&chain_id, None)).await
+}
+
+/// return the cached public key, or derive and check the new one
+pub async fn cached_public_key(
+    key_type: KeyType,
+    key_name: String,
+    derivation_path: Vec<ByteBuf>,
+) -> Result<Vec<u8>, String> {
+    let key_id = RouteKeyId::new(&key_type, &key_name, &derivation_path);
+    if let Some(record) = read_state(|s| s.route_keys.get(&key_id)) {
+        return Ok(record.public_key);
+    }
+
+    let pk = match key_type {
+        KeyType::ChainKeySecp256k1 => {
+            ck_ecdsa::pub_key_secp256k1(key_name, derivation_path).await?
+        }
+        KeyType::MultiEd25519 if derivation_path.len() > 1 => {
+            return Err("the route pool doesn't support the multisig key".to_string())
         }
+        KeyType::MultiEd25519 => multisig::multisig_config()?.public_key()?.to_bytes(),
+        _ => pub_key_ed25519(key_type.to_owned(), key_name, derivation_path).await?,
+    };
+    let public_key = RoutePublicKey::from_bytes(&key_type, &pk)
+        .map_err(|e| format!("invalid public key derived for {:?}: {}", key_id, e))?;
+    let address = format!("{}", public_key.authentication_key().account_address());
+    log!(
+        INFO,
+        "[ck_eddsa::cached_public_key] derived the route key {:?}, address: {}",
+        key_id,
+        address
+    );
+    mutate_state(|s| {
+        s.route_keys.insert(
+            key_id,
+            RouteKeyRecord {
+                public_key: pk.to_owned(),
+                address,
+                derived_at: crate::clock::now(),
+            },
+        )
+    });
+    Ok(pk)
+}
+
+/// the account address derived from the key, the route account moves to it once the key is switched
+pub async fn derived_address(
+    key_type: KeyType,
+    key_name: String,
+    derivation_path: Vec<ByteBuf>,
+) -> Result<String, String> {
+    let key_id = RouteKeyId::new(&key_type, &key_name, &derivation_path);
+    cached_public_key(key_type, key_name, derivation_path).await?;
+    read_state(|s| s.route_keys.get(&key_id))
+        .map(|record| record.address)
+        .ok_or(format!("not found the route key: {:?}", key_id))
+}
+
+/// the derivation path of the route account, `[chain_id]` for the default one
+/// and `[chain_id, "mint", index]` for the accounts in the route pool
+pub fn derivation_path(chain_id: &str, pool_index: Option<u32>) -> Vec<ByteBuf> {
+    let mut path = vec![ByteBuf::from(chain_id.as_bytes())];
+    if let Some(index) = pool_index {
+        path.push(ByteBuf::from(b"mint".to_vec()));
+        path.push(ByteBuf::from(index.to_be_bytes().to_vec()));
     }
+    path
 }
 
-pub async fn sign(msg: Vec<u8>, key_type: KeyType) -> Result<Vec<u8>, String> {
+/// the public key of the route pool account
+pub async fn pool_public_key(key_type: KeyType, index: u32) -> Result<Vec<u8>, String> {
     let (chain_id, schnorr_key_name) = read_config(|s| {
         (
             s.get().chain_id.to_owned(),
             s.get().schnorr_key_name.to_owned(),
         )
     });
-    let derived_path = vec![ByteBuf::from(chain_id.as_bytes())];
-    // let msg = msg.as_bytes().to_vec();
-    let signature = sign_with_eddsa(&key_type, schnorr_key_name, derived_path, msg).await;
-    // let sig = String::from_utf8_lossy(&signature).to_string();
-    Ok(signature)
+    cached_public_key(
+        key_type,
+        schnorr_key_name,
+        derivation_path(&chain_id, Some(index)),
+    )
+    .await
+}
+
+pub async fn sign(msg: Vec<u8>, key_type: KeyType, scope: &CostScope) -> Result<Vec<u8>, String> {
+    let chain_id = read_config(|s| s.get().chain_id.to_owned());
+    sign_with_path(msg, key_type, derivation_path(&chain_id, None), scope).await
+}
+
+pub async fn sign_with_path(
+    msg: Vec<u8>,
+    key_type: KeyType,
+    derivation_path: Vec<ByteBuf>,
+    scope: &CostScope,
+) -> Result<Vec<u8>, String> {
+    // the ecdsa key shares the key name with the schnorr key, e.g. `key_1`
+    let schnorr_key_name = read_config(|s| s.get().schnorr_key_name.to_owned());
+    match key_type {
+        KeyType::ChainKeySecp256k1 => {
+            ck_ecdsa::sign_with_ecdsa(schnorr_key_name, derivation_path, msg, scope).await
+        }
+        _ => sign_with_eddsa(&key_type, schnorr_key_name, derivation_path, msg, scope).await,
+    }
 }
 
 /// Fetches the ed25519 public key from the schnorr canister.
@@ -96,7 +387,7 @@ pub async fn pub_key_ed25519(
     key_type: KeyType,
     key_name: String,
     derivation_path: Vec<ByteBuf>,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, String> {
     match key_type {
         KeyType::ChainKey => {
             let res: Result<(SchnorrPublicKeyResult,), _> = ic_cdk::call(
@@ -116,15 +407,35 @@ pub async fn pub_key_ed25519(
             )
             .await;
 
-            res.unwrap().0.public_key
+            Ok(res.unwrap().0.public_key)
         }
         KeyType::Native(seed) => {
+            check_native_key()?;
             // let derivation_path = derivation_path_ed25519(&ic_cdk::api::id(), &derivation_path);
-            native_public_key_ed25519(seed)
+            Ok(native_public_key_ed25519(native_seed(
+                seed,
+                &derivation_path,
+            )))
+        }
+        KeyType::ChainKeySecp256k1 | KeyType::MultiEd25519 => {
+            Err(format!("{:?} is not an ed25519 key", key_type))
         }
     }
 }
 
+// just for testing, the pool accounts use the seed hashed with the extra path,
+// the default route account keeps the original seed
+fn native_seed(seed: Vec<u8>, derivation_path: &[ByteBuf]) -> Vec<u8> {
+    if derivation_path.len() <= 1 {
+        return seed;
+    }
+    let mut bytes = seed;
+    derivation_path[1..]
+        .iter()
+        .for_each(|p| bytes.extend_from_slice(p));
+    sha256(&bytes).to_vec()
+}
+
 // just for testing
 fn native_public_key_ed25519(seed: Vec<u8>) -> Vec<u8> {
     let seed_32_bytes = <[u8; 32]>::try_from(&seed[0..32]).expect("seed should be >= 32 bytes");
@@ -139,7 +450,8 @@ pub async fn sign_with_eddsa(
     key_name: String,
     derivation_path: Vec<ByteBuf>,
     message: Vec<u8>,
-) -> Vec<u8> {
+    scope: &CostScope,
+) -> Result<Vec<u8>, String> {
     match key_type {
         KeyType::ChainKey => {
             let res: Result<(SignWithSchnorrResult,), _> = ic_cdk::api::call::call_with_payment(
@@ -161,12 +473,25 @@ pub async fn sign_with_eddsa(
                 EDDSA_SIGN_COST as u64,
             )
             .await;
+            cycles::record(
+                scope,
+                CostKind::SignWithEddsa,
+                EDDSA_SIGN_COST,
+                ic_cdk::api::call::msg_cycles_refunded128(),
+            );
 
-            res.unwrap().0.signature
+            Ok(res.unwrap().0.signature)
         }
         KeyType::Native(seed) => {
+            check_native_key()?;
             // let derivation_path = derivation_path_ed25519(&ic_cdk::api::id(), &derivation_path);
-            sign_with_native_ed25519(seed, message)
+            Ok(sign_with_native_ed25519(
+                &native_seed(seed.to_owned(), &derivation_path),
+                message,
+            ))
+        }
+        KeyType::ChainKeySecp256k1 | KeyType::MultiEd25519 => {
+            Err(format!("{:?} is not an ed25519 key", key_type))
         }
     }
 }
@@ -194,11 +519,37 @@ pub fn hash_with_sha256(input: &Vec<u8>) -> String {
     hex::encode(value)
 }
 
+/// HMAC-SHA256 (RFC 2104) of the input
+pub fn hmac_sha256(key: &[u8], input: &[u8]) -> [u8; 32] {
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC accepts a key of any length");
+    mac.update(input);
+    mac.finalize().into_bytes().into()
+}
+
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
     // use crate::types::Pubkey;
 
     #[test]
     fn test_sign_and_verify_native_schnorr_ed25519() {}
+
+    #[test]
+    fn native_seed_is_redacted() {
+        let seed = vec![7u8; 64];
+        let debug = format!("{:?}", KeyType::Native(seed.to_owned()));
+        assert_eq!(debug, format!("Native({})", &hash_with_sha256(&seed)[..8]));
+        assert!(!debug.contains("7, 7"));
+        assert_eq!(format!("{:?}", KeyType::ChainKey), "ChainKey");
+    }
+
+    #[test]
+    fn hmac_sha256_rfc4231() {
+        // test case 2 of RFC 4231
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
 }
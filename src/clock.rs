@@ -0,0 +1,15 @@
+/// The current time in nanoseconds, it falls back to the system time outside the canister
+/// so that the handlers can be tested natively.
+pub fn now() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        ic_cdk::api::time()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time is before the unix epoch")
+            .as_nanos() as u64
+    }
+}
@@ -1,14 +1,23 @@
 #![allow(unused)]
-use crate::aptos_client::{AptosResult, LocalAccount, TxOptions};
-use crate::constants::{DEFAULT_GAS_BUDGET, FEE_TOKEN, NODES_IN_FIDUCIARY_SUBNET};
+use crate::aptos_client::{AptosResult, LocalAccount, State, TxOptions};
+use crate::constants::{
+    DEFAULT_FEE_QUOTE_TTL, DEFAULT_FINALITY_DEPTH, DEFAULT_GAS_BUDGET, DEFAULT_REQUIRED_APPROVALS,
+    FEE_TOKEN, NODES_IN_FIDUCIARY_SUBNET,
+};
 
 use crate::aptos_client::aptos_providers::Provider;
 use crate::aptos_client::constants::NODES_IN_SUBNET;
 use crate::ck_eddsa::KeyType;
+use crate::cycles::{CyclesLevel, CyclesPolicy};
 use crate::ic_log::{DEBUG, ERROR};
 
+use crate::handler::key_rotation::KeyRotation;
 use crate::memory::Memory;
-use crate::types::{ChainId, ChainState, Factor};
+use crate::multisig::MultisigConfig;
+use crate::rate_limit::{PausedToken, RateLimitRule};
+use crate::state::read_state;
+use crate::ticket_quota::TicketQuota;
+use crate::types::{ChainId, ChainState, Factor, TokenId};
 use crate::{auth::Permission, constants::SCHNORR_KEY_NAME, guard::TaskType, lifecycle::InitArgs};
 use aptos_api_types::transaction::{Event, Transaction};
 use candid::{CandidType, Principal};
@@ -22,7 +31,7 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::{
     cell::RefCell,
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 };
 pub type CanisterId = Principal;
 pub type Owner = String;
@@ -41,6 +50,14 @@ pub struct Seqs {
     pub tx_seq: u64,
 }
 
+/// The user tx agreed by the rpc providers
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ValidTx {
+    pub version: u64,
+    pub timestamp_usecs: u64,
+    pub events: Vec<Event>,
+}
+
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
 pub struct MultiRpcConfig {
     pub rpc_list: Vec<String>,
@@ -70,10 +87,11 @@ impl MultiRpcConfig {
         Ok(())
     }
 
+    /// returns the committed version and events of the tx
     pub fn valid_and_get_result(
         &self,
         responses: &Vec<AptosResult<Transaction>>,
-    ) -> Result<Vec<Event>, String> {
+    ) -> Result<ValidTx, String> {
         self.check_config_valid()?;
         let mut events_list = vec![];
         // let mut success_response_body_list = vec![];
@@ -89,9 +107,11 @@ impl MultiRpcConfig {
                     Transaction::PendingTransaction(pending_tx) => {
                         continue;
                     }
-                    Transaction::UserTransaction(user_tx) => {
-                        events_list.push(user_tx.events.to_owned())
-                    }
+                    Transaction::UserTransaction(user_tx) => events_list.push(ValidTx {
+                        version: user_tx.info.version.0,
+                        timestamp_usecs: user_tx.timestamp.0,
+                        events: user_tx.events.to_owned(),
+                    }),
                 },
                 Err(e) => {
                     log!(
@@ -123,6 +143,39 @@ impl MultiRpcConfig {
 
         Ok(events_list[0].to_owned())
     }
+
+    /// returns the latest ledger version reached by at least `minimum_response_count` providers
+    pub fn valid_and_get_ledger_version(
+        &self,
+        responses: &Vec<AptosResult<State>>,
+    ) -> Result<u64, String> {
+        self.check_config_valid()?;
+        let mut versions = vec![];
+        for response in responses {
+            match response {
+                Ok(state) => versions.push(state.version),
+                Err(e) => {
+                    log!(
+                        ERROR,
+                        "[state::valid_and_get_ledger_version] response error: {:?}",
+                        e.to_string()
+                    );
+                    continue;
+                }
+            }
+        }
+
+        if versions.len() < self.minimum_response_count as usize {
+            return Err(format!(
+                "Not enough valid ledger info, expected: {}, actual: {}",
+                self.minimum_response_count,
+                versions.len()
+            ));
+        }
+
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(versions[self.minimum_response_count as usize - 1])
+    }
 }
 
 pub const NATIVE_KEY_TYPE: &str = "Native";
@@ -130,6 +183,8 @@ pub const NATIVE_KEY_TYPE: &str = "Native";
 pub enum SnorKeyType {
     ChainKey,
     Native,
+    ChainKeySecp256k1,
+    MultiEd25519,
 }
 
 impl From<KeyType> for SnorKeyType {
@@ -137,15 +192,28 @@ impl From<KeyType> for SnorKeyType {
         match key_type {
             KeyType::ChainKey => SnorKeyType::ChainKey,
             KeyType::Native(_) => SnorKeyType::Native,
+            KeyType::ChainKeySecp256k1 => SnorKeyType::ChainKeySecp256k1,
+            KeyType::MultiEd25519 => SnorKeyType::MultiEd25519,
         }
     }
 }
+/// A fee asset accepted to pay the redeem fee, its factor is updated by the hub
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct FeeAsset {
+    // the fungible asset object, None for the APT coin or the bridged token itself
+    pub fa_obj: Option<String>,
+    // the account to receive the fee, None for the route fee account
+    pub fee_account: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
 pub struct RouteConfig {
     pub chain_id: String,
     pub hub_principal: Principal,
     pub seqs: Seqs,
-    pub fee_token_factor: Option<u128>,
+    pub fee_token_factors: BTreeMap<TokenId, u128>,
+    pub fee_assets: BTreeMap<TokenId, FeeAsset>,
     pub target_chain_factor: BTreeMap<ChainId, u128>,
     pub chain_state: ChainState,
     pub schnorr_key_name: String,
@@ -156,13 +224,36 @@ pub struct RouteConfig {
     // Locks preventing concurrent execution timer tasks
     pub active_tasks: HashSet<TaskType>,
     pub admin: Principal,
-    pub caller_perms: HashMap<String, Permission>,
+    // a principal may hold several permissions, e.g. `Update` and `Approve`
+    pub caller_perms: HashMap<String, BTreeSet<Permission>>,
     pub multi_rpc_config: MultiRpcConfig,
     pub forward: Option<String>,
     pub enable_debug: bool,
     pub key_type: KeyType,
     pub current_port_package: Option<String>,
     pub tx_opt: TxOptions,
+    // the number of versions the ledger must move past a redeem tx, per network
+    pub finality_depths: BTreeMap<Provider, u64>,
+    // the validity period of the redeem fee quote, in seconds
+    pub fee_quote_ttl: u64,
+    pub rate_limits: Vec<RateLimitRule>,
+    pub paused_tokens: BTreeMap<TokenId, PausedToken>,
+    // the mints above the threshold of the token wait for the approvers
+    pub approval_thresholds: BTreeMap<TokenId, u128>,
+    pub required_approvals: u8,
+    // the decimals of the fa on aptos, if it differs from the hub token decimals
+    pub fa_decimals: BTreeMap<TokenId, u8>,
+    // the hub pushes tickets and directives, the polling only reconciles the gaps
+    pub hub_push_enabled: bool,
+    // the route account address is kept once its authentication key is rotated
+    pub route_address: Option<String>,
+    pub key_rotation: Option<KeyRotation>,
+    // the k-of-n signers of the `MultiEd25519` route account
+    pub multisig: Option<MultisigConfig>,
+    pub cycles_policy: CyclesPolicy,
+    // the last observed level of the cycles balance
+    pub cycles_level: CyclesLevel,
+    pub ticket_quota: TicketQuota,
     // pub local_account: LocalAccount,
     // pub sui_route_address: HashMap<KeyType, Vec<u8>>,
 }
@@ -172,7 +263,8 @@ impl Default for RouteConfig {
             chain_id: String::default(),
             hub_principal: Principal::anonymous(),
             seqs: Seqs::default(),
-            fee_token_factor: None,
+            fee_token_factors: BTreeMap::default(),
+            fee_assets: BTreeMap::from([(FEE_TOKEN.to_string(), FeeAsset::default())]),
             target_chain_factor: BTreeMap::default(),
             chain_state: ChainState::Active,
             schnorr_key_name: SCHNORR_KEY_NAME.to_string(),
@@ -189,6 +281,20 @@ impl Default for RouteConfig {
             key_type: KeyType::ChainKey,
             current_port_package: None,
             tx_opt: TxOptions::default(),
+            finality_depths: BTreeMap::default(),
+            fee_quote_ttl: DEFAULT_FEE_QUOTE_TTL,
+            rate_limits: vec![],
+            paused_tokens: BTreeMap::default(),
+            approval_thresholds: BTreeMap::default(),
+            required_approvals: DEFAULT_REQUIRED_APPROVALS,
+            fa_decimals: BTreeMap::default(),
+            hub_push_enabled: false,
+            route_address: None,
+            key_rotation: None,
+            multisig: None,
+            cycles_policy: CyclesPolicy::default(),
+            cycles_level: CyclesLevel::Normal,
+            ticket_quota: TicketQuota::default(),
         }
     }
 }
@@ -199,7 +305,8 @@ impl From<InitArgs> for RouteConfig {
             chain_id: args.chain_id,
             hub_principal: args.hub_principal,
             seqs: Seqs::default(),
-            fee_token_factor: None,
+            fee_token_factors: BTreeMap::default(),
+            fee_assets: BTreeMap::from([(FEE_TOKEN.to_string(), FeeAsset::default())]),
             target_chain_factor: Default::default(),
             chain_state: args.chain_state,
             schnorr_key_name: args
@@ -209,7 +316,10 @@ impl From<InitArgs> for RouteConfig {
             nodes_in_subnet: args.nodes_in_subnet.unwrap_or(NODES_IN_FIDUCIARY_SUBNET),
             active_tasks: Default::default(),
             admin: args.admin,
-            caller_perms: HashMap::from([(args.admin.to_string(), Permission::Update)]),
+            caller_perms: HashMap::from([(
+                args.admin.to_string(),
+                BTreeSet::from([Permission::Update]),
+            )]),
             fee_account: args.fee_account,
             gas_budget: args.gas_budget.unwrap_or(DEFAULT_GAS_BUDGET),
             multi_rpc_config: MultiRpcConfig::default(),
@@ -218,18 +328,118 @@ impl From<InitArgs> for RouteConfig {
             key_type: KeyType::ChainKey,
             current_port_package: None,
             tx_opt: TxOptions::default(),
+            finality_depths: BTreeMap::default(),
+            fee_quote_ttl: DEFAULT_FEE_QUOTE_TTL,
+            rate_limits: vec![],
+            paused_tokens: BTreeMap::default(),
+            approval_thresholds: BTreeMap::default(),
+            required_approvals: DEFAULT_REQUIRED_APPROVALS,
+            fa_decimals: BTreeMap::default(),
+            hub_push_enabled: false,
+            route_address: None,
+            key_rotation: None,
+            multisig: None,
+            cycles_policy: CyclesPolicy::default(),
+            cycles_level: CyclesLevel::Normal,
+            ticket_quota: TicketQuota::default(),
+        }
+    }
+}
+
+/// The route config written by the first release, it's the plain bincode of the
+/// struct without the magic and the version
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RouteConfigV0 {
+    pub chain_id: String,
+    pub hub_principal: Principal,
+    pub seqs: Seqs,
+    pub fee_token_factor: Option<u128>,
+    pub target_chain_factor: BTreeMap<ChainId, u128>,
+    pub chain_state: ChainState,
+    pub schnorr_key_name: String,
+    pub rpc_provider: Provider,
+    pub nodes_in_subnet: u32,
+    pub fee_account: String,
+    pub gas_budget: u64,
+    pub active_tasks: HashSet<TaskType>,
+    pub admin: Principal,
+    pub caller_perms: HashMap<String, Permission>,
+    pub multi_rpc_config: MultiRpcConfig,
+    pub forward: Option<String>,
+    pub enable_debug: bool,
+    pub key_type: KeyType,
+    pub current_port_package: Option<String>,
+    pub tx_opt: TxOptions,
+}
+
+impl From<RouteConfigV0> for RouteConfig {
+    fn from(v0: RouteConfigV0) -> Self {
+        // the single fee factor was the factor of the APT coin
+        let fee_token_factors = v0
+            .fee_token_factor
+            .map(|factor| BTreeMap::from([(FEE_TOKEN.to_string(), factor)]))
+            .unwrap_or_default();
+        Self {
+            chain_id: v0.chain_id,
+            hub_principal: v0.hub_principal,
+            seqs: v0.seqs,
+            fee_token_factors,
+            target_chain_factor: v0.target_chain_factor,
+            chain_state: v0.chain_state,
+            schnorr_key_name: v0.schnorr_key_name,
+            rpc_provider: v0.rpc_provider,
+            nodes_in_subnet: v0.nodes_in_subnet,
+            fee_account: v0.fee_account,
+            gas_budget: v0.gas_budget,
+            active_tasks: v0.active_tasks,
+            admin: v0.admin,
+            caller_perms: v0
+                .caller_perms
+                .into_iter()
+                .map(|(caller, perm)| (caller, BTreeSet::from([perm])))
+                .collect(),
+            multi_rpc_config: v0.multi_rpc_config,
+            forward: v0.forward,
+            enable_debug: v0.enable_debug,
+            key_type: v0.key_type,
+            current_port_package: v0.current_port_package,
+            tx_opt: v0.tx_opt,
+            ..Default::default()
         }
     }
 }
 
+// the stored config starts with the magic and the version, followed by the cbor of the
+// config. cbor keeps the field names, so the fields added later fall back to their
+// defaults and only a breaking change needs a new version.
+// the magic never starts the v0 bincode, as the length prefix of the chain id it would
+// be far beyond the stable memory
+const CONFIG_MAGIC: &[u8; 4] = b"RCFG";
+const CONFIG_VERSION: u8 = 1;
+
 impl Storable for RouteConfig {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        let bytes = bincode::serialize(&self).expect("failed to serialize SuiRouteConfig");
+        let mut bytes = CONFIG_MAGIC.to_vec();
+        bytes.push(CONFIG_VERSION);
+        ciborium::ser::into_writer(&self, &mut bytes).expect("failed to serialize RouteConfig");
         Cow::Owned(bytes)
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize SuiRouteConfig")
+        let Some(versioned) = bytes.strip_prefix(CONFIG_MAGIC.as_slice()) else {
+            let v0: RouteConfigV0 =
+                bincode::deserialize(bytes.as_ref()).expect("failed to deserialize RouteConfigV0");
+            return RouteConfig::from(v0);
+        };
+        match versioned.split_first() {
+            Some((&CONFIG_VERSION, config)) => {
+                ciborium::de::from_reader(config).expect("failed to deserialize RouteConfig")
+            }
+            other => panic!(
+                "unsupported RouteConfig version: {:?}",
+                other.map(|(version, _)| version)
+            ),
+        }
     }
 
     const BOUND: Bound = Bound::Unbounded;
@@ -237,18 +447,61 @@ impl Storable for RouteConfig {
 
 impl RouteConfig {
     pub fn validate_config(&self) {}
+
+    pub fn finality_depth(&self) -> u64 {
+        self.finality_depths
+            .get(&self.rpc_provider)
+            .copied()
+            .unwrap_or(DEFAULT_FINALITY_DEPTH)
+    }
+
+    /// the fee token of the route chain from the hub, default is APT
+    pub fn default_fee_token(&self) -> TokenId {
+        read_state(|s| s.counterparties.get(&self.chain_id))
+            .and_then(|chain| chain.fee_token)
+            .filter(|fee_token| self.fee_assets.contains_key(fee_token))
+            .unwrap_or(FEE_TOKEN.to_string())
+    }
+
     pub fn get_fee(&self, chain_id: ChainId) -> Option<u128> {
-        read_config(|s| {
-            s.get()
-                .target_chain_factor
-                .get(&chain_id)
-                .map_or(None, |target_chain_factor| {
-                    s.get()
-                        .fee_token_factor
-                        .map(|fee_token_factor| target_chain_factor * fee_token_factor)
-                })
+        self.get_fee_in(chain_id, &self.default_fee_token())
+    }
+
+    pub fn get_fee_in(&self, chain_id: ChainId, fee_token: &TokenId) -> Option<u128> {
+        if !self.fee_assets.contains_key(fee_token) {
+            return None;
+        }
+        let target_chain_factor = self.target_chain_factor.get(&chain_id)?;
+        let fee_token_factor = self.fee_token_factors.get(fee_token)?;
+        Some(target_chain_factor * fee_token_factor)
+    }
+
+    pub fn fee_account_of(&self, fee_token: &TokenId) -> Option<String> {
+        self.fee_assets.get(fee_token).map(|fee_asset| {
+            fee_asset
+                .fee_account
+                .to_owned()
+                .unwrap_or(self.fee_account.to_owned())
+        })
+    }
+
+    /// the fa obj of the fee token, None for the APT coin
+    pub fn fee_fa_obj(&self, fee_token: &TokenId) -> Option<String> {
+        let fee_asset = self.fee_assets.get(fee_token)?;
+        fee_asset.fa_obj.to_owned().or_else(|| {
+            read_state(|s| s.aptos_tokens.get(fee_token)).and_then(|token| token.fa_obj_id)
         })
     }
+
+    pub fn fee_token_of_fa_obj(&self, fa_obj: &String) -> Option<TokenId> {
+        self.fee_assets
+            .keys()
+            .find(|fee_token| {
+                self.fee_fa_obj(fee_token)
+                    .is_some_and(|fee_fa_obj| fee_fa_obj.eq(fa_obj))
+            })
+            .cloned()
+    }
     pub fn update_fee(&mut self, fee: Factor) {
         match fee {
             Factor::UpdateTargetChainFactor(factor) => {
@@ -259,9 +512,8 @@ impl RouteConfig {
             }
 
             Factor::UpdateFeeTokenFactor(token_factor) => {
-                if token_factor.fee_token == FEE_TOKEN {
-                    self.fee_token_factor = Some(token_factor.fee_token_factor);
-                }
+                self.fee_token_factors
+                    .insert(token_factor.fee_token, token_factor.fee_token_factor);
             }
         }
     }
@@ -298,3 +550,86 @@ pub fn replace_config(config: StableRouteConfig) {
         // c.set(config).expect("failed to replace config");
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline_config() -> RouteConfigV0 {
+        RouteConfigV0 {
+            chain_id: "Aptos".to_string(),
+            hub_principal: Principal::from_slice(&[0, 0, 0, 0, 2, 48, 4, 242, 1, 1]),
+            seqs: Seqs {
+                next_ticket_seq: 12,
+                next_directive_seq: 7,
+                tx_seq: 30,
+            },
+            fee_token_factor: Some(10_000),
+            target_chain_factor: BTreeMap::from([("Bitcoin".to_string(), 1_000)]),
+            chain_state: ChainState::Active,
+            schnorr_key_name: "key_1".to_string(),
+            rpc_provider: Provider::Mainnet,
+            nodes_in_subnet: 34,
+            fee_account: "0xfee".to_string(),
+            gas_budget: 2_000_000,
+            active_tasks: HashSet::from([TaskType::HandleTx]),
+            admin: Principal::anonymous(),
+            caller_perms: HashMap::from([("aaaaa-aa".to_string(), Permission::Update)]),
+            multi_rpc_config: MultiRpcConfig {
+                rpc_list: vec!["https://fullnode.mainnet.aptoslabs.com".to_string()],
+                minimum_response_count: 1,
+            },
+            forward: None,
+            enable_debug: true,
+            key_type: KeyType::ChainKey,
+            current_port_package: Some("0xport".to_string()),
+            tx_opt: TxOptions::default(),
+        }
+    }
+
+    #[test]
+    fn decode_baseline_config() {
+        let baseline = baseline_config();
+        let bytes = bincode::serialize(&baseline).unwrap();
+        assert!(!bytes.starts_with(CONFIG_MAGIC));
+
+        let config = RouteConfig::from_bytes(Cow::Owned(bytes));
+        assert_eq!(config.chain_id, baseline.chain_id);
+        assert_eq!(config.hub_principal, baseline.hub_principal);
+        assert_eq!(config.seqs, baseline.seqs);
+        assert_eq!(config.target_chain_factor, baseline.target_chain_factor);
+        assert_eq!(config.rpc_provider, baseline.rpc_provider);
+        assert_eq!(config.gas_budget, baseline.gas_budget);
+        assert_eq!(config.active_tasks, baseline.active_tasks);
+        assert_eq!(
+            config.caller_perms,
+            HashMap::from([("aaaaa-aa".to_string(), BTreeSet::from([Permission::Update]))])
+        );
+        assert_eq!(config.multi_rpc_config, baseline.multi_rpc_config);
+        assert_eq!(config.key_type, baseline.key_type);
+        assert_eq!(config.current_port_package, baseline.current_port_package);
+        assert_eq!(
+            config.fee_token_factors,
+            BTreeMap::from([(FEE_TOKEN.to_string(), 10_000)])
+        );
+        assert!(config.fee_assets.contains_key(FEE_TOKEN));
+        assert_eq!(
+            config.get_fee_in("Bitcoin".to_string(), &FEE_TOKEN.to_string()),
+            Some(10_000_000)
+        );
+        // the fields added after the first release take their defaults
+        assert_eq!(config.fee_quote_ttl, DEFAULT_FEE_QUOTE_TTL);
+        assert_eq!(config.required_approvals, DEFAULT_REQUIRED_APPROVALS);
+        assert!(config.finality_depths.is_empty());
+        assert!(!config.hub_push_enabled);
+
+        // the migrated config is stored in the versioned layout
+        let bytes = config.to_bytes().into_owned();
+        assert!(bytes.starts_with(CONFIG_MAGIC));
+        let decoded = RouteConfig::from_bytes(Cow::Owned(bytes));
+        assert_eq!(decoded.chain_id, config.chain_id);
+        assert_eq!(decoded.seqs, config.seqs);
+        assert_eq!(decoded.caller_perms, config.caller_perms);
+        assert_eq!(decoded.ticket_quota, config.ticket_quota);
+    }
+}
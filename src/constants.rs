@@ -14,20 +14,34 @@ pub const SCHNORR_KEY_NAME: &str = "key_1";
 pub const FEE_TOKEN: &str = "APT";
 
 pub const DEFAULT_GAS_BUDGET: u64 = 5_000_000;
+// the redeem tx must be at least N versions behind the latest ledger version
+pub const DEFAULT_FINALITY_DEPTH: u64 = 100;
+// the max decimals of the fungible asset created on aptos, the hub amount is scaled to it
+pub const MAX_FA_DECIMALS: u8 = 8;
+// the redeem fee quote is valid for N seconds
+pub const DEFAULT_FEE_QUOTE_TTL: u64 = 600;
+// the seed name of the fee quote key
+pub const FEE_QUOTE_SEED: &str = "fee_quote";
 //funcs
 pub const CREATE_FUNGIBLE_ASSET: &str = "create_fa";
 // pub const CREATE_FUNGIBLE_ASSET_V2: &str = "create_fa_v2";
 pub const MINT_WITH_TICKET_FUNC: &str = "mint_fa_with_ticket";
 pub const BURN_TOKEN_FUNC: &str = "burn_fa";
 pub const COLLECT_FEE_FUNC: &str = "collect_fee";
+pub const COLLECT_FA_FEE_FUNC: &str = "collect_fa_fee";
 pub const REMOVE_TICKET_FUNC: &str = "remove_ticket";
 pub const UPDATE_META_FUNC: &str = "mutate_metadata";
 pub const UPDATE_NAME_FUNC: &str = "update_name";
 pub const UPDATE_SYMBOL_FUNC: &str = "update_symbol";
 pub const UPDATE_ICON_FUNC: &str = "update_icon";
 pub const UPDATE_DESC_FUNC: &str = "update_project_uri";
+pub const ADD_MINTER_FUNC: &str = "add_minter";
 pub const GET_FA_OBJ: &str = "get_fa_obj";
 pub const TRANSFER_COINS: &str = "transfer_coins";
+//events
+pub const COLLECT_FEE_EVENT: &str = "CollectFeeEvent";
+pub const COLLECT_FA_FEE_EVENT: &str = "CollectFAFeeEvent";
+pub const BURN_FA_EVENT: &str = "BurnFAEvent";
 // 1  MIST = 0.000_000_001 APT.
 // 1 SUI =1_000_000_000 MAPT
 
@@ -36,14 +50,62 @@ pub const TRANSFER_COINS: &str = "transfer_coins";
 // s2e: free; e2s: 2$; e2e: 1$
 
 pub const DIRECTIVE_LIMIT_SIZE: u64 = 20;
+// the failed directives are retried automatically up to N attempts
+pub const DIRECTIVE_RETRY_LIMIT: u64 = 10;
+// the hub amount of a token with more decimals could overflow u128
+pub const MAX_TOKEN_DECIMALS: u8 = 24;
 pub const TICKET_LIMIT_SIZE: u64 = 20;
 pub const TAKE_SIZE: u64 = 1;
 pub const QUERY_DERECTIVE_INTERVAL: Duration = Duration::from_secs(30);
 // pub const CREATE_MINT_INTERVAL: Duration = Duration::from_secs(50);
 pub const UPDATE_TOKEN_INTERVAL: Duration = Duration::from_secs(30);
 pub const QUERY_TICKET_INTERVAL: Duration = Duration::from_secs(10);
+// the polling intervals once the hub pushes tickets and directives
+pub const RECONCILE_TICKET_INTERVAL: Duration = Duration::from_secs(5 * 60);
+pub const RECONCILE_DIRECTIVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 pub const MINT_TOKEN_INTERVAL: Duration = Duration::from_secs(20);
 pub const CLEAR_INTERVAL: Duration = Duration::from_secs(30);
 pub const HANDLE_TX_INTERVAL: Duration = Duration::from_secs(15);
+pub const RESEND_TICKET_INTERVAL: Duration = Duration::from_secs(60);
+pub const RESEND_TICKET_BACKOFF_BASE: Duration = Duration::from_secs(60);
+pub const RESEND_TICKET_BACKOFF_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+// alert once a ticket failed to send to hub is older than this
+pub const RESEND_TICKET_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+pub const RESEND_LIMIT_SIZE: usize = 10;
+// the max transitions kept in a ticket timeline
+pub const MAX_TICKET_EVENTS: usize = 32;
+// the ticket timeline is removed once it has no new stage for this long
+pub const TICKET_TIMELINE_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+pub const PRUNE_RECORDS_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// the records checked by one run of the prune task, per map
+pub const PRUNE_BATCH_SIZE: usize = 500;
+// the max ticket timelines returned by one page
+pub const MAX_PAGE_SIZE: u64 = 100;
+// the distinct approvers required to approve a large mint
+pub const DEFAULT_REQUIRED_APPROVALS: u8 = 1;
 // pub const RETRY_4_BUILDING: u64 = 10;
 pub const RETRY_NUM: u64 = 5;
+pub const REFRESH_ROUTE_ACCOUNT_INTERVAL: Duration = Duration::from_secs(60);
+// the route account is stuck if its submitted tx is not committed in time
+pub const ROUTE_ACCOUNT_STUCK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// 0.1 APT, the route account below it is not assigned with new txs
+pub const MIN_ROUTE_ACCOUNT_BALANCE: u64 = 10_000_000;
+pub const MAX_ROUTE_ACCOUNTS: u32 = 32;
+// the max keys of the `MultiEd25519` account
+pub const MAX_MULTISIG_SIGNERS: usize = 32;
+// the multisig tx stays valid while the offline signers submit their shares
+pub const DEFAULT_MULTISIG_TX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+// the paid operations averaged by the rolling cycles cost
+pub const CYCLES_AVG_WINDOW: u64 = 20;
+// below it the non-essential tasks are paused
+pub const DEFAULT_LOW_CYCLES_THRESHOLD: u128 = 10_000_000_000_000;
+// below it the new redeems are rejected, only the mints are finalized
+pub const DEFAULT_CRITICAL_CYCLES_THRESHOLD: u128 = 3_000_000_000_000;
+// the unpaid `generate_ticket` calls allowed in the window
+pub const DEFAULT_TICKET_QUOTA_WINDOW_SECS: u64 = 60;
+pub const DEFAULT_TICKET_CALLER_MAX_CALLS: u64 = 5;
+pub const DEFAULT_TICKET_GLOBAL_MAX_CALLS: u64 = 100;
+// the caller is banned once its verifications failed too often
+pub const DEFAULT_TICKET_MAX_FAILURES: u64 = 10;
+pub const DEFAULT_TICKET_FAILURE_WINDOW_SECS: u64 = 60 * 60;
+pub const DEFAULT_TICKET_BAN_SECS: u64 = 24 * 60 * 60;
@@ -0,0 +1,406 @@
+use std::borrow::Cow;
+
+use candid::CandidType;
+use ic_canister_log::log;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::aptos_client::ReqType;
+use crate::config::{mutate_config, read_config};
+use crate::constants::{
+    CYCLES_AVG_WINDOW, DEFAULT_CRITICAL_CYCLES_THRESHOLD, DEFAULT_LOW_CYCLES_THRESHOLD,
+};
+use crate::guard::TaskType;
+use crate::ic_log::CRITICAL;
+use crate::state::{mutate_state, read_state};
+use crate::types::TokenId;
+
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(
+    CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum CostKind {
+    HttpOutcall,
+    SignWithEddsa,
+    SignWithEcdsa,
+}
+
+/// Who the paid operation is done for, it's carried by the `RestClient` and the
+/// `LocalAccount` down to the outcall and the signing call
+#[derive(CandidType, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CostScope {
+    pub task: Option<TaskType>,
+    // the `ReqType` variant or the endpoint name, e.g. `MintToken`, `GenerateTicket`
+    pub req_type: Option<String>,
+    pub token: Option<TokenId>,
+}
+
+impl CostScope {
+    pub fn task(task: TaskType) -> Self {
+        Self {
+            task: Some(task),
+            ..Default::default()
+        }
+    }
+
+    pub fn request(req_type: &str, token: Option<TokenId>) -> Self {
+        Self {
+            task: None,
+            req_type: Some(req_type.to_string()),
+            token,
+        }
+    }
+
+    pub fn with_req(mut self, req: &ReqType) -> Self {
+        let (req_type, token) = match req {
+            ReqType::CreateToken(req) => ("CreateToken", Some(req.token_id.to_owned())),
+            ReqType::UpdateMeta(req) => ("UpdateMeta", Some(req.token_id.to_owned())),
+            ReqType::MintToken(req) => ("MintToken", Some(req.token_id.to_owned())),
+            ReqType::BurnToken(_) => ("BurnToken", None),
+            ReqType::CollectFee(_) => ("CollectFee", None),
+            ReqType::RemoveTicket(_) => ("RemoveTicket", None),
+            ReqType::TransferApt(_) => ("TransferApt", None),
+            ReqType::CollectFAFee(_) => ("CollectFAFee", None),
+            ReqType::AddMinter(_) => ("AddMinter", None),
+        };
+        self.req_type = Some(req_type.to_string());
+        self.token = token;
+        self
+    }
+
+    fn key(&self, kind: CostKind) -> String {
+        format!(
+            "{}/{}/{}/{:?}",
+            self.task.map(|t| format!("{:?}", t)).unwrap_or_default(),
+            self.req_type.to_owned().unwrap_or_default(),
+            self.token.to_owned().unwrap_or_default(),
+            kind
+        )
+    }
+}
+
+/// The cycles spent by a scope on one kind of the paid operations
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CycleStats {
+    pub scope: CostScope,
+    pub kind: CostKind,
+    pub count: u64,
+    // the charged cycles, the refunded ones are not included
+    pub total: u128,
+    pub refunded: u128,
+    // the exponential moving average over the last `CYCLES_AVG_WINDOW` operations
+    pub rolling_avg: u128,
+    pub first_at: u64,
+    pub last_at: u64,
+}
+
+impl Storable for CycleStats {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize CycleStats");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize CycleStats")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl CycleStats {
+    fn new(scope: CostScope, kind: CostKind, now: u64) -> Self {
+        Self {
+            scope,
+            kind,
+            count: 0,
+            total: 0,
+            refunded: 0,
+            rolling_avg: 0,
+            first_at: now,
+            last_at: now,
+        }
+    }
+
+    fn add(&mut self, attached: u128, refunded: u128, now: u64) {
+        let cycles = attached.saturating_sub(refunded);
+        self.rolling_avg = if self.count == 0 {
+            cycles
+        } else {
+            let window = CYCLES_AVG_WINDOW as u128;
+            (self.rolling_avg * (window - 1) + cycles) / window
+        };
+        self.count += 1;
+        self.total = self.total.saturating_add(cycles);
+        self.refunded = self.refunded.saturating_add(refunded);
+        self.last_at = now;
+    }
+}
+
+/// The canister gives up the work step by step as the cycles balance drops
+#[derive(
+    CandidType, Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum CyclesLevel {
+    #[default]
+    Normal,
+    // the metadata sync and the reconciliation with the hub are paused
+    Low,
+    // the new redeems are rejected and the route accounts are not refreshed,
+    // only the mints are still finalized
+    Critical,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CyclesPolicy {
+    pub low_threshold: u128,
+    pub critical_threshold: u128,
+}
+
+impl Default for CyclesPolicy {
+    fn default() -> Self {
+        Self {
+            low_threshold: DEFAULT_LOW_CYCLES_THRESHOLD,
+            critical_threshold: DEFAULT_CRITICAL_CYCLES_THRESHOLD,
+        }
+    }
+}
+
+impl CyclesPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.critical_threshold >= self.low_threshold {
+            return Err(format!(
+                "the critical threshold {} must be lower than the low threshold {}",
+                self.critical_threshold, self.low_threshold
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn level(&self, balance: u128) -> CyclesLevel {
+        if balance < self.critical_threshold {
+            CyclesLevel::Critical
+        } else if balance < self.low_threshold {
+            CyclesLevel::Low
+        } else {
+            CyclesLevel::Normal
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CyclesReport {
+    pub balance: u128,
+    pub level: CyclesLevel,
+    pub total_spent: u128,
+    pub total_refunded: u128,
+    // the attributed spending per day since the first recorded operation
+    pub daily_burn: u128,
+    pub runway_days: Option<u64>,
+    pub stats: Vec<CycleStats>,
+}
+
+/// record the cycles attached to the paid call, `refunded` is read from
+/// `msg_cycles_refunded128` right after the call returns
+pub fn record(scope: &CostScope, kind: CostKind, attached: u128, refunded: u128) {
+    let now = crate::clock::now();
+    let key = scope.key(kind);
+    mutate_state(|s| {
+        let mut stats = s
+            .cycle_stats
+            .get(&key)
+            .unwrap_or_else(|| CycleStats::new(scope.to_owned(), kind, now));
+        stats.add(attached, refunded, now);
+        s.cycle_stats.insert(key, stats);
+    });
+}
+
+/// the level of the current balance, each change of the level is logged as critical
+pub fn current_level() -> CyclesLevel {
+    let balance = ic_cdk::api::canister_balance128();
+    let (policy, last_level) =
+        read_config(|s| (s.get().cycles_policy.to_owned(), s.get().cycles_level));
+    let level = policy.level(balance);
+    if level != last_level {
+        log!(
+            CRITICAL,
+            "[cycles::current_level] the cycles balance is {}, the level changed from {:?} to {:?}: {}",
+            balance,
+            last_level,
+            level,
+            describe(level)
+        );
+        mutate_config(|s| {
+            let mut config = s.get().to_owned();
+            config.cycles_level = level;
+            s.set(config);
+        });
+    }
+    level
+}
+
+fn describe(level: CyclesLevel) -> &'static str {
+    match level {
+        CyclesLevel::Normal => "all the tasks are running",
+        CyclesLevel::Low => "the metadata sync and the reconciliation are paused",
+        CyclesLevel::Critical => "the new redeems are rejected and only the mints are finalized",
+    }
+}
+
+/// the polling of the tickets and the directives only reconciles the gaps if the hub pushes them
+pub fn task_paused(level: CyclesLevel, task: TaskType, hub_push_enabled: bool) -> bool {
+    let required = match task {
+        TaskType::HandleTx | TaskType::ResendTickets | TaskType::PruneRecords => return false,
+        TaskType::GetTickets | TaskType::GetDirectives if !hub_push_enabled => return false,
+        TaskType::GetTickets | TaskType::GetDirectives | TaskType::UpdateToken => CyclesLevel::Low,
+        TaskType::RefreshRouteAccounts => CyclesLevel::Critical,
+    };
+    level >= required
+}
+
+pub fn is_task_paused(task: TaskType) -> bool {
+    let hub_push_enabled = read_config(|s| s.get().hub_push_enabled);
+    task_paused(current_level(), task, hub_push_enabled)
+}
+
+/// reject the new redeem before any outcall is made for it
+pub fn check_redeem() -> Result<(), String> {
+    match current_level() {
+        CyclesLevel::Critical => {
+            Err("the route is low on cycles, the redeem is not accepted for now".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn report(balance: u128) -> CyclesReport {
+    let stats: Vec<_> = read_state(|s| s.cycle_stats.iter().map(|(_, v)| v).collect());
+    let total_spent = stats.iter().map(|s| s.total).sum();
+    let total_refunded = stats.iter().map(|s| s.refunded).sum();
+    let since = stats.iter().map(|s| s.first_at).min();
+    let daily_burn = daily_burn(total_spent, since, crate::clock::now());
+    CyclesReport {
+        balance,
+        level: read_config(|s| s.get().cycles_policy.level(balance)),
+        total_spent,
+        total_refunded,
+        daily_burn,
+        runway_days: runway_days(balance, daily_burn),
+        stats,
+    }
+}
+
+// at least one day is counted, so a few calls right after the upgrade don't inflate the rate
+fn daily_burn(total_spent: u128, since: Option<u64>, now: u64) -> u128 {
+    let Some(since) = since else {
+        return 0;
+    };
+    let elapsed = now.saturating_sub(since).max(DAY_NANOS) as u128;
+    total_spent * DAY_NANOS as u128 / elapsed
+}
+
+fn runway_days(balance: u128, daily_burn: u128) -> Option<u64> {
+    (daily_burn > 0).then(|| (balance / daily_burn).min(u64::MAX as u128) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_average() {
+        let mut stats = CycleStats::new(
+            CostScope::task(TaskType::HandleTx),
+            CostKind::HttpOutcall,
+            0,
+        );
+        stats.add(1_500, 500, 1);
+        assert_eq!(stats.rolling_avg, 1_000);
+        for _ in 0..CYCLES_AVG_WINDOW * 10 {
+            stats.add(2_000, 0, 2);
+        }
+        // converges to the recent cost
+        assert!(stats.rolling_avg > 1_990 && stats.rolling_avg <= 2_000);
+        assert_eq!(stats.count, CYCLES_AVG_WINDOW * 10 + 1);
+        assert_eq!(
+            stats.total,
+            1_000 + 2_000 * (CYCLES_AVG_WINDOW * 10) as u128
+        );
+        assert_eq!(stats.refunded, 500);
+    }
+
+    #[test]
+    fn burn_and_runway() {
+        assert_eq!(daily_burn(1_000, None, DAY_NANOS), 0);
+        // less than a day counts as one day
+        assert_eq!(daily_burn(1_000, Some(0), 60), 1_000);
+        assert_eq!(daily_burn(1_000, Some(0), 4 * DAY_NANOS), 250);
+        assert_eq!(runway_days(1_000, 250), Some(4));
+        assert_eq!(runway_days(1_000, 0), None);
+    }
+
+    #[test]
+    fn staged_pausing() {
+        let policy = CyclesPolicy {
+            low_threshold: 100,
+            critical_threshold: 10,
+        };
+        assert!(policy.validate().is_ok());
+        assert_eq!(policy.level(100), CyclesLevel::Normal);
+        assert_eq!(policy.level(99), CyclesLevel::Low);
+        assert_eq!(policy.level(9), CyclesLevel::Critical);
+
+        assert!(!task_paused(
+            CyclesLevel::Normal,
+            TaskType::UpdateToken,
+            true
+        ));
+        assert!(task_paused(CyclesLevel::Low, TaskType::UpdateToken, true));
+        // the reconciliation is paused, the polling is not
+        assert!(task_paused(CyclesLevel::Low, TaskType::GetTickets, true));
+        assert!(!task_paused(
+            CyclesLevel::Critical,
+            TaskType::GetTickets,
+            false
+        ));
+        assert!(!task_paused(
+            CyclesLevel::Low,
+            TaskType::RefreshRouteAccounts,
+            true
+        ));
+        assert!(task_paused(
+            CyclesLevel::Critical,
+            TaskType::RefreshRouteAccounts,
+            true
+        ));
+        // the mints are finalized at any level
+        assert!(!task_paused(
+            CyclesLevel::Critical,
+            TaskType::HandleTx,
+            true
+        ));
+        assert!(!task_paused(
+            CyclesLevel::Critical,
+            TaskType::ResendTickets,
+            true
+        ));
+
+        let invalid = CyclesPolicy {
+            low_threshold: 10,
+            critical_threshold: 10,
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn scope_of_req() {
+        let scope = CostScope::task(TaskType::HandleTx).with_req(&ReqType::AddMinter("0x1".into()));
+        assert_eq!(scope.req_type, Some("AddMinter".to_string()));
+        assert_eq!(scope.token, None);
+        assert_eq!(
+            scope.key(CostKind::SignWithEddsa),
+            "HandleTx/AddMinter//SignWithEddsa"
+        );
+    }
+}
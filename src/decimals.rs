@@ -0,0 +1,145 @@
+#![allow(unused)]
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::config::read_config;
+use crate::constants::MAX_FA_DECIMALS;
+use crate::state::read_state;
+use crate::types::TokenId;
+
+/// The amount converted between two decimals, rounded down.
+/// The dust is the remainder that can't be represented in the target decimals.
+#[derive(CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Scaled {
+    pub amount: u128,
+    pub dust: u128,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ScaleError {
+    Overflow { amount: u128, from: u8, to: u8 },
+    // the whole amount is dust in the target decimals
+    TooSmall { amount: u128, from: u8, to: u8 },
+}
+
+impl std::fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScaleError::Overflow { amount, from, to } => write!(
+                f,
+                "amount {} overflows when scaled from {} to {} decimals",
+                amount, from, to
+            ),
+            ScaleError::TooSmall { amount, from, to } => write!(
+                f,
+                "amount {} is zero when scaled from {} to {} decimals",
+                amount, from, to
+            ),
+        }
+    }
+}
+
+/// convert the amount from `from` decimals to `to` decimals, the dust is rounded down
+pub fn scale(amount: u128, from: u8, to: u8) -> Result<Scaled, ScaleError> {
+    let pow = |exp: u8| {
+        10u128
+            .checked_pow(exp as u32)
+            .ok_or(ScaleError::Overflow { amount, from, to })
+    };
+    let scaled = if to >= from {
+        Scaled {
+            amount: amount
+                .checked_mul(pow(to - from)?)
+                .ok_or(ScaleError::Overflow { amount, from, to })?,
+            dust: 0,
+        }
+    } else {
+        let divisor = pow(from - to)?;
+        Scaled {
+            amount: amount / divisor,
+            dust: amount % divisor,
+        }
+    };
+    if amount > 0 && scaled.amount == 0 {
+        return Err(ScaleError::TooSmall { amount, from, to });
+    }
+    Ok(scaled)
+}
+
+/// the decimals of the fungible asset created on aptos for the hub token
+pub fn fa_decimals_for(hub_decimals: u8) -> u8 {
+    hub_decimals.min(MAX_FA_DECIMALS)
+}
+
+/// return (hub decimals, aptos fa decimals) of the token.
+/// the tokens created before the fa decimals are recorded use the hub decimals on aptos
+pub fn token_decimals(token_id: &TokenId) -> Option<(u8, u8)> {
+    let hub_decimals = read_state(|s| s.tokens.get(token_id))?.decimals;
+    let fa_decimals =
+        read_config(|s| s.get().fa_decimals.get(token_id).copied()).unwrap_or(hub_decimals);
+    Some((hub_decimals, fa_decimals))
+}
+
+/// convert the hub ticket amount to the aptos fa amount
+pub fn to_aptos_amount(token_id: &TokenId, amount: u128) -> Option<Result<Scaled, ScaleError>> {
+    token_decimals(token_id).map(|(hub, fa)| scale(amount, hub, fa))
+}
+
+/// convert the aptos fa amount to the hub ticket amount
+pub fn to_hub_amount(token_id: &TokenId, amount: u128) -> Option<Result<Scaled, ScaleError>> {
+    token_decimals(token_id).map(|(hub, fa)| scale(amount, fa, hub))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_up_and_down() {
+        assert_eq!(
+            scale(123, 8, 18),
+            Ok(Scaled {
+                amount: 1_230_000_000_000,
+                dust: 0
+            })
+        );
+        assert_eq!(
+            scale(1_234_567_890_123_456_789, 18, 8),
+            Ok(Scaled {
+                amount: 123_456_789,
+                dust: 123_456_789
+            })
+        );
+        assert_eq!(
+            scale(42, 8, 8),
+            Ok(Scaled {
+                amount: 42,
+                dust: 0
+            })
+        );
+        assert_eq!(scale(0, 18, 8), Ok(Scaled { amount: 0, dust: 0 }));
+    }
+
+    #[test]
+    fn scale_errors() {
+        assert_eq!(
+            scale(999, 18, 8),
+            Err(ScaleError::TooSmall {
+                amount: 999,
+                from: 18,
+                to: 8
+            })
+        );
+        assert!(matches!(
+            scale(u128::MAX, 0, 18),
+            Err(ScaleError::Overflow { .. })
+        ));
+        assert!(matches!(scale(1, 0, 40), Err(ScaleError::Overflow { .. })));
+    }
+
+    #[test]
+    fn fa_decimals_are_capped() {
+        assert_eq!(fa_decimals_for(18), MAX_FA_DECIMALS);
+        assert_eq!(fa_decimals_for(6), 6);
+    }
+}
@@ -9,6 +9,10 @@ pub enum TaskType {
     GetDirectives,
     GetTickets,
     HandleTx,
+    ResendTickets,
+    UpdateToken,
+    RefreshRouteAccounts,
+    PruneRecords,
 }
 
 #[derive(Debug, PartialEq, Eq)]
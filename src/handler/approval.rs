@@ -0,0 +1,152 @@
+use crate::aptos_client::MintTokenReq;
+use crate::auth::{has_perm, Permission};
+use crate::config::read_config;
+use crate::handler::fetch_ticket;
+use crate::handler::gen_ticket::send_ticket;
+use crate::hub_client::CanisterHubClient;
+use crate::ic_log::{DEBUG, INFO, WARNING};
+use crate::rate_limit;
+use crate::state::{mutate_state, read_state, PendingApproval, TicketDirection, TicketStage};
+use crate::types::{Memo, Ticket, TicketId, TicketType, TxAction};
+
+use ic_canister_log::log;
+
+/// return the approval threshold if the mint must wait for the approvers
+pub fn requires_approval(mint_req: &MintTokenReq) -> Option<u128> {
+    read_config(|s| {
+        s.get()
+            .approval_thresholds
+            .get(&mint_req.token_id)
+            .copied()
+            .filter(|threshold| mint_req.mint_acmount as u128 > *threshold)
+    })
+}
+
+pub fn add_pending_approval(ticket: Ticket, mint_req: MintTokenReq, threshold: u128) {
+    log!(
+        INFO,
+        "[approval::add_pending_approval] the ticket {} amount {} is above the threshold {}",
+        ticket.ticket_id,
+        mint_req.mint_acmount,
+        threshold
+    );
+    record_stage(
+        &ticket.ticket_id,
+        TicketStage::PendingApproval { threshold },
+    );
+    mutate_state(|s| {
+        s.pending_approvals.insert(
+            ticket.ticket_id.to_owned(),
+            PendingApproval {
+                ticket,
+                mint_req,
+                threshold,
+                approvers: vec![],
+                created_at: crate::clock::now(),
+            },
+        )
+    });
+}
+
+/// approve the pending mint, return true once it has enough approvals and is moved into `tx_queue`.
+/// only the approvers still holding `Approve` are counted
+pub fn approve(ticket_id: &TicketId, approver: String) -> Result<bool, String> {
+    if !has_perm(&approver, Permission::Approve) {
+        return Err(format!("{} is not an approver", approver));
+    }
+    let mut pending = read_state(|s| s.pending_approvals.get(ticket_id))
+        .ok_or(format!("not found the pending approval: {}", ticket_id))?;
+    // the token may be paused while the mint waits, it's approved once the token is resumed
+    if rate_limit::is_paused(&pending.mint_req.token_id) {
+        return Err(format!(
+            "the token {} is paused, {} can't be approved",
+            pending.mint_req.token_id, ticket_id
+        ));
+    }
+    if pending.approvers.contains(&approver) {
+        return Err(format!("{} already approved {}", approver, ticket_id));
+    }
+    pending.approvers.push(approver.to_owned());
+    record_stage(ticket_id, TicketStage::Approved { approver });
+
+    let required = read_config(|s| s.get().required_approvals).max(1) as usize;
+    let approvals = pending
+        .approvers
+        .iter()
+        .filter(|approver| has_perm(approver, Permission::Approve))
+        .count();
+    if approvals < required {
+        mutate_state(|s| s.pending_approvals.insert(ticket_id.to_owned(), pending));
+        return Ok(false);
+    }
+
+    mutate_state(|s| s.pending_approvals.remove(ticket_id));
+    fetch_ticket::enqueue_mint(&pending.ticket, pending.mint_req);
+    Ok(true)
+}
+
+/// reject the pending mint and send a refund ticket back to the source chain via hub
+pub async fn reject(ticket_id: &TicketId, approver: String, reason: String) -> Result<(), String> {
+    let pending = read_state(|s| s.pending_approvals.get(ticket_id))
+        .ok_or(format!("not found the pending approval: {}", ticket_id))?;
+    let refund = refund_ticket(&pending.ticket, &reason)?;
+
+    mutate_state(|s| s.pending_approvals.remove(ticket_id));
+    record_stage(
+        ticket_id,
+        TicketStage::Rejected {
+            approver,
+            reason: reason.to_owned(),
+        },
+    );
+
+    match send_ticket(&CanisterHubClient::from_config(), refund.to_owned()).await {
+        Ok(()) => {
+            log!(
+                DEBUG,
+                "[approval::reject] successful to send refund ticket: {}",
+                refund.ticket_id
+            );
+        }
+        Err(err) => {
+            log!(
+                WARNING,
+                "[approval::reject] failed to send refund ticket: {}, error: {}, it will be resent",
+                refund.ticket_id,
+                err
+            );
+            mutate_state(|s| s.add_failed_ticket(refund, err.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn refund_ticket(ticket: &Ticket, reason: &str) -> Result<Ticket, String> {
+    let sender = ticket.sender.to_owned().ok_or(format!(
+        "the ticket {} has no sender to refund",
+        ticket.ticket_id
+    ))?;
+    let memo = Memo {
+        memo: Some(format!("rejected: {}", reason)),
+        bridge_fee: 0,
+        fee_token: None,
+    };
+    let memo_json = serde_json::to_string(&memo).map_err(|e| e.to_string())?;
+    Ok(Ticket {
+        ticket_id: format!("{}-refund", ticket.ticket_id),
+        ticket_type: TicketType::Normal,
+        ticket_time: crate::clock::now(),
+        src_chain: read_config(|s| s.get().chain_id.to_owned()),
+        dst_chain: ticket.src_chain.to_owned(),
+        action: TxAction::Redeem,
+        token: ticket.token.to_owned(),
+        amount: ticket.amount.to_owned(),
+        sender: Some(ticket.receiver.to_owned()),
+        receiver: sender,
+        memo: Some(memo_json.into_bytes()),
+    })
+}
+
+fn record_stage(ticket_id: &TicketId, stage: TicketStage) {
+    mutate_state(|s| s.record_ticket_stage(ticket_id, TicketDirection::Inbound, stage));
+}
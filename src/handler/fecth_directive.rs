@@ -1,54 +1,40 @@
 #![allow(unused)]
 use crate::aptos_client::{CreateTokenReq, ReqType, TxReq, TxStatus, UpdateMetaReq};
-use crate::constants::DIRECTIVE_LIMIT_SIZE;
+use crate::constants::{DIRECTIVE_LIMIT_SIZE, DIRECTIVE_RETRY_LIMIT, MAX_TOKEN_DECIMALS};
 
 use crate::config::{mutate_config, read_config};
-use crate::state::{mutate_state, read_state, UpdateTokenStatus, UpdateType};
-use crate::types::{ChainId, Directive, Error, Seq, Topic};
+use crate::hub_client::{CanisterHubClient, HubClient};
+use crate::state::{
+    mutate_state, read_state, DirectiveRecord, DirectiveStatus, UpdateTokenStatus, UpdateType,
+};
+use crate::types::{ChainId, Directive, Error, Factor, Seq, Topic};
 use candid::Principal;
 
 use crate::call_error::{CallError, Reason};
-use crate::ic_log::{DEBUG, ERROR};
+use crate::ic_log::{CRITICAL, DEBUG, ERROR, WARNING};
 use ic_canister_log::log;
 
 /// query directives from hub and save to route state
 pub async fn query_directives() {
+    query_directives_from(&CanisterHubClient::from_config()).await
+}
+
+pub async fn query_directives_from(hub: &impl HubClient) {
     // log!(DEBUG, "[query_directives] running .... ");
-    let (hub_principal, offset) =
-        read_config(|s| (s.get().hub_principal, s.get().seqs.next_directive_seq));
-    match inner_query_directives(hub_principal, offset, DIRECTIVE_LIMIT_SIZE).await {
+    let offset = read_config(|s| s.get().seqs.next_directive_seq);
+    match hub.query_directives(offset, DIRECTIVE_LIMIT_SIZE).await {
         Ok(directives) => {
-            for (_, directive) in &directives {
-                match directive {
-                    Directive::AddChain(chain) | Directive::UpdateChain(chain) => {
-                        mutate_state(|s| s.add_chain(chain.to_owned()));
-                    }
-
-                    Directive::AddToken(token) => {
-                        mutate_state(|s| s.add_token(token.to_owned()));
-                    }
-
-                    Directive::UpdateToken(update_token) => {
-                        mutate_state(|s| s.update_token(update_token.to_owned()));
-                    }
-                    Directive::ToggleChainState(toggle) => {
-                        mutate_state(|s| s.toggle_chain_state(toggle.to_owned()));
-                    }
-                    Directive::UpdateFee(fee) => {
-                        mutate_config(|s| {
-                            let mut config = s.get().to_owned();
-                            config.update_fee(fee.to_owned());
-                            s.set(config);
-                        });
-                    }
+            // the directives may be pushed by hub while querying
+            let mut next_seq = read_config(|s| s.get().seqs.next_directive_seq);
+            for (seq, directive) in directives {
+                if seq < next_seq {
+                    continue;
                 }
+                handle_directive(seq, directive);
+                next_seq = seq + 1;
             }
-            let next_seq = directives.last().map_or(offset, |(seq, _)| seq + 1);
-            mutate_config(|s| {
-                let mut config = s.get().to_owned();
-                config.seqs.next_directive_seq = next_seq;
-                s.set(config);
-            });
+            set_next_directive_seq(next_seq);
+            retry_failed_directives();
         }
         Err(err) => {
             log!(
@@ -60,29 +46,250 @@ pub async fn query_directives() {
     };
 }
 
-pub async fn inner_query_directives(
-    hub_principal: Principal,
-    offset: u64,
-    limit: u64,
-) -> Result<Vec<(Seq, Directive)>, CallError> {
-    let resp: (Result<Vec<(Seq, Directive)>, Error>,) = ic_cdk::api::call::call(
-        hub_principal,
-        "query_directives",
-        (
-            None::<Option<ChainId>>,
-            None::<Option<Topic>>,
-            offset,
-            limit,
-        ),
-    )
-    .await
-    .map_err(|(code, message)| CallError {
-        method: "query_directives".to_string(),
-        reason: Reason::from_reject(code, message),
-    })?;
-    let data = resp.0.map_err(|err| CallError {
-        method: "query_directives".to_string(),
-        reason: Reason::CanisterError(err.to_string()),
-    })?;
-    Ok(data)
+/// handle the directives pushed by hub in order, the duplicated ones are skipped.
+/// return the next expected seq and whether a gap is detected, the directives after the gap
+/// are left to the polling
+pub fn receive_pushed_directives(mut directives: Vec<(Seq, Directive)>) -> (Seq, bool) {
+    directives.sort_by_key(|(seq, _)| *seq);
+    let mut next_seq = read_config(|s| s.get().seqs.next_directive_seq);
+    let mut gap = false;
+    for (seq, directive) in directives {
+        if seq < next_seq {
+            continue;
+        }
+        if seq > next_seq {
+            log!(
+                WARNING,
+                "[receive_pushed_directives] gap detected, expected seq: {}, pushed seq: {}",
+                next_seq,
+                seq
+            );
+            gap = true;
+            break;
+        }
+        handle_directive(seq, directive);
+        next_seq += 1;
+    }
+    set_next_directive_seq(next_seq);
+    (next_seq, gap)
+}
+
+/// record the directive with its hash, then validate and apply it.
+/// the same directive received again is skipped
+fn handle_directive(seq: Seq, directive: Directive) {
+    let hash = directive.hash();
+    if let Some(record) = read_state(|s| s.directive_records.get(&seq)) {
+        if record.hash != hash {
+            log!(
+                CRITICAL,
+                "[handle_directive] the directive at seq {} is changed, recorded: {}, received: {}",
+                seq,
+                record.hash,
+                hash
+            );
+        }
+        return;
+    }
+    let now = crate::clock::now();
+    mutate_state(|s| {
+        s.directive_records.insert(
+            seq,
+            DirectiveRecord {
+                seq,
+                hash,
+                directive,
+                status: DirectiveStatus::Pending,
+                attempts: 0,
+                received_at: now,
+                updated_at: now,
+            },
+        )
+    });
+    let _ = apply_directive(seq);
+}
+
+/// validate and apply the recorded directive, the outcome is recorded
+pub fn apply_directive(seq: Seq) -> Result<(), String> {
+    let mut record = read_state(|s| s.directive_records.get(&seq))
+        .ok_or(format!("not found the directive: {}", seq))?;
+    if record.status == DirectiveStatus::Applied {
+        return Err(format!("the directive {} is applied already", seq));
+    }
+    if let DirectiveStatus::Superseded { by } = record.status {
+        return Err(format!("the directive {} is superseded by {}", seq, by));
+    }
+    // applying the stale directive would revert the newer state
+    if let Some(by) = superseded_by(seq, &record.directive) {
+        log!(
+            WARNING,
+            "[apply_directive] skip directive {}: {}, superseded by the applied directive {}",
+            seq,
+            record.directive,
+            by
+        );
+        record.status = DirectiveStatus::Superseded { by };
+        record.updated_at = crate::clock::now();
+        mutate_state(|s| s.directive_records.insert(seq, record));
+        return Err(format!("the directive {} is superseded by {}", seq, by));
+    }
+    let result = validate_directive(&record.directive)
+        .and_then(|_| execute_directive(record.directive.to_owned()));
+    record.attempts += 1;
+    record.updated_at = crate::clock::now();
+    record.status = match &result {
+        Ok(()) => {
+            log!(
+                DEBUG,
+                "[apply_directive] applied directive {}: {}",
+                seq,
+                record.directive
+            );
+            DirectiveStatus::Applied
+        }
+        Err(error) => {
+            log!(
+                ERROR,
+                "[apply_directive] failed to apply directive {}: {}, error: {}",
+                seq,
+                record.directive,
+                error
+            );
+            DirectiveStatus::Failed {
+                error: error.to_owned(),
+            }
+        }
+    };
+    mutate_state(|s| s.directive_records.insert(seq, record));
+    result
+}
+
+/// retry the failed directives, e.g. the update token waits for the fa obj
+pub fn retry_failed_directives() {
+    let failed: Vec<Seq> = read_state(|s| {
+        s.directive_records
+            .iter()
+            .filter(|(_, record)| {
+                matches!(record.status, DirectiveStatus::Failed { .. })
+                    && record.attempts < DIRECTIVE_RETRY_LIMIT
+            })
+            .map(|(seq, _)| seq)
+            .collect()
+    });
+    for seq in failed {
+        let _ = apply_directive(seq);
+    }
+}
+
+/// the chain, token or fee factor changed by the directive
+fn directive_target(directive: &Directive) -> String {
+    match directive {
+        Directive::AddChain(chain) | Directive::UpdateChain(chain) => {
+            format!("chain:{}", chain.chain_id)
+        }
+        Directive::ToggleChainState(toggle) => format!("chain:{}", toggle.chain_id),
+        Directive::AddToken(token) | Directive::UpdateToken(token) => {
+            format!("token:{}", token.token_id)
+        }
+        Directive::UpdateFee(Factor::UpdateTargetChainFactor(factor)) => {
+            format!("fee:chain:{}", factor.target_chain_id)
+        }
+        Directive::UpdateFee(Factor::UpdateFeeTokenFactor(factor)) => {
+            format!("fee:token:{}", factor.fee_token)
+        }
+    }
+}
+
+/// the latest applied directive after `seq` for the same target
+fn superseded_by(seq: Seq, directive: &Directive) -> Option<Seq> {
+    let target = directive_target(directive);
+    read_state(|s| {
+        s.directive_records
+            .range(seq + 1..)
+            .filter(|(_, record)| {
+                record.status == DirectiveStatus::Applied
+                    && directive_target(&record.directive) == target
+            })
+            .map(|(later, _)| later)
+            .last()
+    })
+}
+
+/// check the directive can be applied to the current state
+fn validate_directive(directive: &Directive) -> Result<(), String> {
+    match directive {
+        Directive::AddChain(chain) | Directive::UpdateChain(chain) => {
+            if chain.chain_id.is_empty() {
+                return Err("the chain id is empty".to_string());
+            }
+        }
+        Directive::AddToken(token) => {
+            if token.token_id.is_empty() {
+                return Err("the token id is empty".to_string());
+            }
+            if token.decimals > MAX_TOKEN_DECIMALS {
+                return Err(format!(
+                    "unsupported decimals {} of {}, the max is {}",
+                    token.decimals, token.token_id, MAX_TOKEN_DECIMALS
+                ));
+            }
+        }
+        Directive::UpdateToken(token) => {
+            if !read_state(|s| s.tokens.contains_key(&token.token_id)) {
+                return Err(format!("unknown token: {}", token.token_id));
+            }
+            if read_state(|s| s.tokens.get(&token.token_id))
+                .is_some_and(|current| current.decimals != token.decimals)
+            {
+                return Err(format!(
+                    "the decimals of {} can't be changed",
+                    token.token_id
+                ));
+            }
+        }
+        Directive::ToggleChainState(toggle) => {
+            let chain_id = read_config(|s| s.get().chain_id.to_owned());
+            if toggle.chain_id != chain_id
+                && !read_state(|s| s.counterparties.contains_key(&toggle.chain_id))
+            {
+                return Err(format!("unknown chain: {}", toggle.chain_id));
+            }
+        }
+        Directive::UpdateFee(_) => {}
+    }
+    Ok(())
+}
+
+fn execute_directive(directive: Directive) -> Result<(), String> {
+    match directive {
+        Directive::AddChain(chain) | Directive::UpdateChain(chain) => {
+            mutate_state(|s| s.add_chain(chain));
+        }
+
+        Directive::AddToken(token) => {
+            mutate_state(|s| s.add_token(token));
+        }
+
+        Directive::UpdateToken(update_token) => {
+            mutate_state(|s| s.update_token(update_token))?;
+        }
+        Directive::ToggleChainState(toggle) => {
+            mutate_state(|s| s.toggle_chain_state(toggle));
+        }
+        Directive::UpdateFee(fee) => {
+            mutate_config(|s| {
+                let mut config = s.get().to_owned();
+                config.update_fee(fee);
+                s.set(config);
+            });
+        }
+    }
+    Ok(())
+}
+
+fn set_next_directive_seq(next_seq: Seq) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.seqs.next_directive_seq = next_seq;
+        s.set(config);
+    });
 }
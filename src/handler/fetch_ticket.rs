@@ -5,10 +5,14 @@ use crate::aptos_client::{MintTokenReq, ReqType, TxReq, TxStatus};
 use crate::ck_eddsa::hash_with_sha256;
 use crate::config::{mutate_config, read_config};
 use crate::constants::TICKET_LIMIT_SIZE;
-use crate::ic_log::ERROR;
+use crate::decimals;
+use crate::hub_client::{CanisterHubClient, HubClient};
+use crate::ic_log::{DEBUG, ERROR, INFO, WARNING};
 
-use crate::state::read_state;
-use crate::types::{ChainId, ChainState, Error, Seq, Ticket};
+use crate::handler::approval;
+use crate::rate_limit::{self, Direction};
+use crate::state::{read_state, HeldMint, QuarantinedTicket, TicketDirection, TicketStage};
+use crate::types::{ChainId, ChainState, Error, Seq, Ticket, TicketId};
 use aptos_types::account_address::AccountAddress;
 use candid::Principal;
 
@@ -19,68 +23,30 @@ use crate::{
 
 use ic_canister_log::log;
 
-/// handler tickets from customs to sui
+/// handler tickets from customs to sui, the tickets are stored into `tickets_queue`
+/// by hub seq and validated by `process_tickets`
 pub async fn query_tickets() {
+    query_tickets_from(&CanisterHubClient::from_config()).await
+}
+
+pub async fn query_tickets_from(hub: &impl HubClient) {
     if read_config(|s| s.get().chain_state == ChainState::Deactive) {
         return;
     }
 
-    let (hub_principal, offset) =
-        read_config(|s| (s.get().hub_principal, s.get().seqs.next_ticket_seq));
-    match inner_query_tickets(hub_principal, offset, TICKET_LIMIT_SIZE).await {
+    let offset = read_config(|s| s.get().seqs.next_ticket_seq);
+    match hub.query_tickets(offset, TICKET_LIMIT_SIZE).await {
         Ok(tickets) => {
-            let mut next_seq = offset;
-            for (seq, ticket) in &tickets {
-                if let Err(e) = AccountAddress::from_str(&ticket.receiver) {
-                    log!(
-                        ERROR,
-                        "[fetch_ticket::query_tickets] failed to parse ticket receiver: {}, error:{}",
-                        ticket.receiver,
-                        e.to_string()
-                    );
-                    next_seq = seq + 1;
-                    continue;
-                };
-                if let Err(e) = ticket.amount.parse::<u64>() {
-                    log!(
-                        ERROR,
-                        "[fetch_ticket::query_tickets] failed to parse ticket amount: {}, Error:{}",
-                        ticket.amount,
-                        e.to_string()
-                    );
-                    next_seq = seq + 1;
+            // the tickets may be pushed by hub while querying
+            let mut next_seq = read_config(|s| s.get().seqs.next_ticket_seq);
+            for (seq, ticket) in tickets {
+                if seq < next_seq {
                     continue;
-                };
-                let fa_obj_id = read_state(|s| s.aptos_tokens.get(&ticket.token))
-                    .expect("aptos token is None")
-                    .fa_obj_id
-                    .expect("fungible asset object id is None");
-                let req_id = hash_with_sha256(
-                    &bincode::serialize(&ticket).expect("failed to serialize ticket"),
-                );
-                let mint_req = MintTokenReq {
-                    ticket_id: ticket.ticket_id.to_owned(),
-                    token_id: ticket.token.to_owned(),
-                    fa_obj: fa_obj_id,
-                    recipient: ticket.receiver.to_owned(),
-                    mint_acmount: ticket.amount.parse::<u64>().unwrap(),
-                };
-                let tx_req = TxReq {
-                    req_type: ReqType::MintToken(mint_req.to_owned()),
-                    tx_hash: None,
-                    tx_status: TxStatus::New,
-                    retry: 0,
-                };
-
-                mutate_state(|s| s.tx_queue.insert(req_id, tx_req));
-
+                }
+                store_ticket(seq, ticket);
                 next_seq = seq + 1;
             }
-            mutate_config(|s| {
-                let mut config = s.get().to_owned();
-                config.seqs.next_ticket_seq = next_seq;
-                s.set(config);
-            })
+            set_next_ticket_seq(next_seq);
         }
         Err(e) => {
             log!(
@@ -92,25 +58,236 @@ pub async fn query_tickets() {
     }
 }
 
-/// query ticket from hub
-pub async fn inner_query_tickets(
-    hub_principal: Principal,
-    offset: u64,
-    limit: u64,
-) -> Result<Vec<(Seq, Ticket)>, CallError> {
-    let resp: (Result<Vec<(Seq, Ticket)>, Error>,) = ic_cdk::api::call::call(
-        hub_principal,
-        "query_tickets",
-        (None::<Option<ChainId>>, offset, limit),
-    )
-    .await
-    .map_err(|(code, message)| CallError {
-        method: "query_tickets".to_string(),
-        reason: Reason::from_reject(code, message),
-    })?;
-    let data = resp.0.map_err(|err| CallError {
-        method: "query_tickets".to_string(),
-        reason: Reason::CanisterError(err.to_string()),
-    })?;
-    Ok(data)
+/// store the tickets pushed by hub, the duplicated ones are skipped.
+/// return the next expected seq and whether a gap is detected, the tickets after the gap
+/// are left to the polling
+pub fn receive_pushed_tickets(mut tickets: Vec<(Seq, Ticket)>) -> (Seq, bool) {
+    tickets.sort_by_key(|(seq, _)| *seq);
+    let mut next_seq = read_config(|s| s.get().seqs.next_ticket_seq);
+    let mut gap = false;
+    for (seq, ticket) in tickets {
+        if seq < next_seq {
+            continue;
+        }
+        if seq > next_seq {
+            log!(
+                WARNING,
+                "[fetch_ticket::receive_pushed_tickets] gap detected, expected seq: {}, pushed seq: {}",
+                next_seq,
+                seq
+            );
+            gap = true;
+            break;
+        }
+        store_ticket(seq, ticket);
+        next_seq += 1;
+    }
+    set_next_ticket_seq(next_seq);
+    (next_seq, gap)
+}
+
+fn store_ticket(seq: Seq, ticket: Ticket) {
+    mutate_state(|s| s.tickets_queue.insert(seq, ticket.to_owned()));
+    record_stage(&ticket.ticket_id, TicketStage::ReceivedFromHub { seq });
+}
+
+fn set_next_ticket_seq(next_seq: Seq) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.seqs.next_ticket_seq = next_seq;
+        s.set(config);
+    })
+}
+
+/// The result of validating a ticket from hub
+enum TicketCheck {
+    Valid(MintTokenReq),
+    // the ticket may become valid later, e.g. the fa obj is not created yet
+    Waiting(String),
+    Invalid(String),
+}
+
+fn check_ticket(ticket: &Ticket) -> TicketCheck {
+    if let Err(e) = AccountAddress::from_str(&ticket.receiver) {
+        return TicketCheck::Invalid(format!(
+            "invalid receiver: {}, error: {}",
+            ticket.receiver, e
+        ));
+    }
+    let amount = match ticket.amount.parse::<u128>() {
+        Ok(amount) => amount,
+        Err(e) => {
+            return TicketCheck::Invalid(format!("invalid amount: {}, error: {}", ticket.amount, e))
+        }
+    };
+    let amount = match decimals::to_aptos_amount(&ticket.token, amount) {
+        None => return TicketCheck::Invalid(format!("unknown token: {}", ticket.token)),
+        Some(Err(e)) => return TicketCheck::Invalid(e.to_string()),
+        Some(Ok(scaled)) => {
+            if scaled.dust > 0 {
+                log!(
+                    INFO,
+                    "[fetch_ticket::check_ticket] ticket {} amount {} is rounded down to {}, dust: {}",
+                    ticket.ticket_id,
+                    ticket.amount,
+                    scaled.amount,
+                    scaled.dust
+                );
+            }
+            scaled.amount
+        }
+    };
+    // the fa mint amount is u64 in move
+    let Ok(amount) = u64::try_from(amount) else {
+        return TicketCheck::Invalid(format!(
+            "amount {} exceeds the max mint amount {}",
+            amount,
+            u64::MAX
+        ));
+    };
+    let Some(aptos_token) = read_state(|s| s.aptos_tokens.get(&ticket.token)) else {
+        return TicketCheck::Invalid(format!("unknown token: {}", ticket.token));
+    };
+    let Some(fa_obj_id) = aptos_token.fa_obj_id else {
+        return TicketCheck::Waiting(format!("the fa obj of {} is not created yet", ticket.token));
+    };
+    TicketCheck::Valid(MintTokenReq {
+        ticket_id: ticket.ticket_id.to_owned(),
+        token_id: ticket.token.to_owned(),
+        fa_obj: fa_obj_id,
+        recipient: ticket.receiver.to_owned(),
+        mint_acmount: amount,
+    })
+}
+
+/// validate the tickets in `tickets_queue`, the valid ones are moved into `tx_queue`
+/// and the invalid ones into the quarantine for review
+pub fn process_tickets() {
+    let tickets: Vec<(Seq, Ticket)> = read_state(|s| s.tickets_queue.iter().collect());
+    for (seq, ticket) in tickets {
+        match check_ticket(&ticket) {
+            TicketCheck::Valid(mint_req) => {
+                mutate_state(|s| s.tickets_queue.remove(&seq));
+                // the mints waiting for the approvers count in the windows as well
+                if let Err(e) = rate_limit::check_and_record(
+                    Direction::Mint,
+                    &mint_req.token_id,
+                    &mint_req.recipient,
+                    mint_req.mint_acmount as u128,
+                ) {
+                    log!(
+                        WARNING,
+                        "[fetch_ticket::process_tickets] hold ticket {}: {}",
+                        ticket.ticket_id,
+                        e
+                    );
+                    hold_mint(seq, ticket, e.to_string());
+                    continue;
+                }
+                if let Some(threshold) = approval::requires_approval(&mint_req) {
+                    approval::add_pending_approval(ticket, mint_req, threshold);
+                    continue;
+                }
+                enqueue_mint(&ticket, mint_req);
+            }
+            TicketCheck::Waiting(reason) => {
+                log!(
+                    DEBUG,
+                    "[fetch_ticket::process_tickets] ticket {} is waiting: {}",
+                    ticket.ticket_id,
+                    reason
+                );
+            }
+            TicketCheck::Invalid(reason) => {
+                log!(
+                    ERROR,
+                    "[fetch_ticket::process_tickets] quarantine ticket {}: {}",
+                    ticket.ticket_id,
+                    reason
+                );
+                record_stage(
+                    &ticket.ticket_id,
+                    TicketStage::Quarantined {
+                        reason: reason.to_owned(),
+                    },
+                );
+                mutate_state(|s| {
+                    s.tickets_queue.remove(&seq);
+                    s.quarantined_tickets.insert(
+                        ticket.ticket_id.to_owned(),
+                        QuarantinedTicket {
+                            seq,
+                            ticket,
+                            reason,
+                            quarantined_at: crate::clock::now(),
+                        },
+                    );
+                });
+            }
+        }
+    }
+}
+
+pub fn enqueue_mint(ticket: &Ticket, mint_req: MintTokenReq) {
+    let req_id = hash_with_sha256(&bincode::serialize(ticket).expect("failed to serialize ticket"));
+    let tx_req = TxReq {
+        req_type: ReqType::MintToken(mint_req),
+        tx_hash: None,
+        tx_status: TxStatus::New,
+        retry: 0,
+    };
+    mutate_state(|s| s.tx_queue.insert(req_id.to_owned(), tx_req));
+    record_stage(&ticket.ticket_id, TicketStage::Queued { req_id });
+}
+
+fn hold_mint(seq: Seq, ticket: Ticket, reason: String) {
+    record_stage(
+        &ticket.ticket_id,
+        TicketStage::Held {
+            reason: reason.to_owned(),
+        },
+    );
+    mutate_state(|s| {
+        s.held_mints.insert(
+            ticket.ticket_id.to_owned(),
+            HeldMint {
+                seq,
+                ticket,
+                reason,
+                held_at: crate::clock::now(),
+            },
+        )
+    });
+}
+
+/// approve the held mint, it bypasses the rate limits and is moved into `tx_queue`
+pub fn release_held_mint(ticket_id: &TicketId) -> Result<(), String> {
+    let held = read_state(|s| s.held_mints.get(ticket_id))
+        .ok_or(format!("not found the held mint: {}", ticket_id))?;
+    let TicketCheck::Valid(mint_req) = check_ticket(&held.ticket) else {
+        return Err(format!(
+            "the held ticket {} is not valid anymore",
+            ticket_id
+        ));
+    };
+    mutate_state(|s| s.held_mints.remove(ticket_id));
+    record_stage(ticket_id, TicketStage::Released);
+    enqueue_mint(&held.ticket, mint_req);
+    Ok(())
+}
+
+/// move the quarantined ticket back into `tickets_queue` to validate it again
+pub fn release_quarantined_ticket(ticket_id: &TicketId) -> Result<(), String> {
+    let quarantined = mutate_state(|s| s.quarantined_tickets.remove(ticket_id))
+        .ok_or(format!("not found the quarantined ticket: {}", ticket_id))?;
+    mutate_state(|s| {
+        s.tickets_queue
+            .insert(quarantined.seq, quarantined.ticket.to_owned())
+    });
+    record_stage(ticket_id, TicketStage::Released);
+    Ok(())
+}
+
+fn record_stage(ticket_id: &TicketId, stage: TicketStage) {
+    mutate_state(|s| s.record_ticket_stage(ticket_id, TicketDirection::Inbound, stage));
 }
@@ -1,8 +1,19 @@
+use crate::aptos_client::error::AptosRouteError;
 use crate::aptos_client::rest_client::RestClient;
-use crate::aptos_client::AptosResult;
-use crate::config::read_config;
+use crate::aptos_client::{AptosResult, State};
+use crate::config::{read_config, ValidTx};
+use crate::constants::{
+    BURN_FA_EVENT, COLLECT_FA_FEE_EVENT, COLLECT_FEE_EVENT, FEE_QUOTE_SEED, FEE_TOKEN,
+};
 // use crate::state::AptosPort;
-use crate::types::{ChainState, Error, TicketType, TxAction};
+use crate::ck_eddsa::hmac_sha256;
+use crate::cycles::{self, CostScope};
+use crate::decimals;
+use crate::hub_client::{CanisterHubClient, HubClient};
+use crate::rate_limit::{self, Direction, LimitError};
+use crate::state::{TicketDirection, TicketStage};
+use crate::ticket_quota;
+use crate::types::{ChainId, ChainState, TicketId, TicketType, TokenId, TxAction};
 use crate::types::{Memo, Ticket};
 use aptos_api_types::move_types::MoveType;
 use aptos_api_types::transaction::Transaction;
@@ -24,6 +35,7 @@ use serde_with::serde_as;
 use serde_with::DisplayFromStr;
 use std::borrow::Cow;
 use std::str::FromStr;
+use subtle::ConstantTimeEq;
 
 use ic_canister_log::log;
 
@@ -48,6 +60,175 @@ pub enum GenerateTicketError {
     RedeemFeeNotSet,
     TransferFailure(String),
     UnsupportedAction(String),
+    InvalidTxHash(String),
+    InvalidAddress(String),
+    InvalidAmount(u64),
+    /// The tx is not found or still pending on the rpc providers.
+    TxNotFound(String),
+    /// The tx must be `finality_depth` versions behind the latest ledger version.
+    TxNotYetFinal {
+        tx_version: u64,
+        latest_version: u64,
+        finality_depth: u64,
+    },
+    FeeMismatch {
+        required: u128,
+        paid: u128,
+    },
+    BurnMismatch {
+        expected: String,
+        found: String,
+    },
+    /// The event is not emitted by the current aptos port.
+    WrongPort(String),
+    /// The same tx is being processed by another request.
+    DuplicateRequest(String),
+    ChainDeactivated(String),
+    /// The token is paused by the admin or the circuit breaker.
+    TokenPaused(String),
+    /// The redeem exceeds the configured rate limits.
+    RateLimited(String),
+}
+
+impl GenerateTicketError {
+    /// whether the same request may succeed if it's retried later
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            GenerateTicketError::TemporarilyUnavailable(_)
+                | GenerateTicketError::SendTicketErr(_)
+                | GenerateTicketError::TxNotFound(_)
+                | GenerateTicketError::TxNotYetFinal { .. }
+                | GenerateTicketError::DuplicateRequest(_)
+                | GenerateTicketError::TokenPaused(_)
+                | GenerateTicketError::RateLimited(_)
+        )
+    }
+
+    /// whether the caller submitted a random or forged tx hash, only these failures count
+    /// against the caller. The other failures are usually the honest mistakes or the route's
+    pub fn is_bogus_tx(&self) -> bool {
+        matches!(
+            self,
+            GenerateTicketError::TxNotFound(_) | GenerateTicketError::InvalidTxHash(_)
+        )
+    }
+}
+
+impl std::fmt::Display for GenerateTicketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerateTicketError::TemporarilyUnavailable(msg) => write!(f, "{}", msg),
+            GenerateTicketError::InvalidTxHash(e) => write!(f, "invalid tx hash: {}", e),
+            GenerateTicketError::InvalidAddress(e) => write!(f, "invalid address: {}", e),
+            GenerateTicketError::InvalidAmount(amount) => {
+                write!(
+                    f,
+                    "invalid amount: {}, amount must be > 0 and convertible to the hub token decimals",
+                    amount
+                )
+            }
+            GenerateTicketError::TxNotFound(tx_hash) => write!(f, "tx not found: {}", tx_hash),
+            GenerateTicketError::TxNotYetFinal {
+                tx_version,
+                latest_version,
+                finality_depth,
+            } => write!(
+                f,
+                "the tx at version {} is not final yet, latest version: {}, finality depth: {}",
+                tx_version, latest_version, finality_depth
+            ),
+            GenerateTicketError::FeeMismatch { required, paid } => {
+                write!(f, "fee mismatch, required: {}, paid: {}", required, paid)
+            }
+            GenerateTicketError::BurnMismatch { expected, found } => {
+                write!(f, "burn mismatch, expected: {}, found: {}", expected, found)
+            }
+            GenerateTicketError::WrongPort(event) => {
+                write!(f, "the {} is not from aptos port", event)
+            }
+            GenerateTicketError::DuplicateRequest(tx_hash) => {
+                write!(f, "duplicate request: {}", tx_hash)
+            }
+            GenerateTicketError::ChainDeactivated(chain_id) => {
+                write!(f, "chain state is deactive: {}", chain_id)
+            }
+            GenerateTicketError::TokenPaused(token_id) => {
+                write!(f, "the token {} is paused", token_id)
+            }
+            GenerateTicketError::RateLimited(msg) => write!(f, "{}", msg),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// The error returned by `generate_ticket`, it's frozen so that the existing clients
+/// keep decoding it. The new variants are only returned by `generate_ticket_v2`.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Eq)]
+pub enum LegacyGenerateTicketError {
+    TemporarilyUnavailable(String),
+    UnsupportedToken(String),
+    UnsupportedChainId(String),
+    /// The redeem account does not hold the requested token amount.
+    InsufficientFunds {
+        balance: u64,
+    },
+    /// The caller didn't approve enough funds for spending.
+    InsufficientAllowance {
+        allowance: u64,
+    },
+    SendTicketErr(String),
+    InsufficientRedeemFee {
+        required: u64,
+        provided: u64,
+    },
+    RedeemFeeNotSet,
+    TransferFailure(String),
+    UnsupportedAction(String),
+}
+
+impl From<GenerateTicketError> for LegacyGenerateTicketError {
+    fn from(error: GenerateTicketError) -> Self {
+        match error {
+            GenerateTicketError::TemporarilyUnavailable(msg) => Self::TemporarilyUnavailable(msg),
+            GenerateTicketError::UnsupportedToken(token) => Self::UnsupportedToken(token),
+            GenerateTicketError::UnsupportedChainId(chain) => Self::UnsupportedChainId(chain),
+            GenerateTicketError::InsufficientFunds { balance } => {
+                Self::InsufficientFunds { balance }
+            }
+            GenerateTicketError::InsufficientAllowance { allowance } => {
+                Self::InsufficientAllowance { allowance }
+            }
+            GenerateTicketError::SendTicketErr(e) => Self::SendTicketErr(e),
+            GenerateTicketError::InsufficientRedeemFee { required, provided } => {
+                Self::InsufficientRedeemFee { required, provided }
+            }
+            GenerateTicketError::RedeemFeeNotSet => Self::RedeemFeeNotSet,
+            GenerateTicketError::TransferFailure(e) => Self::TransferFailure(e),
+            GenerateTicketError::UnsupportedAction(action) => Self::UnsupportedAction(action),
+            // the reasons added later are carried in the message
+            other => Self::TemporarilyUnavailable(other.to_string()),
+        }
+    }
+}
+
+/// The failure returned by `generate_ticket_v2`, the error is optional so that old
+/// clients decode unknown error variants as null and still get the retryable flag.
+#[derive(CandidType, Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct GenerateTicketFailure {
+    pub error: Option<GenerateTicketError>,
+    pub retryable: bool,
+    pub message: String,
+}
+
+impl From<GenerateTicketError> for GenerateTicketFailure {
+    fn from(error: GenerateTicketError) -> Self {
+        Self {
+            retryable: error.retryable(),
+            message: error.to_string(),
+            error: Some(error),
+        }
+    }
 }
 
 #[derive(CandidType, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -57,6 +238,7 @@ pub struct GenerateTicketReq {
     pub sender: String,
     pub receiver: String,
     pub token_id: String,
+    // the burned fa amount, it's u64 in move
     pub amount: u64,
     pub action: TxAction,
     pub memo: Option<String>,
@@ -81,25 +263,61 @@ pub struct GenerateTicketOk {
 }
 
 pub async fn generate_ticket(
+    caller: Principal,
     req: GenerateTicketReq,
 ) -> Result<GenerateTicketOk, GenerateTicketError> {
     log!(DEBUG, "[generate_ticket] generate_ticket req: {:#?}", req);
+    ticket_quota::admit(&caller).map_err(GenerateTicketError::RateLimited)?;
     validate_req(&req)?;
 
-    let (hub_principal, chain_id) =
-        read_config(|s| (s.get().hub_principal, s.get().chain_id.to_owned()));
+    let chain_id = read_config(|s| s.get().chain_id.to_owned());
 
-    if !verify_tx(req.to_owned()).await? {
-        return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-            "[generate_ticket] Unable to verify the tx ({}) ",
-            req.tx_hash,
-        )));
+    let verified = match precheck_tx(&req).await {
+        Ok(()) => verify_tx(req.to_owned()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &verified {
+        // the random or forged tx hashes are counted against the caller
+        if e.is_bogus_tx() {
+            ticket_quota::record_failure(&caller, &e.to_string());
+        }
+        // release the request, so that the user can retry it later.
+        // the timeline is only created for a verified tx, the caller picks the tx hash
+        mutate_state(|s| s.gen_ticket_reqs.remove(&req.tx_hash));
+    }
+    let redeem_fee = verified?;
+    record_stage(&req.tx_hash, TicketStage::RedeemRequested);
+    if let Err(e) = rate_limit::check_and_record(
+        Direction::Redeem,
+        &req.token_id,
+        &req.receiver,
+        req.amount as u128,
+    ) {
+        mutate_state(|s| s.gen_ticket_reqs.remove(&req.tx_hash));
+        record_stage(
+            &req.tx_hash,
+            TicketStage::VerifyFailed {
+                error: e.to_string(),
+            },
+        );
+        return Err(match e {
+            LimitError::TokenPaused(token_id) => GenerateTicketError::TokenPaused(token_id),
+            e => GenerateTicketError::RateLimited(e.to_string()),
+        });
     }
-    let fee = read_config(|s| s.get().get_fee(req.target_chain_id.to_owned())).unwrap_or_default();
+    record_stage(
+        &req.tx_hash,
+        TicketStage::Verified {
+            fee_token: redeem_fee.fee_token.to_owned(),
+            fee_amount: redeem_fee.fee_amount,
+        },
+    );
     let memo = Memo {
         memo: req.memo,
-        bridge_fee: fee,
+        bridge_fee: redeem_fee.fee_amount,
+        fee_token: Some(redeem_fee.fee_token),
     };
+    let hub_amount = hub_amount(&req)?;
     // let memo = bridge_fee.add_to_memo(req.memo).unwrap_or_default();
     let memo_json = serde_json::to_string_pretty(&memo).map_err(|e| {
         GenerateTicketError::TemporarilyUnavailable(format!(
@@ -112,23 +330,26 @@ pub async fn generate_ticket(
     let ticket = Ticket {
         ticket_id: req.tx_hash.to_string(),
         ticket_type: TicketType::Normal,
-        ticket_time: ic_cdk::api::time(),
+        ticket_time: crate::clock::now(),
         src_chain: chain_id,
         dst_chain: req.target_chain_id.to_owned(),
         action: req.action.to_owned(),
         token: req.token_id.to_owned(),
-        amount: req.amount.to_string(),
+        amount: hub_amount.to_string(),
         sender: Some(req.sender.to_owned()),
         receiver: req.receiver.to_string(),
         memo: Some(memo_json.to_bytes().to_vec()),
     };
 
-    match send_ticket(hub_principal, ticket.to_owned()).await {
+    match send_ticket(&CanisterHubClient::from_config(), ticket.to_owned()).await {
         Err(err) => {
-            mutate_state(|s| {
-                s.tickets_failed_to_hub
-                    .insert(ticket.ticket_id.to_string(), ticket.to_owned());
-            });
+            mutate_state(|s| s.add_failed_ticket(ticket.to_owned(), err.to_string()));
+            record_stage(
+                &req.tx_hash,
+                TicketStage::SendToHubFailed {
+                    error: err.to_string(),
+                },
+            );
             log!(
                 WARNING,
                 "[generate_ticket] failed to send ticket: {}",
@@ -144,6 +365,7 @@ pub async fn generate_ticket(
             );
 
             mutate_state(|s| s.gen_ticket_reqs.remove(&req.tx_hash.to_owned()));
+            record_stage(&req.tx_hash, TicketStage::SentToHub);
             Ok(GenerateTicketOk {
                 ticket_id: req.tx_hash.to_string(),
             })
@@ -151,16 +373,44 @@ pub async fn generate_ticket(
     }
 }
 
+/// convert the burned fa amount to the hub token decimals, the dust is rounded down
+fn hub_amount(req: &GenerateTicketReq) -> Result<u128, GenerateTicketError> {
+    let scaled = decimals::to_hub_amount(&req.token_id, req.amount as u128)
+        .ok_or(GenerateTicketError::UnsupportedToken(
+            req.token_id.to_owned(),
+        ))?
+        .map_err(|e| {
+            log!(WARNING, "[generate_ticket] {}: {}", req.tx_hash, e);
+            GenerateTicketError::InvalidAmount(req.amount)
+        })?;
+    if scaled.dust > 0 {
+        log!(
+            DEBUG,
+            "[generate_ticket] {} amount {} is rounded down to {}, dust: {}",
+            req.tx_hash,
+            req.amount,
+            scaled.amount,
+            scaled.dust
+        );
+    }
+    Ok(scaled.amount)
+}
+
+/// record the stage of the redeem ticket
+pub fn record_stage(ticket_id: &TicketId, stage: TicketStage) {
+    mutate_state(|s| s.record_ticket_stage(ticket_id, TicketDirection::Outbound, stage));
+}
+
 pub fn validate_req(req: &GenerateTicketReq) -> Result<(), GenerateTicketError> {
     HashValue::from_str(&req.tx_hash)
-        .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
+        .map_err(|e| GenerateTicketError::InvalidTxHash(e.to_string()))?;
     AccountAddress::from_str(&req.sender)
-        .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
+        .map_err(|e| GenerateTicketError::InvalidAddress(e.to_string()))?;
 
-    if read_config(|s| s.get().chain_state == ChainState::Deactive) {
-        return Err(GenerateTicketError::TemporarilyUnavailable(
-            "chain state is deactive!".into(),
-        ));
+    let (chain_id, chain_state) =
+        read_config(|s| (s.get().chain_id.to_owned(), s.get().chain_state.to_owned()));
+    if chain_state == ChainState::Deactive {
+        return Err(GenerateTicketError::ChainDeactivated(chain_id));
     }
 
     if !read_state(|s| {
@@ -174,13 +424,11 @@ pub fn validate_req(req: &GenerateTicketReq) -> Result<(), GenerateTicketError>
     }
     {
         address::validate_account(&req.target_chain_id, &req.receiver)
-            .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
+            .map_err(|e| GenerateTicketError::InvalidAddress(e.to_string()))?;
     }
 
-    if req.amount <= 0 {
-        return Err(GenerateTicketError::TemporarilyUnavailable(
-            "amount must be > 0".into(),
-        ));
+    if req.amount == 0 {
+        return Err(GenerateTicketError::InvalidAmount(req.amount));
     }
 
     if !read_state(|s| s.tokens.contains_key(&req.token_id.to_string())) {
@@ -189,9 +437,17 @@ pub fn validate_req(req: &GenerateTicketReq) -> Result<(), GenerateTicketError>
         ));
     }
 
+    if rate_limit::is_paused(&req.token_id) {
+        return Err(GenerateTicketError::TokenPaused(req.token_id.to_owned()));
+    }
+
+    cycles::check_redeem().map_err(GenerateTicketError::TemporarilyUnavailable)?;
+
+    hub_amount(req)?;
+
     if read_state(|s| s.gen_ticket_reqs.contains_key(&req.tx_hash.to_owned())) {
-        return Err(GenerateTicketError::TemporarilyUnavailable(
-            "duplicate request!".into(),
+        return Err(GenerateTicketError::DuplicateRequest(
+            req.tx_hash.to_owned(),
         ));
     }
     mutate_state(|s| {
@@ -201,25 +457,100 @@ pub fn validate_req(req: &GenerateTicketReq) -> Result<(), GenerateTicketError>
     Ok(())
 }
 
-pub async fn verify_tx(req: GenerateTicketReq) -> Result<bool, GenerateTicketError> {
+/// check the tx exists with a single outcall to the default provider, so a random tx hash
+/// doesn't cost the outcalls to every provider of the multi-rpc verification
+pub async fn precheck_tx(req: &GenerateTicketReq) -> Result<(), GenerateTicketError> {
+    let client = RestClient::client().with_scope(CostScope::request(
+        "GenerateTicket",
+        Some(req.token_id.to_owned()),
+    ));
+    match client
+        .get_transaction_by_hash(req.tx_hash.to_owned(), &client.forward)
+        .await
+    {
+        Ok(tx) if tx.is_pending() => Err(GenerateTicketError::TemporarilyUnavailable(format!(
+            "the tx {} is pending",
+            req.tx_hash
+        ))),
+        Ok(tx) if !tx.success() => Err(GenerateTicketError::InvalidTxHash(format!(
+            "the tx {} failed: {}",
+            req.tx_hash,
+            tx.vm_status()
+        ))),
+        Ok(_) => Ok(()),
+        Err(AptosRouteError::Api(e)) if e.status_code == 404 => {
+            Err(GenerateTicketError::TxNotFound(req.tx_hash.to_owned()))
+        }
+        Err(e) => {
+            log!(
+                WARNING,
+                "[generate_ticket::precheck_tx] failed to get the tx {}: {}",
+                req.tx_hash,
+                e
+            );
+            Err(GenerateTicketError::TemporarilyUnavailable(e.to_string()))
+        }
+    }
+}
+
+/// verify the redeem tx, return the verified redeem fee if both the fee and the token are ok
+pub async fn verify_tx(req: GenerateTicketReq) -> Result<RedeemFee, GenerateTicketError> {
     let multi_rpc_config = read_config(|s| s.get().multi_rpc_config.to_owned());
     multi_rpc_config
         .check_config_valid()
         .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
-    let client = RestClient::client();
-    let events = query_tx_from_multi_rpc(
+    let client = RestClient::client().with_scope(CostScope::request(
+        "GenerateTicket",
+        Some(req.token_id.to_owned()),
+    ));
+    let responses = query_tx_from_multi_rpc(
         &client,
         req.tx_hash.to_owned(),
         multi_rpc_config.rpc_list.to_owned(),
     )
     .await;
 
-    let events = multi_rpc_config
-        .valid_and_get_result(&events)
-        .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
+    let ValidTx {
+        version: tx_version,
+        timestamp_usecs,
+        events,
+    } = multi_rpc_config
+        .valid_and_get_result(&responses)
+        .map_err(|e| {
+            if responses.iter().all(is_tx_not_found) {
+                GenerateTicketError::TxNotFound(req.tx_hash.to_owned())
+            } else {
+                GenerateTicketError::TemporarilyUnavailable(e.to_string())
+            }
+        })?;
 
-    let mut collect_fee_ok = false;
-    let mut burn_token_ok = false;
+    // the ledger must move at least `finality_depth` versions past the redeem tx
+    let finality_depth = read_config(|s| s.get().finality_depth());
+    if finality_depth > 0 {
+        let states =
+            query_ledger_from_multi_rpc(&client, multi_rpc_config.rpc_list.to_owned()).await;
+        let latest_version = multi_rpc_config
+            .valid_and_get_ledger_version(&states)
+            .map_err(|e| GenerateTicketError::TemporarilyUnavailable(e.to_string()))?;
+        log!(
+            DEBUG,
+            "[verify_tx] tx version: {}, latest version: {}, finality depth: {}",
+            tx_version,
+            latest_version,
+            finality_depth
+        );
+        if tx_version.saturating_add(finality_depth) > latest_version {
+            return Err(GenerateTicketError::TxNotYetFinal {
+                tx_version,
+                latest_version,
+                finality_depth,
+            });
+        }
+    }
+
+    let mut paid_fee: Option<PaidFee> = None;
+    let mut redeem_memo: Option<RedeemMemo> = None;
+    let mut burn_mismatch: Option<GenerateTicketError> = None;
 
     let current_package =
         read_config(|c| c.get().current_port_package.to_owned()).expect("port package is none!");
@@ -228,110 +559,239 @@ pub async fn verify_tx(req: GenerateTicketReq) -> Result<bool, GenerateTicketErr
         read_state(|s| s.aptos_ports.get(&current_package)).expect("port info is none!");
 
     for event in &events {
-        if let Ok(collect_fee_event) =
-            serde_json::from_value::<CollectFeeEvent>(event.data.to_owned())
+        let MoveType::Struct(type_info) = &event.typ else {
+            log!(DEBUG, "[verify_tx] Unknown Parsed Value: {:#?}", event);
+            continue;
+        };
+        log!(DEBUG, "[verify_tx] type_info: {:?}", type_info);
+        let event_name = type_info.name.to_string();
+        if ![COLLECT_FEE_EVENT, COLLECT_FA_FEE_EVENT, BURN_FA_EVENT].contains(&event_name.as_str())
         {
-            log!(
-                DEBUG,
-                "[verify_tx] collect_fee_event: {:?}",
-                collect_fee_event
-            );
-            if let MoveType::Struct(type_info) = &event.typ {
-                log!(DEBUG, "[verify_tx] type_info: {:?}", type_info);
-                if !(type_info.address.to_string().eq(&port_info.package)
-                    && type_info.module.to_string().eq(&port_info.module)
-                    && type_info
-                        .name
-                        .to_string()
-                        .eq(&"CollectFeeEvent".to_string()))
-                {
-                    return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-                        "[verify_tx] the collect fee event is not from aptos port! ",
-                    )));
-                }
-            } else {
-                return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-                    "[verify_tx] move type is not struct",
-                )));
-            }
-
-            let fee = read_config(|s| s.get().get_fee(req.target_chain_id.to_owned())).ok_or(
-                GenerateTicketError::TemporarilyUnavailable(format!(
-                    "[verify_tx] No found fee for {}",
-                    req.target_chain_id
-                )),
-            )?;
-
-            log!(DEBUG, "[verify_tx] fee from route: {}", fee);
-
-            let collect_amount = collect_fee_event.fee_amount as u128;
-
-            let fee_account = read_config(|s| s.get().fee_account.to_string());
-
-            log!(DEBUG, "[verify_tx] fee_account from route: {}", fee_account);
+            log!(DEBUG, "[verify_tx] Unknown Parsed Value: {:#?}", event);
+            continue;
+        }
+        if !(type_info.address.to_string().eq(&port_info.package)
+            && type_info.module.to_string().eq(&port_info.module))
+        {
+            return Err(GenerateTicketError::WrongPort(event_name));
+        }
 
-            if !(collect_fee_event.sender.to_string().eq(&req.sender)
-                && collect_fee_event.recipient.to_string().eq(&fee_account)
-                && collect_amount == fee)
-            {
-                return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-                    "[verify_tx] Unable to verify the collect fee info",
-                )));
+        match event_name.as_str() {
+            COLLECT_FEE_EVENT => {
+                let collect_fee_event = serde_json::from_value::<CollectFeeEvent>(
+                    event.data.to_owned(),
+                )
+                .map_err(|e| {
+                    GenerateTicketError::TemporarilyUnavailable(format!(
+                        "[verify_tx] failed to parse collect fee event: {}",
+                        e
+                    ))
+                })?;
+                log!(
+                    DEBUG,
+                    "[verify_tx] collect_fee_event: {:?}",
+                    collect_fee_event
+                );
+                paid_fee = Some(PaidFee {
+                    fee_token: FEE_TOKEN.to_string(),
+                    sender: collect_fee_event.sender,
+                    recipient: collect_fee_event.recipient,
+                    fee_amount: collect_fee_event.fee_amount,
+                });
             }
-            collect_fee_ok = true
-        } else if let Ok(burn_event) = serde_json::from_value::<BurnFAEvent>(event.data.to_owned())
-        {
-            log!(DEBUG, "[verify_tx] burn_event: {:?}", burn_event);
-            if let MoveType::Struct(type_info) = &event.typ {
-                log!(DEBUG, "[verify_tx] type_info: {:?}", type_info);
-                if !(type_info.address.to_string().eq(&port_info.package)
-                    && type_info.module.to_string().eq(&port_info.module)
-                    && type_info.name.to_string().eq(&"BurnFAEvent".to_string()))
-                {
-                    return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-                        "[verify_tx] the burn fa event is not from aptos port! ",
-                    )));
-                }
-            } else {
-                return Err(GenerateTicketError::TemporarilyUnavailable(format!(
-                    "[verify_tx] move type is not struct",
-                )));
+            COLLECT_FA_FEE_EVENT => {
+                let collect_fee_event = serde_json::from_value::<CollectFAFeeEvent>(
+                    event.data.to_owned(),
+                )
+                .map_err(|e| {
+                    GenerateTicketError::TemporarilyUnavailable(format!(
+                        "[verify_tx] failed to parse collect fa fee event: {}",
+                        e
+                    ))
+                })?;
+                log!(
+                    DEBUG,
+                    "[verify_tx] collect_fa_fee_event: {:?}",
+                    collect_fee_event
+                );
+                let fee_token =
+                    read_config(|s| s.get().fee_token_of_fa_obj(&collect_fee_event.fa_obj)).ok_or(
+                        GenerateTicketError::TemporarilyUnavailable(format!(
+                            "[verify_tx] the fa obj {} is not a fee asset",
+                            collect_fee_event.fa_obj
+                        )),
+                    )?;
+                paid_fee = Some(PaidFee {
+                    fee_token,
+                    sender: collect_fee_event.sender,
+                    recipient: collect_fee_event.recipient,
+                    fee_amount: collect_fee_event.fee_amount,
+                });
             }
+            _ => {
+                let burn_event = serde_json::from_value::<BurnFAEvent>(event.data.to_owned())
+                    .map_err(|e| {
+                        GenerateTicketError::TemporarilyUnavailable(format!(
+                            "[verify_tx] failed to parse burn fa event: {}",
+                            e
+                        ))
+                    })?;
+                log!(DEBUG, "[verify_tx] burn_event: {:?}", burn_event);
 
-            let fa_obj_id = read_state(|s| s.aptos_tokens.get(&req.token_id))
-                .expect("Not found aptos token")
-                .fa_obj_id
-                .expect("fa obj id is None");
+                let fa_obj_id = read_state(|s| s.aptos_tokens.get(&req.token_id))
+                    .expect("Not found aptos token")
+                    .fa_obj_id
+                    .expect("fa obj id is None");
 
-            if burn_event.sender.to_string().eq(&req.sender)
-                && burn_event.fa_obj.eq(&fa_obj_id)
-                && burn_event.amount == req.amount
-            {
-                burn_token_ok = true;
+                let expected = format!("{}:{}:{}", req.sender, fa_obj_id, req.amount);
+                let found = format!(
+                    "{}:{}:{}",
+                    burn_event.sender, burn_event.fa_obj, burn_event.amount
+                );
+                if !expected.eq(&found) {
+                    burn_mismatch = Some(GenerateTicketError::BurnMismatch { expected, found });
+                    continue;
+                }
+
+                // the target chain and receiver must be bound on chain by the burn memo
+                let expected_memo = RedeemMemo::new(
+                    req.target_chain_id.to_owned(),
+                    req.receiver.to_owned(),
+                    req.memo.to_owned(),
+                );
+                let memo = burn_event
+                    .memo
+                    .as_ref()
+                    .and_then(|memo| RedeemMemo::decode(memo).ok())
+                    .filter(|memo| memo.matches(&req))
+                    .ok_or(GenerateTicketError::BurnMismatch {
+                        expected: expected_memo.encode(),
+                        found: burn_event.memo.to_owned().unwrap_or_default(),
+                    })?;
+                log!(DEBUG, "[verify_tx] redeem_memo: {:?}", memo);
+                redeem_memo = Some(memo);
             }
-        } else {
-            log!(DEBUG, "[verify_tx] Unknown Parsed Value: {:#?}", event);
         }
     }
     log!(
         DEBUG,
-        "[verify_tx] verify tx ,collect_fee :{},burn_token:{}",
-        collect_fee_ok,
-        burn_token_ok,
+        "[verify_tx] verify tx ,paid_fee :{:?},redeem_memo:{:?}",
+        paid_fee,
+        redeem_memo,
+    );
+    let Some(redeem_memo) = redeem_memo else {
+        return Err(burn_mismatch.unwrap_or(GenerateTicketError::BurnMismatch {
+            expected: format!("{}:{}", req.sender, req.amount),
+            found: "none".to_string(),
+        }));
+    };
+    let Some(paid_fee) = paid_fee else {
+        let required = read_config(|s| s.get().get_fee(req.target_chain_id.to_owned()));
+        return Err(GenerateTicketError::FeeMismatch {
+            required: required.unwrap_or_default(),
+            paid: 0,
+        });
+    };
+    // aptos timestamp is in microseconds
+    let tx_time = timestamp_usecs.saturating_mul(1_000);
+    verify_fee(&req, paid_fee, redeem_memo.fee_quote.as_ref(), tx_time)
+}
+
+/// the tx is unknown or still pending on the rpc provider
+fn is_tx_not_found(response: &AptosResult<Transaction>) -> bool {
+    match response {
+        Ok(Transaction::PendingTransaction(_)) => true,
+        Err(AptosRouteError::Api(e)) => e.status_code == 404,
+        _ => false,
+    }
+}
+
+/// The fee collected by the port in the redeem tx
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PaidFee {
+    fee_token: TokenId,
+    sender: String,
+    recipient: String,
+    fee_amount: u64,
+}
+
+/// check the collected fee is paid by the sender to the fee account of the fee token,
+/// the paid amount must reach the current fee, or the quoted fee if the quote was still valid
+/// when the redeem tx was committed
+fn verify_fee(
+    req: &GenerateTicketReq,
+    paid_fee: PaidFee,
+    fee_quote: Option<&FeeQuote>,
+    tx_time: u64,
+) -> Result<RedeemFee, GenerateTicketError> {
+    let (fee, fee_account) = read_config(|s| {
+        (
+            s.get()
+                .get_fee_in(req.target_chain_id.to_owned(), &paid_fee.fee_token),
+            s.get().fee_account_of(&paid_fee.fee_token),
+        )
+    });
+    let fee = fee.ok_or(GenerateTicketError::TemporarilyUnavailable(format!(
+        "[verify_tx] No found {} fee for {}",
+        paid_fee.fee_token, req.target_chain_id
+    )))?;
+    let fee_account = fee_account.ok_or(GenerateTicketError::TemporarilyUnavailable(format!(
+        "[verify_tx] {} is not a fee asset",
+        paid_fee.fee_token
+    )))?;
+    log!(
+        DEBUG,
+        "[verify_tx] fee from route: {} {}, fee_account: {}",
+        fee,
+        paid_fee.fee_token,
+        fee_account
     );
-    Ok(collect_fee_ok && burn_token_ok)
+
+    // the fee paid by others or to others is not counted
+    if !(paid_fee.sender.eq(&req.sender) && paid_fee.recipient.eq(&fee_account)) {
+        return Err(GenerateTicketError::FeeMismatch {
+            required: fee,
+            paid: 0,
+        });
+    }
+
+    let paid_amount = paid_fee.fee_amount as u128;
+    let quoted = fee_quote.is_some_and(|quote| {
+        let valid = fee_quote_key().is_some_and(|key| quote.is_signed_by(&key))
+            && quote.target_chain_id.eq(&req.target_chain_id)
+            && quote.fee_token.eq(&paid_fee.fee_token)
+            && tx_time <= quote.expires_at;
+        log!(
+            DEBUG,
+            "[verify_tx] fee quote: {:?}, valid: {}, tx time: {}",
+            quote,
+            valid,
+            tx_time
+        );
+        valid && paid_amount >= quote.fee_amount
+    });
+    if !(quoted || paid_amount >= fee) {
+        log!(
+            DEBUG,
+            "[verify_tx] the paid fee {} {} is less than the required fee {}",
+            paid_amount,
+            paid_fee.fee_token,
+            fee
+        );
+        return Err(GenerateTicketError::FeeMismatch {
+            required: fee,
+            paid: paid_amount,
+        });
+    }
+    Ok(RedeemFee {
+        fee_token: paid_fee.fee_token,
+        fee_amount: paid_amount,
+    })
 }
 
 /// send ticket to hub
-pub async fn send_ticket(hub_principal: Principal, ticket: Ticket) -> Result<(), CallError> {
-    let resp: (Result<(), Error>,) =
-        ic_cdk::api::call::call(hub_principal, "send_ticket", (ticket,))
-            .await
-            .map_err(|(code, message)| CallError {
-                method: "send_ticket".to_string(),
-                reason: Reason::from_reject(code, message),
-            })?;
-    let data = resp.0.map_err(|err| CallError {
+pub async fn send_ticket(hub: &impl HubClient, ticket: Ticket) -> Result<(), CallError> {
+    let data = hub.send_ticket(ticket).await?.map_err(|err| CallError {
         method: "send_ticket".to_string(),
         reason: Reason::CanisterError(err.to_string()),
     })?;
@@ -354,6 +814,17 @@ pub async fn query_tx_from_multi_rpc(
     futures::future::join_all(fut).await
 }
 
+pub async fn query_ledger_from_multi_rpc(
+    client: &RestClient,
+    rpc_url_vec: Vec<String>,
+) -> Vec<AptosResult<State>> {
+    let mut fut = Vec::with_capacity(rpc_url_vec.len());
+    for rpc_url in rpc_url_vec {
+        fut.push(async { client.get_ledger_state(&Some(rpc_url)).await });
+    }
+    futures::future::join_all(fut).await
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 // #[serde(rename_all = "camelCase")]
@@ -364,6 +835,23 @@ pub struct CollectFeeEvent {
     pub fee_amount: u64,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CollectFAFeeEvent {
+    pub sender: String,
+    pub recipient: String,
+    pub fa_obj: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub fee_amount: u64,
+}
+
+/// The fee verified from the redeem tx
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RedeemFee {
+    pub fee_token: TokenId,
+    pub fee_amount: u128,
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct BurnFAEvent {
@@ -371,6 +859,157 @@ pub struct BurnFAEvent {
     pub fa_obj: String,
     #[serde_as(as = "DisplayFromStr")]
     pub amount: u64,
+    #[serde(default, deserialize_with = "deserialize_move_option")]
+    pub memo: Option<String>,
+}
+
+/// the move `Option<String>` is rendered as `{"vec":[...]}` by the aptos rest api
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoveOptionString {
+    Vec { vec: Vec<String> },
+    Plain(String),
+}
+
+fn deserialize_move_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<MoveOptionString>::deserialize(deserializer)?;
+    Ok(match value {
+        Some(MoveOptionString::Vec { vec }) => vec.into_iter().next(),
+        Some(MoveOptionString::Plain(memo)) => Some(memo),
+        None => None,
+    })
+}
+
+pub const REDEEM_MEMO_VERSION: u8 = 1;
+
+/// The memo attached to `BurnTokenReq.memo` and emitted by the port `BurnFAEvent`,
+/// it binds the redeem target chain and receiver to the burn tx.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RedeemMemo {
+    pub version: u8,
+    pub target_chain_id: String,
+    pub receiver: String,
+    pub memo: Option<String>,
+    // the fee quote the user paid against, optional
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_quote: Option<FeeQuote>,
+}
+
+impl RedeemMemo {
+    pub fn new(target_chain_id: String, receiver: String, memo: Option<String>) -> Self {
+        Self {
+            version: REDEEM_MEMO_VERSION,
+            target_chain_id,
+            receiver,
+            memo,
+            fee_quote: None,
+        }
+    }
+
+    pub fn with_fee_quote(mut self, fee_quote: Option<FeeQuote>) -> Self {
+        self.fee_quote = fee_quote;
+        self
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize RedeemMemo")
+    }
+
+    pub fn decode(memo: &str) -> Result<Self, String> {
+        let redeem_memo = serde_json::from_str::<RedeemMemo>(memo).map_err(|e| e.to_string())?;
+        if redeem_memo.version != REDEEM_MEMO_VERSION {
+            return Err(format!(
+                "unsupported redeem memo version: {}",
+                redeem_memo.version
+            ));
+        }
+        Ok(redeem_memo)
+    }
+
+    pub fn matches(&self, req: &GenerateTicketReq) -> bool {
+        self.target_chain_id.eq(&req.target_chain_id)
+            && self.receiver.eq(&req.receiver)
+            && self.memo.eq(&req.memo)
+    }
+}
+
+// the key of the fee quotes is kept in the seeds, so the quotes survive the upgrades.
+// it's None until the seed is drawn after the install
+fn fee_quote_key() -> Option<[u8; 64]> {
+    read_state(|s| s.seeds.get(&FEE_QUOTE_SEED.to_string()))
+}
+
+/// A redeem fee quote, the quote id is the HMAC of the quote so that the route
+/// can verify it later without storing it.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FeeQuote {
+    pub quote_id: String,
+    pub target_chain_id: ChainId,
+    pub fee_token: TokenId,
+    pub fee_amount: u128,
+    // the quote is valid until this time, in nanoseconds
+    pub expires_at: u64,
+}
+
+impl FeeQuote {
+    pub fn new(
+        target_chain_id: ChainId,
+        fee_token: TokenId,
+        fee_amount: u128,
+        expires_at: u64,
+        key: &[u8],
+    ) -> Self {
+        let mut quote = Self {
+            quote_id: String::default(),
+            target_chain_id,
+            fee_token,
+            fee_amount,
+            expires_at,
+        };
+        quote.quote_id = quote.digest(key);
+        quote
+    }
+
+    fn digest(&self, key: &[u8]) -> String {
+        let content = bincode::serialize(&(
+            &self.target_chain_id,
+            &self.fee_token,
+            self.fee_amount,
+            self.expires_at,
+        ))
+        .expect("failed to serialize FeeQuote");
+        hex::encode(hmac_sha256(key, &content))
+    }
+
+    pub fn is_signed_by(&self, key: &[u8]) -> bool {
+        self.quote_id
+            .as_bytes()
+            .ct_eq(self.digest(key).as_bytes())
+            .into()
+    }
+}
+
+/// quote the current redeem fee, None if the fee or the quote key is not available
+pub fn quote_fee(target_chain_id: ChainId, fee_token: Option<TokenId>) -> Option<FeeQuote> {
+    let key = fee_quote_key()?;
+    let (fee_token, fee_amount, fee_quote_ttl) = read_config(|s| {
+        let config = s.get();
+        let fee_token = fee_token.unwrap_or(config.default_fee_token());
+        let fee_amount = config.get_fee_in(target_chain_id.to_owned(), &fee_token);
+        (fee_token, fee_amount, config.fee_quote_ttl)
+    });
+    let expires_at =
+        crate::clock::now() + std::time::Duration::from_secs(fee_quote_ttl).as_nanos() as u64;
+    Some(FeeQuote::new(
+        target_chain_id,
+        fee_token,
+        fee_amount?,
+        expires_at,
+        &key,
+    ))
 }
 
 #[cfg(test)]
@@ -382,7 +1021,10 @@ mod test {
     use candid::Principal;
     use omnity_types::address;
 
-    use crate::handler::gen_ticket::{BurnFAEvent, CollectFeeEvent};
+    use crate::handler::gen_ticket::{
+        BurnFAEvent, CollectFAFeeEvent, CollectFeeEvent, FeeQuote, GenerateTicketError,
+        GenerateTicketFailure, LegacyGenerateTicketError, RedeemMemo,
+    };
 
     #[test]
     fn test_management_canister() {
@@ -767,6 +1409,111 @@ mod test {
         }
     }
 
+    #[test]
+    fn redeem_memo_roundtrip() {
+        let memo = RedeemMemo::new(
+            "sICP".to_string(),
+            "ytoqu-ey42w-sb2ul-m7xgn-oc7xo-i4btp-kuxjc-b6pt4-dwdzu-kfqs4-nae".to_string(),
+            Some("some memo".to_string()),
+        );
+        let encoded = memo.encode();
+        assert_eq!(
+            encoded,
+            r#"{"version":1,"target_chain_id":"sICP","receiver":"ytoqu-ey42w-sb2ul-m7xgn-oc7xo-i4btp-kuxjc-b6pt4-dwdzu-kfqs4-nae","memo":"some memo"}"#
+        );
+        assert_eq!(RedeemMemo::decode(&encoded).unwrap(), memo);
+
+        let unknown_version = encoded.replace("\"version\":1", "\"version\":9");
+        assert!(RedeemMemo::decode(&unknown_version).is_err());
+        assert!(RedeemMemo::decode("some memo").is_err());
+    }
+
+    #[test]
+    fn generate_ticket_error_reasons() {
+        let not_final = GenerateTicketError::TxNotYetFinal {
+            tx_version: 1,
+            latest_version: 50,
+            finality_depth: 100,
+        };
+        assert!(not_final.retryable());
+        assert_eq!(
+            LegacyGenerateTicketError::from(not_final.to_owned()),
+            LegacyGenerateTicketError::TemporarilyUnavailable(not_final.to_string())
+        );
+
+        let fee_mismatch = GenerateTicketError::FeeMismatch {
+            required: 20_000,
+            paid: 10_000,
+        };
+        let failure = GenerateTicketFailure::from(fee_mismatch.to_owned());
+        assert!(!failure.retryable);
+        // an underpaid fee is an honest mistake, it isn't counted against the caller
+        assert!(!fee_mismatch.is_bogus_tx());
+        assert!(GenerateTicketError::TxNotFound("0x1".to_string()).is_bogus_tx());
+        assert_eq!(failure.error, Some(fee_mismatch));
+
+        assert_eq!(
+            LegacyGenerateTicketError::from(GenerateTicketError::UnsupportedToken(
+                "sICP".to_string()
+            )),
+            LegacyGenerateTicketError::UnsupportedToken("sICP".to_string())
+        );
+    }
+
+    #[test]
+    fn fee_quote_signature() {
+        let seed = [7u8; 64];
+        let quote = FeeQuote::new("sICP".to_string(), "APT".to_string(), 20_000, 1_000, &seed);
+        assert!(quote.is_signed_by(&seed));
+        assert!(!quote.is_signed_by(&[8u8; 64]));
+
+        let mut tampered = quote.to_owned();
+        tampered.fee_amount = 1;
+        assert!(!tampered.is_signed_by(&seed));
+
+        let memo = RedeemMemo::new("sICP".to_string(), "receiver".to_string(), None)
+            .with_fee_quote(Some(quote.to_owned()));
+        let decoded = RedeemMemo::decode(&memo.encode()).unwrap();
+        assert_eq!(decoded.fee_quote, Some(quote));
+    }
+
+    #[test]
+    fn parse_burn_event_with_memo() {
+        let memo = RedeemMemo::new("sICP".to_string(), "receiver".to_string(), None);
+        let data = serde_json::json!({
+            "amount": "222222",
+            "fa_obj": "0x19b1bb5f38ed05902e344d83c2ba06e5133a20b4e3a28690c2fb1c90784227f1",
+            "sender": "0xeec548b9b358e769e74a7a4ba5c034fbb0c37a9872a4c3d47c8d0cacb2b3bd4f",
+            "memo": { "vec": [memo.encode()] }
+        });
+        let burn_event = serde_json::from_value::<BurnFAEvent>(data).unwrap();
+        assert_eq!(burn_event.memo, Some(memo.encode()));
+
+        let data = serde_json::json!({
+            "amount": "222222",
+            "fa_obj": "0x19b1bb5f38ed05902e344d83c2ba06e5133a20b4e3a28690c2fb1c90784227f1",
+            "sender": "0xeec548b9b358e769e74a7a4ba5c034fbb0c37a9872a4c3d47c8d0cacb2b3bd4f",
+        });
+        let burn_event = serde_json::from_value::<BurnFAEvent>(data).unwrap();
+        assert_eq!(burn_event.memo, None);
+    }
+
+    #[test]
+    fn parse_collect_fa_fee_event() {
+        let data = serde_json::json!({
+            "fa_obj": "0x19b1bb5f38ed05902e344d83c2ba06e5133a20b4e3a28690c2fb1c90784227f1",
+            "fee_amount": "20000",
+            "recipient": "0xc8d919cef59bf118454313315950f8a10ddb49f799fcceac7479721891051e45",
+            "sender": "0xeec548b9b358e769e74a7a4ba5c034fbb0c37a9872a4c3d47c8d0cacb2b3bd4f"
+        });
+        let collect_fee_event = serde_json::from_value::<CollectFAFeeEvent>(data).unwrap();
+        assert_eq!(collect_fee_event.fee_amount, 20000);
+        assert_eq!(
+            collect_fee_event.fa_obj,
+            "0x19b1bb5f38ed05902e344d83c2ba06e5133a20b4e3a28690c2fb1c90784227f1"
+        );
+    }
+
     #[test]
     fn memo_with_fee() {
         use crate::types::Memo;
@@ -776,6 +1523,7 @@ mod test {
         let memo_with_fee = Memo {
             memo,
             bridge_fee: fee,
+            fee_token: None,
         };
 
         let memo = serde_json::to_string_pretty(&memo_with_fee).unwrap();
@@ -787,6 +1535,7 @@ mod test {
         let memo_with_fee = Memo {
             memo,
             bridge_fee: fee,
+            fee_token: None,
         };
         let memo = serde_json::to_string_pretty(&memo_with_fee).unwrap();
 
@@ -1,11 +1,15 @@
 use crate::aptos_client::rest_client::RestClient;
 use crate::aptos_client::{tx_builder, LocalAccount, ReqType, TxReq, TxStatus};
 
-use crate::call_error::{CallError, Reason};
 use crate::config::read_config;
 use crate::constants::{GET_FA_OBJ, RETRY_NUM, TAKE_SIZE};
+use crate::cycles::CostScope;
+use crate::guard::TaskType;
+use crate::handler::update_token;
+use crate::hub_client::{CanisterHubClient, HubClient};
 use crate::ic_log::{DEBUG, ERROR, WARNING};
-use crate::state::{mutate_state, read_state};
+use crate::route_pool;
+use crate::state::{mutate_state, read_state, TicketDirection, TicketStage};
 use ic_canister_log::log;
 
 pub async fn handle_tx_req() {
@@ -53,17 +57,28 @@ pub async fn handle_tx_req() {
 pub async fn build_and_send_tx(req_id: &String, req: &mut TxReq) {
     log!(DEBUG, "[handler_tx::build_and_send_tx] req ({:?})", req,);
 
-    match LocalAccount::local_account().await {
+    // the mint txs are signed by the route pool accounts if any is available
+    let pool_index = match &req.req_type {
+        ReqType::MintToken(_) => route_pool::assign(req_id),
+        _ => None,
+    };
+    let scope = CostScope::task(TaskType::HandleTx).with_req(&req.req_type);
+    let local_account = match pool_index {
+        Some(index) => LocalAccount::pool_account(index, scope.to_owned()).await,
+        None => LocalAccount::local_account(scope.to_owned()).await,
+    };
+    match local_account.map(|local_account| local_account.with_req_id(req_id)) {
         Ok(mut local_account) => {
             if let Ok(signed_txn) =
                 tx_builder::get_signed_tx(&mut local_account, &req.req_type, None).await
             {
+                record_stage(req, TicketStage::Signed);
                 log!(
                     DEBUG,
                     "[handler_tx::build_and_send_tx] SignedTransaction: {:#?} ",
                     signed_txn
                 );
-                let client = RestClient::client();
+                let client = RestClient::client().with_scope(scope);
                 match client.summit_tx(&signed_txn).await {
                     Ok(pending_tx) => {
                         log!(
@@ -71,12 +86,21 @@ pub async fn build_and_send_tx(req_id: &String, req: &mut TxReq) {
                             "[handler_tx::build_and_send_tx] summit_tx ret: {:?}  ",
                             pending_tx
                         );
+                        if let Some(index) = pool_index {
+                            route_pool::record_submitted(index, signed_txn.sequence_number());
+                        }
                         //update req
                         req.tx_status = TxStatus::Pending;
                         req.tx_hash = Some(pending_tx.hash.to_string());
                         mutate_state(|s| {
                             s.tx_queue.insert(req_id.to_owned(), req.to_owned());
                         });
+                        record_stage(
+                            req,
+                            TicketStage::Submitted {
+                                tx_hash: pending_tx.hash.to_string(),
+                            },
+                        );
                     }
                     Err(tx_error) => {
                         //reduce error log
@@ -95,6 +119,7 @@ pub async fn build_and_send_tx(req_id: &String, req: &mut TxReq) {
                         }
 
                         // update req status to failed and retry later
+                        route_pool::release(req_id);
                         req.retry += 1;
                         req.tx_status = TxStatus::TxFailed {
                             e: tx_error.to_string(),
@@ -103,10 +128,17 @@ pub async fn build_and_send_tx(req_id: &String, req: &mut TxReq) {
                         mutate_state(|s| {
                             s.tx_queue.insert(req_id.to_owned(), req.to_owned());
                         });
+                        record_stage(
+                            req,
+                            TicketStage::TxFailed {
+                                error: tx_error.to_string(),
+                            },
+                        );
                     }
                 }
             } else {
                 log!(DEBUG, "[handler_tx::build_and_send_tx] get_signed_tx error",);
+                route_pool::release(req_id);
             }
         }
         Err(e) => {
@@ -115,13 +147,15 @@ pub async fn build_and_send_tx(req_id: &String, req: &mut TxReq) {
                 "[handler_tx::build_and_send_tx] get local_account error: {:?} ",
                 e
             );
+            route_pool::release(req_id);
         }
     }
 }
 
 pub async fn update_tx_status(req_id: &String, req: &mut TxReq) {
     // query signature status
-    let client = RestClient::client();
+    let client = RestClient::client()
+        .with_scope(CostScope::task(TaskType::HandleTx).with_req(&req.req_type));
     let tx = client
         .get_transaction_by_hash(
             req.tx_hash.to_owned().expect("tx hash is None!"),
@@ -149,17 +183,34 @@ pub async fn update_tx_status(req_id: &String, req: &mut TxReq) {
             if tx.success() {
                 // req finalized
                 req.tx_status = TxStatus::Finalized;
+                route_pool::release(req_id);
 
                 mutate_state(|s| {
                     s.tx_queue.insert(req_id.to_owned(), req.to_owned());
                 });
+                let tx_hash = req.tx_hash.to_owned().unwrap_or_default();
+                record_stage(
+                    req,
+                    TicketStage::Committed {
+                        tx_hash: tx_hash.to_owned(),
+                        version: tx.version(),
+                    },
+                );
 
                 if let ReqType::CreateToken(create_token_req) = &req.req_type {
                     update_fa_obj(&client, &create_token_req.token_id).await;
                 }
+                if let ReqType::AddMinter(minter) = &req.req_type {
+                    route_pool::on_minter_added(minter);
+                }
                 // update tx hash to hub
                 if let ReqType::MintToken(mint_token_req) = &req.req_type {
-                    update_tx_to_hub(&mint_token_req.ticket_id, &mint_token_req.token_id).await;
+                    update_tx_to_hub(
+                        &CanisterHubClient::from_config(),
+                        &mint_token_req.ticket_id,
+                        &tx_hash,
+                    )
+                    .await;
                 }
             } else {
                 //reduce error log
@@ -178,18 +229,36 @@ pub async fn update_tx_status(req_id: &String, req: &mut TxReq) {
                         tx.vm_status(),
                     );
                 }
-                // update status and retry
+                // update status and retry, it may be signed by the other route account
+                route_pool::release(req_id);
                 req.retry += 1;
                 req.tx_status = TxStatus::TxFailed { e: tx.vm_status() };
                 req.tx_hash = None;
                 mutate_state(|s| {
                     s.tx_queue.insert(req_id.to_owned(), req.to_owned());
                 });
+                record_stage(
+                    req,
+                    TicketStage::TxFailed {
+                        error: tx.vm_status(),
+                    },
+                );
             }
         }
     }
 }
 
+/// record the stage of the ticket the req is created for,
+/// the metadata update req is tracked in `update_token_queue` instead
+fn record_stage(req: &TxReq, stage: TicketStage) {
+    if let ReqType::UpdateMeta(update_meta_req) = &req.req_type {
+        update_token::record_tx_status(update_meta_req, req);
+    }
+    if let Some(ticket_id) = req.req_type.ticket_id() {
+        mutate_state(|s| s.record_ticket_stage(&ticket_id, TicketDirection::Inbound, stage));
+    }
+}
+
 pub async fn update_fa_obj(client: &RestClient, token_id: &String) {
     // get fa obj id from port
     let current_package =
@@ -226,24 +295,24 @@ pub async fn update_fa_obj(client: &RestClient, token_id: &String) {
     }
 }
 
-pub async fn update_tx_to_hub(ticket_id: &String, tx_hash: &String) {
-    let hub_principal = read_config(|s| s.get().hub_principal);
+pub async fn update_tx_to_hub(hub: &impl HubClient, ticket_id: &String, tx_hash: &String) {
     let tx_hash = tx_hash.to_owned();
-    match ic_cdk::api::call::call(
-        hub_principal,
-        "update_tx_hash",
-        (ticket_id.to_owned(), tx_hash.to_owned()),
-    )
-    .await
-    .map_err(|(code, message)| CallError {
-        method: "update_tx_hash".to_string(),
-        reason: Reason::from_reject(code, message),
-    }) {
+    match hub
+        .update_tx_hash(ticket_id.to_owned(), tx_hash.to_owned())
+        .await
+    {
         Ok(()) => {
             log!(DEBUG,
                         "[handler_tx::update_tx_to_hub] mint req tx({:?}) already finallized and update tx hash to hub! ",
                         tx_hash
                     );
+            mutate_state(|s| {
+                s.record_ticket_stage(
+                    ticket_id,
+                    TicketDirection::Inbound,
+                    TicketStage::ReportedToHub { tx_hash },
+                )
+            });
         }
         Err(err) => {
             log!(
@@ -251,6 +320,15 @@ pub async fn update_tx_to_hub(ticket_id: &String, tx_hash: &String) {
                 "[handler_tx::update_tx_to_hub] failed to update tx hash to hub:{}",
                 err
             );
+            mutate_state(|s| {
+                s.record_ticket_stage(
+                    ticket_id,
+                    TicketDirection::Inbound,
+                    TicketStage::ReportToHubFailed {
+                        error: err.to_string(),
+                    },
+                )
+            });
             //TODO: save the req into failed queue;
         }
     }
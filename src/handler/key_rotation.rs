@@ -0,0 +1,381 @@
+use aptos_cached_packages::aptos_stdlib;
+use candid::CandidType;
+use ic_canister_log::log;
+use move_core_types::account_address::AccountAddress;
+use serde::{Deserialize, Serialize};
+
+use crate::aptos_client::error::AptosRouteError;
+use crate::aptos_client::rest_client::RestClient;
+use crate::aptos_client::{tx_builder, AccountKey, LocalAccount};
+use crate::ck_eddsa::{self, KeyAction, KeyType, RouteKeyId};
+use crate::config::{mutate_config, read_config, SnorKeyType};
+use crate::cycles::CostScope;
+use crate::guard::TaskType;
+use crate::handler::scheduler;
+use crate::ic_log::{DEBUG, ERROR, INFO};
+use crate::state::read_state;
+
+// the ed25519 scheme of `0x1::account::rotate_authentication_key`
+const ED25519_SCHEME: u8 = 0;
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RotationStatus {
+    Submitted,
+    Completed,
+    Failed { error: String },
+}
+
+/// The rotation of the route account authentication key, the account address
+/// stays the same so the port settings and the funds are kept
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyRotation {
+    pub address: String,
+    pub from_key_type: SnorKeyType,
+    pub from_key_name: String,
+    pub to_key_type: SnorKeyType,
+    pub to_key_name: String,
+    pub new_public_key: Vec<u8>,
+    pub tx_hash: String,
+    pub status: RotationStatus,
+    pub started_at: u64,
+    pub updated_at: u64,
+    // the expiration timestamp of the rotation tx, in seconds
+    #[serde(default)]
+    pub expires_at: u64,
+}
+
+/// `0x1::account::RotationProofChallenge`, signed by both the current and the new key
+#[derive(Serialize)]
+struct RotationProofChallenge {
+    account_address: AccountAddress,
+    module_name: String,
+    struct_name: String,
+    sequence_number: u64,
+    originator: AccountAddress,
+    current_auth_key: AccountAddress,
+    new_public_key: Vec<u8>,
+}
+
+fn resolve_key_type(key_type: &SnorKeyType) -> Result<KeyType, String> {
+    match key_type {
+        SnorKeyType::ChainKey => Ok(KeyType::ChainKey),
+        SnorKeyType::Native => ck_eddsa::native_key(),
+        SnorKeyType::ChainKeySecp256k1 => Ok(KeyType::ChainKeySecp256k1),
+        SnorKeyType::MultiEd25519 => Ok(KeyType::MultiEd25519),
+    }
+}
+
+pub fn in_progress() -> bool {
+    read_config(|s| {
+        s.get()
+            .key_rotation
+            .as_ref()
+            .is_some_and(|r| r.status == RotationStatus::Submitted)
+    })
+}
+
+/// submit the `rotate_authentication_key` tx signed by the current key.
+/// the `HandleTx` task is stopped until the rotation is confirmed, so no other tx
+/// takes the sequence number of the rotation proof
+pub async fn start(
+    to_key_type: SnorKeyType,
+    to_key_name: Option<String>,
+) -> Result<KeyRotation, String> {
+    if in_progress() {
+        return Err("a key rotation is in progress".to_string());
+    }
+    if read_state(|s| !s.route_accounts.is_empty()) {
+        return Err(
+            "the route pool accounts are derived from the same key, they can't be rotated yet"
+                .to_string(),
+        );
+    }
+    let (chain_id, from_key, from_key_name) = read_config(|s| {
+        (
+            s.get().chain_id.to_owned(),
+            s.get().key_type.to_owned(),
+            s.get().schnorr_key_name.to_owned(),
+        )
+    });
+    let to_key = resolve_key_type(&to_key_type)?;
+    let to_key_name = to_key_name.unwrap_or(from_key_name.to_owned());
+    if to_key == from_key && to_key_name == from_key_name {
+        return Err("the new key is the same as the current one".to_string());
+    }
+    // the rotation proof is signed by both keys with the ed25519 scheme
+    if [&from_key, &to_key]
+        .iter()
+        .any(|key| matches!(key, KeyType::ChainKeySecp256k1 | KeyType::MultiEd25519))
+    {
+        return Err("only the ed25519 keys can be rotated".to_string());
+    }
+
+    scheduler::stop_schedule(Some(vec![TaskType::HandleTx]));
+    match submit(&chain_id, &from_key, &from_key_name, &to_key, &to_key_name).await {
+        Ok((address, new_public_key, tx_hash, expires_at)) => {
+            let now = crate::clock::now();
+            let rotation = KeyRotation {
+                address,
+                from_key_type: from_key.into(),
+                from_key_name,
+                to_key_type,
+                to_key_name,
+                new_public_key,
+                tx_hash,
+                status: RotationStatus::Submitted,
+                started_at: now,
+                updated_at: now,
+                expires_at,
+            };
+            log!(
+                INFO,
+                "[key_rotation::start] submitted the key rotation: {:?}",
+                rotation
+            );
+            audit(
+                KeyAction::RotationStarted,
+                &rotation,
+                &chain_id,
+                &from_key,
+                &to_key,
+            );
+            save(rotation.to_owned());
+            Ok(rotation)
+        }
+        Err(e) => {
+            log!(
+                ERROR,
+                "[key_rotation::start] failed to rotate the key: {}",
+                e
+            );
+            scheduler::start_schedule(Some(vec![TaskType::HandleTx]));
+            Err(e)
+        }
+    }
+}
+
+async fn submit(
+    chain_id: &str,
+    from_key: &KeyType,
+    from_key_name: &str,
+    to_key: &KeyType,
+    to_key_name: &str,
+) -> Result<(String, Vec<u8>, String, u64), String> {
+    let scope = CostScope::request("RotateKey", None);
+    let derivation_path = ck_eddsa::derivation_path(chain_id, None);
+    let current_key = AccountKey::account_key(from_key.to_owned())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut account = LocalAccount::local_account(scope.to_owned())
+        .await
+        .map_err(|e| e.to_string())?;
+    // the new key is cached by its key id, it's used once the rotation is confirmed
+    let new_public_key = ck_eddsa::cached_public_key(
+        to_key.to_owned(),
+        to_key_name.to_string(),
+        derivation_path.to_owned(),
+    )
+    .await?;
+
+    let challenge = RotationProofChallenge {
+        account_address: AccountAddress::ONE,
+        module_name: "account".to_string(),
+        struct_name: "RotationProofChallenge".to_string(),
+        sequence_number: account
+            .update_seq_from_chain()
+            .await
+            .map_err(|e| e.to_string())?,
+        originator: account.address(),
+        current_auth_key: current_key.authentication_key().account_address(),
+        new_public_key: new_public_key.to_owned(),
+    };
+    let msg = bcs::to_bytes(&challenge).map_err(|e| e.to_string())?;
+    let cap_rotate_key = ck_eddsa::sign_with_eddsa(
+        from_key,
+        from_key_name.to_string(),
+        derivation_path.to_owned(),
+        msg.to_owned(),
+        &scope,
+    )
+    .await?;
+    let cap_update_table = ck_eddsa::sign_with_eddsa(
+        to_key,
+        to_key_name.to_string(),
+        derivation_path,
+        msg,
+        &scope,
+    )
+    .await?;
+
+    let payload = aptos_stdlib::account_rotate_authentication_key(
+        ED25519_SCHEME,
+        current_key.public_key().to_bytes(),
+        ED25519_SCHEME,
+        new_public_key.to_owned(),
+        cap_rotate_key,
+        cap_update_table,
+    );
+    let signed_txn = tx_builder::get_signed_payload_tx(&mut account, payload, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let pending_tx = RestClient::client()
+        .with_scope(scope)
+        .summit_tx(&signed_txn)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((
+        format!("{}", account.address()),
+        new_public_key,
+        pending_tx.hash.to_string(),
+        signed_txn.expiration_timestamp_secs(),
+    ))
+}
+
+fn submitted() -> Result<KeyRotation, String> {
+    read_config(|s| s.get().key_rotation.to_owned())
+        .filter(|r| r.status == RotationStatus::Submitted)
+        .ok_or("no key rotation is in progress".to_string())
+}
+
+fn is_expired(rotation: &KeyRotation) -> bool {
+    crate::clock::now() / 1_000_000_000 > rotation.expires_at
+}
+
+/// check the rotation tx, once it is committed the route signs with the new key
+/// and keeps the original address. The rotation fails if the tx is not found after
+/// its expiration, e.g. it was dropped by the mempool
+pub async fn confirm() -> Result<KeyRotation, String> {
+    let mut rotation = submitted()?;
+    let client = RestClient::client().with_scope(CostScope::request("RotateKey", None));
+    let tx = match client
+        .get_transaction_by_hash(rotation.tx_hash.to_owned(), &client.forward)
+        .await
+    {
+        Ok(tx) => tx,
+        Err(AptosRouteError::Api(e)) if e.status_code == 404 && is_expired(&rotation) => {
+            return Ok(fail(
+                rotation,
+                "the rotation tx is not found after its expiration".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    if tx.is_pending() {
+        log!(
+            DEBUG,
+            "[key_rotation::confirm] the rotation tx {} is pending",
+            rotation.tx_hash
+        );
+        return Ok(rotation);
+    }
+
+    if !tx.success() {
+        return Ok(fail(rotation, tx.vm_status()));
+    }
+
+    let (chain_id, from_key) =
+        read_config(|s| (s.get().chain_id.to_owned(), s.get().key_type.to_owned()));
+    let to_key = resolve_key_type(&rotation.to_key_type)?;
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.key_type = to_key.to_owned();
+        config.schnorr_key_name = rotation.to_key_name.to_owned();
+        config.route_address = Some(rotation.address.to_owned());
+        s.set(config);
+    });
+    rotation.status = RotationStatus::Completed;
+    audit(
+        KeyAction::RotationCompleted,
+        &rotation,
+        &chain_id,
+        &from_key,
+        &to_key,
+    );
+    log!(
+        INFO,
+        "[key_rotation::confirm] the route account {} is rotated to the new key",
+        rotation.address
+    );
+    rotation.updated_at = crate::clock::now();
+    save(rotation.to_owned());
+    scheduler::start_schedule(Some(vec![TaskType::HandleTx]));
+    Ok(rotation)
+}
+
+/// give up the rotation whose tx can't be committed anymore, the route keeps the current key.
+/// the committed tx must be confirmed instead
+pub async fn cancel() -> Result<KeyRotation, String> {
+    let rotation = submitted()?;
+    let client = RestClient::client().with_scope(CostScope::request("RotateKey", None));
+    match client
+        .get_transaction_by_hash(rotation.tx_hash.to_owned(), &client.forward)
+        .await
+    {
+        Ok(tx) if tx.is_pending() => {
+            return Err(format!("the rotation tx {} is pending", rotation.tx_hash))
+        }
+        Ok(_) => {
+            return Err(format!(
+                "the rotation tx {} is committed, use confirm_key_rotation",
+                rotation.tx_hash
+            ))
+        }
+        Err(_) if !is_expired(&rotation) => {
+            return Err(format!(
+                "the rotation tx {} may be committed until {}",
+                rotation.tx_hash, rotation.expires_at
+            ))
+        }
+        Err(_) => {}
+    }
+    Ok(fail(rotation, "cancelled by the admin".to_string()))
+}
+
+/// the route keeps the current key and the `HandleTx` task is started again
+fn fail(mut rotation: KeyRotation, error: String) -> KeyRotation {
+    log!(
+        ERROR,
+        "[key_rotation::fail] the rotation tx {} failed: {}",
+        rotation.tx_hash,
+        error
+    );
+    let (chain_id, from_key) =
+        read_config(|s| (s.get().chain_id.to_owned(), s.get().key_type.to_owned()));
+    let to_key = resolve_key_type(&rotation.to_key_type).unwrap_or(from_key.to_owned());
+    rotation.status = RotationStatus::Failed { error };
+    rotation.updated_at = crate::clock::now();
+    audit(
+        KeyAction::RotationFailed,
+        &rotation,
+        &chain_id,
+        &from_key,
+        &to_key,
+    );
+    save(rotation.to_owned());
+    scheduler::start_schedule(Some(vec![TaskType::HandleTx]));
+    rotation
+}
+
+fn save(rotation: KeyRotation) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.key_rotation = Some(rotation);
+        s.set(config);
+    });
+}
+
+fn audit(
+    action: KeyAction,
+    rotation: &KeyRotation,
+    chain_id: &str,
+    from_key: &KeyType,
+    to_key: &KeyType,
+) {
+    let derivation_path = ck_eddsa::derivation_path(chain_id, None);
+    ck_eddsa::record_key_audit(
+        action,
+        RouteKeyId::new(from_key, &rotation.from_key_name, &derivation_path),
+        RouteKeyId::new(to_key, &rotation.to_key_name, &derivation_path),
+        rotation.address.to_owned(),
+    );
+}
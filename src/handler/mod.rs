@@ -1,5 +1,10 @@
+pub mod approval;
 pub mod fecth_directive;
 pub mod fetch_ticket;
 pub mod gen_ticket;
 pub mod handle_tx;
+pub mod key_rotation;
+pub mod prune;
+pub mod resend_ticket;
 pub mod scheduler;
+pub mod update_token;
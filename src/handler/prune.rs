@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use ic_canister_log::log;
+use ic_stable_structures::{StableBTreeMap, Storable};
+
+use crate::config::read_config;
+use crate::constants::{PRUNE_BATCH_SIZE, TICKET_TIMELINE_RETENTION};
+use crate::ic_log::DEBUG;
+use crate::memory::Memory;
+use crate::rate_limit;
+use crate::state::{mutate_state, RouteState};
+
+thread_local! {
+    // the last key checked in each map, the next run continues after it
+    static CURSORS: RefCell<HashMap<&'static str, String>> = RefCell::default();
+}
+
+/// remove the expired records, each map is checked in batches of `PRUNE_BATCH_SIZE`
+pub fn prune_records() {
+    let now = crate::clock::now();
+    let since = now.saturating_sub(TICKET_TIMELINE_RETENTION.as_nanos() as u64);
+    prune(
+        "ticket_timelines",
+        |s| &mut s.ticket_timelines,
+        |timeline| {
+            timeline
+                .latest()
+                .map_or(true, |event| event.timestamp < since)
+        },
+    );
+
+    // the usages of the recipients and the removed rules are left behind once their
+    // window is over
+    let since = now.saturating_sub(rate_limit::max_window_secs().saturating_mul(1_000_000_000));
+    prune(
+        "rate_usages",
+        |s| &mut s.rate_usages,
+        |usage| {
+            usage
+                .entries
+                .iter()
+                .all(|(timestamp, _)| *timestamp < since)
+        },
+    );
+
+    let quota = read_config(|s| s.get().ticket_quota.to_owned());
+    prune(
+        "ticket_callers",
+        |s| &mut s.ticket_callers,
+        |record| record.is_stale(now, &quota),
+    );
+}
+
+/// check the next batch of the map and remove the expired values, returns the removed count
+pub fn prune<V, M, E>(name: &'static str, map: M, expired: E) -> usize
+where
+    V: Storable,
+    M: Fn(&mut RouteState) -> &mut StableBTreeMap<String, V, Memory>,
+    E: Fn(&V) -> bool,
+{
+    let cursor = CURSORS.with_borrow(|c| c.get(name).cloned());
+    let (removed, next_cursor) = mutate_state(|s| {
+        let map = map(s);
+        let batch: Vec<(String, V)> = match cursor {
+            Some(cursor) => map
+                .range((Bound::Excluded(cursor), Bound::Unbounded))
+                .take(PRUNE_BATCH_SIZE)
+                .collect(),
+            None => map.iter().take(PRUNE_BATCH_SIZE).collect(),
+        };
+        // start over once the end of the map is reached
+        let next_cursor = match batch.len() {
+            PRUNE_BATCH_SIZE => batch.last().map(|(key, _)| key.to_owned()),
+            _ => None,
+        };
+        let mut removed = 0;
+        for (key, value) in batch {
+            if expired(&value) {
+                map.remove(&key);
+                removed += 1;
+            }
+        }
+        (removed, next_cursor)
+    });
+    CURSORS.with_borrow_mut(|c| match next_cursor {
+        Some(key) => c.insert(name, key),
+        None => c.remove(name),
+    });
+    if removed > 0 {
+        log!(
+            DEBUG,
+            "[prune::prune] removed {} expired records from {}",
+            removed,
+            name
+        );
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{
+        read_state, replace_state, TicketDirection, TicketEvent, TicketStage, TicketTimeline,
+    };
+
+    fn timeline(ticket_id: &str, timestamp: u64) -> TicketTimeline {
+        TicketTimeline {
+            ticket_id: ticket_id.to_string(),
+            direction: TicketDirection::Outbound,
+            events: vec![TicketEvent {
+                stage: TicketStage::SentToHub,
+                timestamp,
+            }],
+        }
+    }
+
+    #[test]
+    fn expired_timelines_are_pruned() {
+        replace_state(RouteState::init());
+        let now = crate::clock::now();
+        let expired_at = now - TICKET_TIMELINE_RETENTION.as_nanos() as u64 - 1;
+        mutate_state(|s| {
+            s.ticket_timelines
+                .insert("ticket-0".to_string(), timeline("ticket-0", expired_at));
+            s.ticket_timelines
+                .insert("ticket-1".to_string(), timeline("ticket-1", now));
+        });
+
+        prune_records();
+        assert!(!read_state(|s| s
+            .ticket_timelines
+            .contains_key(&"ticket-0".to_string())));
+        assert!(read_state(|s| s
+            .ticket_timelines
+            .contains_key(&"ticket-1".to_string())));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::constants::{RESEND_LIMIT_SIZE, RESEND_TICKET_MAX_AGE};
+use crate::handler::gen_ticket::record_stage;
+use crate::hub_client::{CanisterHubClient, HubClient};
+use crate::ic_log::{CRITICAL, DEBUG, WARNING};
+use crate::state::{mutate_state, read_state, ResendStatus, TicketStage};
+use crate::types::{Error, Ticket, TicketId};
+
+use ic_canister_log::log;
+
+/// resend the tickets failed to send to hub, with backoff
+pub async fn resend_tickets() {
+    resend_tickets_to(&CanisterHubClient::from_config()).await
+}
+
+pub async fn resend_tickets_to(hub: &impl HubClient) {
+    let now = crate::clock::now();
+    prune_sent_tickets();
+
+    let due_tickets: Vec<(Ticket, ResendStatus)> = read_state(|s| {
+        s.tickets_failed_to_hub
+            .iter()
+            .map(|(ticket_id, ticket)| {
+                let status = s
+                    .resend_tickets
+                    .get(&ticket_id)
+                    .unwrap_or_else(|| ResendStatus::new(now, "unknown".to_string()));
+                (ticket, status)
+            })
+            .filter(|(_, status)| status.next_retry_at <= now)
+            .take(RESEND_LIMIT_SIZE)
+            .collect()
+    });
+
+    for (ticket, mut status) in due_tickets {
+        let ticket_id = ticket.ticket_id.to_owned();
+        let result = match hub.send_ticket(ticket).await {
+            Ok(Ok(())) => Ok(()),
+            // the hub has the ticket already
+            Ok(Err(Error::AlreadyExistingTicketId(_))) => Ok(()),
+            Ok(Err(err)) => Err(err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        let now = crate::clock::now();
+        match result {
+            Ok(()) => {
+                log!(
+                    DEBUG,
+                    "[resend_ticket::resend_tickets] successful to resend ticket: {}",
+                    ticket_id
+                );
+                mutate_state(|s| {
+                    s.tickets_failed_to_hub.remove(&ticket_id);
+                    s.gen_ticket_reqs.remove(&ticket_id);
+                    s.resend_tickets.remove(&ticket_id);
+                });
+                record_stage(&ticket_id, TicketStage::SentToHub);
+            }
+            Err(err) => {
+                log!(
+                    WARNING,
+                    "[resend_ticket::resend_tickets] failed to resend ticket: {}, retry: {}, error: {}",
+                    ticket_id,
+                    status.retry,
+                    err
+                );
+                record_stage(
+                    &ticket_id,
+                    TicketStage::SendToHubFailed {
+                        error: err.to_owned(),
+                    },
+                );
+                status.failed(now, err);
+                if !status.alerted
+                    && now.saturating_sub(status.first_failed_at)
+                        > RESEND_TICKET_MAX_AGE.as_nanos() as u64
+                {
+                    log!(
+                        CRITICAL,
+                        "[resend_ticket::resend_tickets] the ticket {} has failed to send to hub since {}, retry: {}",
+                        ticket_id,
+                        status.first_failed_at,
+                        status.retry
+                    );
+                    status.alerted = true;
+                }
+                mutate_state(|s| s.resend_tickets.insert(ticket_id, status));
+            }
+        }
+    }
+}
+
+// the sent tickets are tracked by their timelines, drop the entries kept with `sent_at`
+fn prune_sent_tickets() {
+    let sent: Vec<TicketId> = read_state(|s| {
+        s.resend_tickets
+            .iter()
+            .filter(|(_, status)| status.sent_at.is_some())
+            .map(|(ticket_id, _)| ticket_id)
+            .collect()
+    });
+    if sent.is_empty() {
+        return;
+    }
+    mutate_state(|s| {
+        for ticket_id in sent {
+            s.resend_tickets.remove(&ticket_id);
+        }
+    });
+}
@@ -3,14 +3,22 @@ use super::fecth_directive;
 use crate::constants::CLEAR_INTERVAL;
 
 use crate::constants::HANDLE_TX_INTERVAL;
+use crate::constants::PRUNE_RECORDS_INTERVAL;
+use crate::constants::REFRESH_ROUTE_ACCOUNT_INTERVAL;
+use crate::constants::RESEND_TICKET_INTERVAL;
 use crate::handler::fetch_ticket;
 use crate::handler::handle_tx;
-// use crate::handler::update_token;
+use crate::handler::prune;
+use crate::handler::resend_ticket;
+use crate::handler::update_token;
+use crate::route_pool;
 
-use crate::config::mutate_config;
+use crate::config::{mutate_config, read_config};
+use crate::cycles;
 use crate::{
     constants::{
-        MINT_TOKEN_INTERVAL, QUERY_DERECTIVE_INTERVAL, QUERY_TICKET_INTERVAL, UPDATE_TOKEN_INTERVAL,
+        MINT_TOKEN_INTERVAL, QUERY_DERECTIVE_INTERVAL, QUERY_TICKET_INTERVAL,
+        RECONCILE_DIRECTIVE_INTERVAL, RECONCILE_TICKET_INTERVAL, UPDATE_TOKEN_INTERVAL,
     },
     guard::{TaskType, TimerGuard},
 };
@@ -20,6 +28,7 @@ use ic_cdk_timers::TimerId;
 use crate::ic_log::{DEBUG, WARNING};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::time::Duration;
 thread_local! {
     static TIMER_GUARD: RefCell<HashMap<TaskType,TimerId>> = RefCell::new(HashMap::default());
 }
@@ -28,19 +37,25 @@ pub fn start_schedule(tasks: Option<Vec<TaskType>>) {
     match tasks {
         None => {
             fetch_directive_task();
-            // update_token_meta_task();
+            update_token_meta_task();
             fetch_tickets_task();
             // mint_token_task();
             handle_tx_task();
+            resend_tickets_task();
+            refresh_route_accounts_task();
+            prune_records_task();
         }
         Some(tasks) => {
             for task in tasks {
                 match task {
                     TaskType::GetDirectives => fetch_directive_task(),
-                    // TaskType::UpdateToken => update_token_meta_task(),
                     TaskType::GetTickets => fetch_tickets_task(),
                     // TaskType::MintToken => mint_token_task(),
                     TaskType::HandleTx => handle_tx_task(),
+                    TaskType::ResendTickets => resend_tickets_task(),
+                    TaskType::UpdateToken => update_token_meta_task(),
+                    TaskType::RefreshRouteAccounts => refresh_route_accounts_task(),
+                    TaskType::PruneRecords => prune_records_task(),
                 }
             }
         }
@@ -80,63 +95,197 @@ pub fn stop_schedule(tasks: Option<Vec<TaskType>>) {
     }
 }
 
+// query the hub at once, e.g. a gap is detected in the pushed seqs
+pub fn reconcile_now(task: TaskType) {
+    ic_cdk_timers::set_timer(Duration::ZERO, move || match task {
+        TaskType::GetTickets => ic_cdk::spawn(get_tickets()),
+        TaskType::GetDirectives => ic_cdk::spawn(get_directives()),
+        _ => {}
+    });
+}
+
+async fn get_tickets() {
+    let _guard = match TimerGuard::new(TaskType::GetTickets) {
+        Ok(guard) => guard,
+        Err(e) => {
+            log!(WARNING, "TaskType::GetTickets error : {:?}", e);
+            return;
+        }
+    };
+    // only the reconciliation is paused, the pushed tickets are still processed
+    if cycles::is_task_paused(TaskType::GetTickets) {
+        log!(
+            DEBUG,
+            "TaskType::GetTickets query is paused for the low cycles"
+        );
+    } else {
+        fetch_ticket::query_tickets().await;
+    }
+    fetch_ticket::process_tickets();
+}
+
+async fn get_directives() {
+    let _guard = match TimerGuard::new(TaskType::GetDirectives) {
+        Ok(guard) => guard,
+        Err(e) => {
+            log!(WARNING, "TaskType::GetDirectives error : {:?}", e);
+            return;
+        }
+    };
+    if cycles::is_task_paused(TaskType::GetDirectives) {
+        log!(
+            DEBUG,
+            "TaskType::GetDirectives is paused for the low cycles"
+        );
+        return;
+    }
+    fecth_directive::query_directives().await;
+}
+
 fn fetch_tickets_task() {
-    // query_tickets task
-    let query_ticket_timer_id = ic_cdk_timers::set_timer_interval(QUERY_TICKET_INTERVAL, || {
+    // query_tickets task, it only reconciles the gaps if the hub pushes tickets
+    let interval = if read_config(|s| s.get().hub_push_enabled) {
+        RECONCILE_TICKET_INTERVAL
+    } else {
+        QUERY_TICKET_INTERVAL
+    };
+    let query_ticket_timer_id = ic_cdk_timers::set_timer_interval(interval, || {
+        ic_cdk::spawn(get_tickets());
+    });
+    log!(DEBUG, "GetTickets task id : {:?}", query_ticket_timer_id);
+    TIMER_GUARD.with_borrow_mut(|guard| {
+        guard.insert(TaskType::GetTickets, query_ticket_timer_id);
+    });
+}
+
+fn fetch_directive_task() {
+    // query_directives task, it only reconciles the gaps if the hub pushes directives
+    let interval = if read_config(|s| s.get().hub_push_enabled) {
+        RECONCILE_DIRECTIVE_INTERVAL
+    } else {
+        QUERY_DERECTIVE_INTERVAL
+    };
+    let directive_timer_id = ic_cdk_timers::set_timer_interval(interval, || {
+        ic_cdk::spawn(get_directives());
+    });
+    log!(DEBUG, "GetDirectives task id : {:?}", directive_timer_id);
+    TIMER_GUARD.with_borrow_mut(|guard| {
+        guard.insert(TaskType::GetDirectives, directive_timer_id);
+    });
+}
+
+fn handle_tx_task() {
+    // handle to update token metadata
+    let update_token_timer_id = ic_cdk_timers::set_timer_interval(HANDLE_TX_INTERVAL, || {
         ic_cdk::spawn(async {
-            let _guard = match TimerGuard::new(TaskType::GetTickets) {
+            let _guard = match TimerGuard::new(TaskType::HandleTx) {
                 Ok(guard) => guard,
                 Err(e) => {
-                    log!(WARNING, "TaskType::GetTickets error : {:?}", e);
+                    log!(WARNING, "TaskType::HandleTx error : {:?}", e);
                     return;
                 }
             };
-
-            fetch_ticket::query_tickets().await;
+            // the mints are finalized at any cycles level, it keeps the level up to date
+            cycles::current_level();
+            handle_tx::handle_tx_req().await;
         });
     });
-    log!(DEBUG, "GetTickets task id : {:?}", query_ticket_timer_id);
+    log!(DEBUG, "HandleTx task id: {:?}", update_token_timer_id);
     TIMER_GUARD.with_borrow_mut(|guard| {
-        guard.insert(TaskType::GetTickets, query_ticket_timer_id);
+        guard.insert(TaskType::HandleTx, update_token_timer_id);
     });
 }
 
-fn fetch_directive_task() {
-    // query_directives task
-    let directive_timer_id = ic_cdk_timers::set_timer_interval(QUERY_DERECTIVE_INTERVAL, || {
+fn resend_tickets_task() {
+    // resend the tickets failed to send to hub
+    let resend_ticket_timer_id = ic_cdk_timers::set_timer_interval(RESEND_TICKET_INTERVAL, || {
         ic_cdk::spawn(async {
-            let _guard = match TimerGuard::new(TaskType::GetDirectives) {
+            let _guard = match TimerGuard::new(TaskType::ResendTickets) {
                 Ok(guard) => guard,
                 Err(e) => {
-                    log!(WARNING, "TaskType::GetDirectives error : {:?}", e);
+                    log!(WARNING, "TaskType::ResendTickets error : {:?}", e);
                     return;
                 }
             };
-            fecth_directive::query_directives().await;
+            resend_ticket::resend_tickets().await;
         });
     });
-    log!(DEBUG, "GetDirectives task id : {:?}", directive_timer_id);
+    log!(DEBUG, "ResendTickets task id: {:?}", resend_ticket_timer_id);
     TIMER_GUARD.with_borrow_mut(|guard| {
-        guard.insert(TaskType::GetDirectives, directive_timer_id);
+        guard.insert(TaskType::ResendTickets, resend_ticket_timer_id);
     });
 }
 
-fn handle_tx_task() {
-    // handle to update token metadata
-    let update_token_timer_id = ic_cdk_timers::set_timer_interval(HANDLE_TX_INTERVAL, || {
+fn update_token_meta_task() {
+    // sync the updated hub token metadata to the fa metadata on aptos
+    let update_token_timer_id = ic_cdk_timers::set_timer_interval(UPDATE_TOKEN_INTERVAL, || {
         ic_cdk::spawn(async {
-            let _guard = match TimerGuard::new(TaskType::HandleTx) {
+            let _guard = match TimerGuard::new(TaskType::UpdateToken) {
                 Ok(guard) => guard,
                 Err(e) => {
-                    log!(WARNING, "TaskType::HandleTx error : {:?}", e);
+                    log!(WARNING, "TaskType::UpdateToken error : {:?}", e);
                     return;
                 }
             };
-            handle_tx::handle_tx_req().await;
+            if cycles::is_task_paused(TaskType::UpdateToken) {
+                log!(DEBUG, "TaskType::UpdateToken is paused for the low cycles");
+                return;
+            }
+            update_token::sync_token_meta().await;
         });
     });
-    log!(DEBUG, "HandleTx task id: {:?}", update_token_timer_id);
+    log!(DEBUG, "UpdateToken task id: {:?}", update_token_timer_id);
     TIMER_GUARD.with_borrow_mut(|guard| {
-        guard.insert(TaskType::HandleTx, update_token_timer_id);
+        guard.insert(TaskType::UpdateToken, update_token_timer_id);
+    });
+}
+
+fn refresh_route_accounts_task() {
+    // monitor the sequence number and balance of the route pool accounts
+    let refresh_timer_id =
+        ic_cdk_timers::set_timer_interval(REFRESH_ROUTE_ACCOUNT_INTERVAL, || {
+            ic_cdk::spawn(async {
+                let _guard = match TimerGuard::new(TaskType::RefreshRouteAccounts) {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        log!(WARNING, "TaskType::RefreshRouteAccounts error : {:?}", e);
+                        return;
+                    }
+                };
+                if cycles::is_task_paused(TaskType::RefreshRouteAccounts) {
+                    log!(
+                        DEBUG,
+                        "TaskType::RefreshRouteAccounts is paused for the low cycles"
+                    );
+                    return;
+                }
+                route_pool::refresh_route_accounts().await;
+            });
+        });
+    log!(
+        DEBUG,
+        "RefreshRouteAccounts task id: {:?}",
+        refresh_timer_id
+    );
+    TIMER_GUARD.with_borrow_mut(|guard| {
+        guard.insert(TaskType::RefreshRouteAccounts, refresh_timer_id);
+    });
+}
+
+fn prune_records_task() {
+    // remove the expired records, so that the stable maps stay bounded
+    let prune_timer_id = ic_cdk_timers::set_timer_interval(PRUNE_RECORDS_INTERVAL, || {
+        let _guard = match TimerGuard::new(TaskType::PruneRecords) {
+            Ok(guard) => guard,
+            Err(e) => {
+                log!(WARNING, "TaskType::PruneRecords error : {:?}", e);
+                return;
+            }
+        };
+        prune::prune_records();
+    });
+    log!(DEBUG, "PruneRecords task id: {:?}", prune_timer_id);
+    TIMER_GUARD.with_borrow_mut(|guard| {
+        guard.insert(TaskType::PruneRecords, prune_timer_id);
     });
 }
@@ -0,0 +1,184 @@
+use crate::aptos_client::rest_client::RestClient;
+use crate::aptos_client::{FaMetadata, ReqType, TxReq, TxStatus, UpdateMetaReq};
+use crate::ck_eddsa::hash_with_sha256;
+use crate::constants::RETRY_NUM;
+use crate::cycles::CostScope;
+use crate::guard::TaskType;
+use crate::ic_log::{DEBUG, INFO, WARNING};
+use crate::state::{mutate_state, read_state, UpdateTokenStatus};
+use crate::types::{Token, TokenId};
+
+use ic_canister_log::log;
+
+/// compare the hub tokens in `update_token_queue` with the on-chain fa metadata
+/// and enqueue the `mutate_metadata` tx for the fields that differ
+pub async fn sync_token_meta() {
+    let updates: Vec<(TokenId, UpdateTokenStatus)> = read_state(|s| {
+        s.update_token_queue
+            .iter()
+            .filter(|(_, update)| update.status == TxStatus::New && update.retry < RETRY_NUM)
+            .collect()
+    });
+
+    for (token_id, mut update) in updates {
+        let Some(token) = read_state(|s| s.tokens.get(&token_id)) else {
+            mutate_state(|s| s.update_token_queue.remove(&token_id));
+            continue;
+        };
+        let Some(fa_obj) = read_state(|s| s.aptos_tokens.get(&token_id)).and_then(|t| t.fa_obj_id)
+        else {
+            log!(
+                DEBUG,
+                "[update_token::sync_token_meta] the fa obj of {} is not created yet",
+                token_id
+            );
+            continue;
+        };
+
+        let client = RestClient::client().with_scope(CostScope {
+            task: Some(TaskType::UpdateToken),
+            req_type: None,
+            token: Some(token_id.to_owned()),
+        });
+        let metadata = match client.get_fa_metadata(fa_obj.to_owned()).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log!(
+                    WARNING,
+                    "[update_token::sync_token_meta] failed to get the metadata of {}: {}",
+                    token_id,
+                    e
+                );
+                update.retry += 1;
+                mutate_state(|s| s.update_token_queue.insert(token_id, update));
+                continue;
+            }
+        };
+
+        match diff_meta(&token, &fa_obj, &metadata) {
+            None => {
+                log!(
+                    INFO,
+                    "[update_token::sync_token_meta] the metadata of {} is already synced",
+                    token_id
+                );
+                update.req.fa_obj = fa_obj;
+                update.status = TxStatus::Finalized;
+            }
+            Some(req) => {
+                log!(
+                    INFO,
+                    "[update_token::sync_token_meta] enqueue the metadata update: {:?}",
+                    req
+                );
+                let req_id = hash_with_sha256(
+                    &bincode::serialize(&req).expect("failed to serialize update_meta_req"),
+                );
+                let tx_req = TxReq {
+                    req_type: ReqType::UpdateMeta(req.to_owned()),
+                    tx_hash: None,
+                    tx_status: TxStatus::New,
+                    retry: 0,
+                };
+                mutate_state(|s| s.tx_queue.insert(req_id, tx_req));
+                update.req = req;
+                update.status = TxStatus::Pending;
+            }
+        }
+        mutate_state(|s| s.update_token_queue.insert(token_id, update));
+    }
+}
+
+/// build the update req with only the fields that differ from the on-chain metadata
+pub fn diff_meta(token: &Token, fa_obj: &str, metadata: &FaMetadata) -> Option<UpdateMetaReq> {
+    let name = (token.name != metadata.name).then(|| token.name.to_owned());
+    let symbol = (token.symbol != metadata.symbol).then(|| token.symbol.to_owned());
+    let icon_uri = token
+        .icon
+        .to_owned()
+        .filter(|icon| *icon != metadata.icon_uri);
+    if name.is_none() && symbol.is_none() && icon_uri.is_none() {
+        return None;
+    }
+    Some(UpdateMetaReq {
+        token_id: token.token_id.to_owned(),
+        fa_obj: fa_obj.to_owned(),
+        name,
+        symbol,
+        decimals: None,
+        icon_uri,
+        project_uri: None,
+    })
+}
+
+/// track the status of the metadata update tx in `update_token_queue`
+pub fn record_tx_status(req: &UpdateMetaReq, tx_req: &TxReq) {
+    mutate_state(|s| {
+        let Some(mut update) = s.update_token_queue.get(&req.token_id) else {
+            return;
+        };
+        // the token is updated again, the newer req is tracked instead
+        if update.req != *req {
+            return;
+        }
+        update.tx_hash = tx_req.tx_hash.to_owned();
+        update.retry = tx_req.retry;
+        update.status = tx_req.tx_status.to_owned();
+        s.update_token_queue.insert(req.token_id.to_owned(), update);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn metadata() -> FaMetadata {
+        FaMetadata {
+            name: "ICP".to_string(),
+            symbol: "ICP".to_string(),
+            decimals: 8,
+            icon_uri: "https://example.com/icp.svg".to_string(),
+            project_uri: "https://www.omnity.network".to_string(),
+        }
+    }
+
+    fn hub_token(name: &str, symbol: &str, icon: Option<&str>) -> Token {
+        Token {
+            token_id: "sICP-native-ICP".to_string(),
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            decimals: 8,
+            icon: icon.map(str::to_string),
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn no_update_when_synced() {
+        let token = hub_token("ICP", "ICP", Some("https://example.com/icp.svg"));
+        assert_eq!(diff_meta(&token, "0x1", &metadata()), None);
+        // the hub token without icon keeps the on-chain icon
+        let token = hub_token("ICP", "ICP", None);
+        assert_eq!(diff_meta(&token, "0x1", &metadata()), None);
+    }
+
+    #[test]
+    fn only_changed_fields_are_updated() {
+        let token = hub_token(
+            "Internet Computer",
+            "ICP",
+            Some("https://example.com/new.svg"),
+        );
+        let req = diff_meta(&token, "0x1", &metadata()).unwrap();
+        assert_eq!(req.name, Some("Internet Computer".to_string()));
+        assert_eq!(req.symbol, None);
+        assert_eq!(req.decimals, None);
+        assert_eq!(
+            req.icon_uri,
+            Some("https://example.com/new.svg".to_string())
+        );
+        assert_eq!(req.project_uri, None);
+        assert_eq!(req.fa_obj, "0x1");
+    }
+}
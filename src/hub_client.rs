@@ -0,0 +1,501 @@
+use candid::Principal;
+
+use crate::call_error::{CallError, Reason};
+use crate::config::read_config;
+use crate::types::{ChainId, Directive, Error, Seq, Ticket, TicketId, Topic};
+
+/// The calls the route makes to the omnity hub
+#[allow(async_fn_in_trait)]
+pub trait HubClient {
+    async fn query_tickets(&self, offset: u64, limit: u64)
+        -> Result<Vec<(Seq, Ticket)>, CallError>;
+
+    async fn query_directives(
+        &self,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<(Seq, Directive)>, CallError>;
+
+    /// the hub error is kept for the caller, e.g. `AlreadyExistingTicketId` is a success on resend
+    async fn send_ticket(&self, ticket: Ticket) -> Result<Result<(), Error>, CallError>;
+
+    async fn update_tx_hash(&self, ticket_id: TicketId, tx_hash: String) -> Result<(), CallError>;
+}
+
+/// The hub client calling the hub canister
+pub struct CanisterHubClient {
+    pub hub_principal: Principal,
+}
+
+impl CanisterHubClient {
+    pub fn new(hub_principal: Principal) -> Self {
+        Self { hub_principal }
+    }
+
+    pub fn from_config() -> Self {
+        Self::new(read_config(|s| s.get().hub_principal))
+    }
+}
+
+impl HubClient for CanisterHubClient {
+    async fn query_tickets(
+        &self,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<(Seq, Ticket)>, CallError> {
+        let resp: (Result<Vec<(Seq, Ticket)>, Error>,) = ic_cdk::api::call::call(
+            self.hub_principal,
+            "query_tickets",
+            (None::<Option<ChainId>>, offset, limit),
+        )
+        .await
+        .map_err(|(code, message)| CallError {
+            method: "query_tickets".to_string(),
+            reason: Reason::from_reject(code, message),
+        })?;
+        let data = resp.0.map_err(|err| CallError {
+            method: "query_tickets".to_string(),
+            reason: Reason::CanisterError(err.to_string()),
+        })?;
+        Ok(data)
+    }
+
+    async fn query_directives(
+        &self,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<(Seq, Directive)>, CallError> {
+        let resp: (Result<Vec<(Seq, Directive)>, Error>,) = ic_cdk::api::call::call(
+            self.hub_principal,
+            "query_directives",
+            (
+                None::<Option<ChainId>>,
+                None::<Option<Topic>>,
+                offset,
+                limit,
+            ),
+        )
+        .await
+        .map_err(|(code, message)| CallError {
+            method: "query_directives".to_string(),
+            reason: Reason::from_reject(code, message),
+        })?;
+        let data = resp.0.map_err(|err| CallError {
+            method: "query_directives".to_string(),
+            reason: Reason::CanisterError(err.to_string()),
+        })?;
+        Ok(data)
+    }
+
+    async fn send_ticket(&self, ticket: Ticket) -> Result<Result<(), Error>, CallError> {
+        let resp: (Result<(), Error>,) =
+            ic_cdk::api::call::call(self.hub_principal, "send_ticket", (ticket,))
+                .await
+                .map_err(|(code, message)| CallError {
+                    method: "send_ticket".to_string(),
+                    reason: Reason::from_reject(code, message),
+                })?;
+        Ok(resp.0)
+    }
+
+    async fn update_tx_hash(&self, ticket_id: TicketId, tx_hash: String) -> Result<(), CallError> {
+        ic_cdk::api::call::call(self.hub_principal, "update_tx_hash", (ticket_id, tx_hash))
+            .await
+            .map_err(|(code, message)| CallError {
+                method: "update_tx_hash".to_string(),
+                reason: Reason::from_reject(code, message),
+            })
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, VecDeque};
+
+    use super::HubClient;
+    use crate::call_error::{CallError, Reason};
+    use crate::types::{Directive, Error, Seq, Ticket, TicketId};
+
+    /// The in-memory hub, scripted with tickets, directives and the errors of the next calls
+    #[derive(Default)]
+    pub struct MockHubClient {
+        pub tickets: RefCell<BTreeMap<Seq, Ticket>>,
+        pub directives: RefCell<BTreeMap<Seq, Directive>>,
+        // the next calls fail with these errors, in order
+        pub call_errors: RefCell<VecDeque<CallError>>,
+        // the hub errors returned by the next `send_ticket` calls, in order
+        pub send_ticket_errors: RefCell<VecDeque<Error>>,
+        pub sent_tickets: RefCell<Vec<Ticket>>,
+        pub tx_hashes: RefCell<Vec<(TicketId, String)>>,
+    }
+
+    impl MockHubClient {
+        pub fn push_ticket(&self, seq: Seq, ticket: Ticket) {
+            self.tickets.borrow_mut().insert(seq, ticket);
+        }
+
+        pub fn push_directive(&self, seq: Seq, directive: Directive) {
+            self.directives.borrow_mut().insert(seq, directive);
+        }
+
+        pub fn fail_next_call(&self, method: &str, error: &str) {
+            self.call_errors.borrow_mut().push_back(CallError {
+                method: method.to_string(),
+                reason: Reason::Rejected(error.to_string()),
+            });
+        }
+
+        pub fn reject_next_ticket(&self, error: Error) {
+            self.send_ticket_errors.borrow_mut().push_back(error);
+        }
+
+        fn next_error(&self) -> Result<(), CallError> {
+            match self.call_errors.borrow_mut().pop_front() {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        }
+    }
+
+    fn page<T: Clone>(items: &BTreeMap<Seq, T>, offset: u64, limit: u64) -> Vec<(Seq, T)> {
+        items
+            .range(offset..)
+            .take(limit as usize)
+            .map(|(seq, item)| (*seq, item.to_owned()))
+            .collect()
+    }
+
+    impl HubClient for MockHubClient {
+        async fn query_tickets(
+            &self,
+            offset: u64,
+            limit: u64,
+        ) -> Result<Vec<(Seq, Ticket)>, CallError> {
+            self.next_error()?;
+            Ok(page(&self.tickets.borrow(), offset, limit))
+        }
+
+        async fn query_directives(
+            &self,
+            offset: u64,
+            limit: u64,
+        ) -> Result<Vec<(Seq, Directive)>, CallError> {
+            self.next_error()?;
+            Ok(page(&self.directives.borrow(), offset, limit))
+        }
+
+        async fn send_ticket(&self, ticket: Ticket) -> Result<Result<(), Error>, CallError> {
+            self.next_error()?;
+            if let Some(err) = self.send_ticket_errors.borrow_mut().pop_front() {
+                return Ok(Err(err));
+            }
+            self.sent_tickets.borrow_mut().push(ticket);
+            Ok(Ok(()))
+        }
+
+        async fn update_tx_hash(
+            &self,
+            ticket_id: TicketId,
+            tx_hash: String,
+        ) -> Result<(), CallError> {
+            self.next_error()?;
+            self.tx_hashes.borrow_mut().push((ticket_id, tx_hash));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+
+    use super::mock::MockHubClient;
+    use crate::aptos_client::ReqType;
+    use crate::config::{read_config, replace_config, RouteConfig};
+    use crate::handler::fecth_directive::{
+        query_directives_from, receive_pushed_directives, retry_failed_directives,
+    };
+    use crate::handler::fetch_ticket::{
+        process_tickets, query_tickets_from, receive_pushed_tickets,
+    };
+    use crate::handler::handle_tx::update_tx_to_hub;
+    use crate::handler::resend_ticket::resend_tickets_to;
+    use crate::memory::init_config;
+    use crate::state::{
+        mutate_state, read_state, replace_state, AptosToken, DirectiveStatus, GenTicketStatus,
+        RouteState, TicketStage,
+    };
+    use crate::types::{
+        Chain, ChainState, ChainType, Directive, Error, Ticket, TicketType, Token, TxAction,
+    };
+
+    const TOKEN_ID: &str = "Bitcoin-runes-HOPE•YOU•GET•RICH";
+    const RECEIVER: &str = "0xaf9306cac62396be300b175046140c392eed876bd8ac0efac6301cea286fa272";
+
+    fn init_route() {
+        let mut stable_config = init_config();
+        stable_config
+            .set(RouteConfig {
+                chain_id: "eAptos".to_string(),
+                ..Default::default()
+            })
+            .expect("failed to set config");
+        replace_config(stable_config);
+        replace_state(RouteState::init());
+    }
+
+    fn add_chain_and_token(hub: &MockHubClient) {
+        hub.push_directive(
+            0,
+            Directive::AddChain(Chain {
+                chain_id: "Bitcoin".to_string(),
+                canister_id: "bkyz2-fmaaa-aaaaa-qaaaq-cai".to_string(),
+                chain_type: ChainType::SettlementChain,
+                chain_state: ChainState::Active,
+                contract_address: None,
+                counterparties: None,
+                fee_token: None,
+            }),
+        );
+        hub.push_directive(
+            1,
+            Directive::AddToken(Token {
+                token_id: TOKEN_ID.to_string(),
+                name: "HOPE•YOU•GET•RICH".to_string(),
+                symbol: "RICH".to_string(),
+                decimals: 18,
+                icon: None,
+                metadata: HashMap::default(),
+            }),
+        );
+    }
+
+    fn ticket(ticket_id: &str, amount: &str) -> Ticket {
+        Ticket {
+            ticket_id: ticket_id.to_string(),
+            ticket_type: TicketType::Normal,
+            ticket_time: 0,
+            src_chain: "Bitcoin".to_string(),
+            dst_chain: "eAptos".to_string(),
+            action: TxAction::Transfer,
+            token: TOKEN_ID.to_string(),
+            amount: amount.to_string(),
+            sender: Some("bc1q".to_string()),
+            receiver: RECEIVER.to_string(),
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn directive_flow() {
+        init_route();
+        let hub = MockHubClient::default();
+        add_chain_and_token(&hub);
+
+        // the failed query must not move the seq
+        hub.fail_next_call("query_directives", "hub is busy");
+        block_on(query_directives_from(&hub));
+        assert_eq!(read_config(|s| s.get().seqs.next_directive_seq), 0);
+
+        block_on(query_directives_from(&hub));
+        assert_eq!(read_config(|s| s.get().seqs.next_directive_seq), 2);
+        assert!(read_state(|s| s
+            .counterparties
+            .contains_key(&"Bitcoin".to_string())));
+        assert!(read_state(|s| s.tokens.contains_key(&TOKEN_ID.to_string())));
+        let create_token = read_state(|s| {
+            s.tx_queue.iter().find_map(|(_, req)| match req.req_type {
+                ReqType::CreateToken(req) => Some(req),
+                _ => None,
+            })
+        })
+        .expect("the create token req is not queued");
+        // the 18 decimals token is created with the capped fa decimals
+        assert_eq!(create_token.decimals, 8);
+        assert!(read_state(|s| s
+            .directive_records
+            .iter()
+            .all(|(_, record)| record.status == DirectiveStatus::Applied)));
+
+        // the update of an unknown token is recorded as failed, and skipped when received again
+        let mut unknown = Token {
+            token_id: "unknown".to_string(),
+            name: "unknown".to_string(),
+            symbol: "UNKNOWN".to_string(),
+            decimals: 8,
+            icon: None,
+            metadata: HashMap::default(),
+        };
+        hub.push_directive(2, Directive::UpdateToken(unknown.to_owned()));
+        block_on(query_directives_from(&hub));
+        let record = read_state(|s| s.directive_records.get(&2)).expect("the record is missing");
+        assert!(matches!(record.status, DirectiveStatus::Failed { .. }));
+        unknown.name = "changed".to_string();
+        receive_pushed_directives(vec![(2, Directive::UpdateToken(unknown.to_owned()))]);
+        assert_eq!(
+            read_state(|s| s.directive_records.get(&2)).map(|record| record.hash),
+            Some(record.hash)
+        );
+        assert_eq!(read_config(|s| s.get().seqs.next_directive_seq), 3);
+
+        // the failed update is stale once a later update of the token is applied
+        let mut renamed = unknown.to_owned();
+        renamed.name = "renamed".to_string();
+        receive_pushed_directives(vec![
+            (3, Directive::AddToken(unknown.to_owned())),
+            (4, Directive::UpdateToken(renamed)),
+        ]);
+        retry_failed_directives();
+        assert_eq!(
+            read_state(|s| s.directive_records.get(&2)).map(|record| record.status),
+            Some(DirectiveStatus::Superseded { by: 4 })
+        );
+        assert_eq!(
+            read_state(|s| s.tokens.get(&"unknown".to_string())).map(|token| token.name),
+            Some("renamed".to_string())
+        );
+    }
+
+    #[test]
+    fn ticket_mint_and_report_flow() {
+        init_route();
+        let hub = MockHubClient::default();
+        add_chain_and_token(&hub);
+        block_on(query_directives_from(&hub));
+        mutate_state(|s| {
+            s.aptos_tokens.insert(
+                TOKEN_ID.to_string(),
+                AptosToken {
+                    fa_obj_id: Some(RECEIVER.to_string()),
+                    type_tag: None,
+                },
+            )
+        });
+
+        hub.push_ticket(0, ticket("ticket-0", "1000000000000000000"));
+        hub.push_ticket(1, ticket("ticket-1", "not a number"));
+        block_on(query_tickets_from(&hub));
+        assert_eq!(read_config(|s| s.get().seqs.next_ticket_seq), 2);
+        assert_eq!(read_state(|s| s.tickets_queue.len()), 2);
+
+        process_tickets();
+        assert_eq!(read_state(|s| s.tickets_queue.len()), 0);
+        assert!(read_state(|s| s
+            .quarantined_tickets
+            .contains_key(&"ticket-1".to_string())));
+        let mint_req = read_state(|s| {
+            s.tx_queue.iter().find_map(|(_, req)| match req.req_type {
+                ReqType::MintToken(req) => Some(req),
+                _ => None,
+            })
+        })
+        .expect("the mint req is not queued");
+        assert_eq!(mint_req.ticket_id, "ticket-0");
+        assert_eq!(mint_req.mint_acmount, 100_000_000);
+
+        block_on(update_tx_to_hub(
+            &hub,
+            &mint_req.ticket_id,
+            &"0xabc".to_string(),
+        ));
+        assert_eq!(
+            hub.tx_hashes.borrow().as_slice(),
+            &[("ticket-0".to_string(), "0xabc".to_string())]
+        );
+        let timeline = read_state(|s| s.ticket_timelines.get(&"ticket-0".to_string()))
+            .expect("the ticket timeline is missing");
+        assert_eq!(
+            timeline.latest().map(|event| event.stage.to_owned()),
+            Some(TicketStage::ReportedToHub {
+                tx_hash: "0xabc".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn pushed_directives_and_tickets_flow() {
+        init_route();
+        let hub = MockHubClient::default();
+        add_chain_and_token(&hub);
+        let directives: Vec<_> = hub.directives.borrow().to_owned().into_iter().collect();
+
+        // the directives pushed again are skipped
+        let (next_seq, gap) = receive_pushed_directives(vec![directives[0].to_owned()]);
+        assert_eq!((next_seq, gap), (1, false));
+        let (next_seq, gap) = receive_pushed_directives(directives.to_owned());
+        assert_eq!((next_seq, gap), (2, false));
+        assert!(read_state(|s| s.tokens.contains_key(&TOKEN_ID.to_string())));
+        mutate_state(|s| {
+            s.aptos_tokens.insert(
+                TOKEN_ID.to_string(),
+                AptosToken {
+                    fa_obj_id: Some(RECEIVER.to_string()),
+                    type_tag: None,
+                },
+            )
+        });
+
+        hub.push_ticket(0, ticket("ticket-0", "100000000000"));
+        hub.push_ticket(1, ticket("ticket-1", "100000000000"));
+        hub.push_ticket(2, ticket("ticket-2", "100000000000"));
+        let tickets: Vec<_> = hub.tickets.borrow().to_owned().into_iter().collect();
+        let (next_seq, gap) =
+            receive_pushed_tickets(vec![tickets[0].to_owned(), tickets[2].to_owned()]);
+        assert_eq!((next_seq, gap), (1, true));
+        // the duplicated ticket is skipped
+        let (next_seq, gap) = receive_pushed_tickets(vec![tickets[0].to_owned()]);
+        assert_eq!((next_seq, gap), (1, false));
+        assert_eq!(read_state(|s| s.tickets_queue.len()), 1);
+
+        block_on(query_tickets_from(&hub));
+        assert_eq!(read_config(|s| s.get().seqs.next_ticket_seq), 3);
+        assert_eq!(read_state(|s| s.tickets_queue.len()), 3);
+        process_tickets();
+        assert_eq!(read_state(|s| s.tx_queue.len()), 4);
+    }
+
+    #[test]
+    fn resend_ticket_flow() {
+        init_route();
+        let hub = MockHubClient::default();
+        mutate_state(|s| {
+            s.add_failed_ticket(ticket("ticket-0", "1"), "hub is busy".to_string());
+            s.add_failed_ticket(ticket("ticket-1", "1"), "hub is busy".to_string());
+            // both tickets are due to resend
+            for ticket_id in ["ticket-0", "ticket-1"] {
+                let mut status = s
+                    .resend_tickets
+                    .get(&ticket_id.to_string())
+                    .expect("the resend status is missing");
+                status.next_retry_at = 0;
+                s.resend_tickets.insert(ticket_id.to_string(), status);
+            }
+        });
+
+        // the first ticket fails again, and the hub has the second one already
+        hub.fail_next_call("send_ticket", "hub is busy");
+        hub.reject_next_ticket(Error::AlreadyExistingTicketId("ticket-1".to_string()));
+        block_on(resend_tickets_to(&hub));
+
+        assert!(!read_state(|s| s
+            .tickets_failed_to_hub
+            .contains_key(&"ticket-1".to_string())));
+        assert!(read_state(|s| s
+            .tickets_failed_to_hub
+            .contains_key(&"ticket-0".to_string())));
+        let status = read_state(|s| s.resend_tickets.get(&"ticket-0".to_string()))
+            .expect("the resend status is missing");
+        assert_eq!(status.retry, 1);
+        // the sent ticket is tracked by its timeline only
+        assert!(!read_state(|s| s
+            .resend_tickets
+            .contains_key(&"ticket-1".to_string())));
+        assert_eq!(
+            read_state(|s| s.gen_ticket_status(&"ticket-1".to_string())),
+            GenTicketStatus::SentToHub
+        );
+    }
+}
@@ -14,7 +14,10 @@ pub struct PrintProxySink(&'static str, &'static GlobalBuffer);
 
 impl Sink for PrintProxySink {
     fn append(&self, entry: ic_canister_log::LogEntry) {
+        #[cfg(target_arch = "wasm32")]
         ic_cdk::println!("{} {}:{} {}", self.0, entry.file, entry.line, entry.message);
+        #[cfg(not(target_arch = "wasm32"))]
+        println!("{} {}:{} {}", self.0, entry.file, entry.line, entry.message);
         self.1.append(entry)
     }
 }
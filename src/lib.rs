@@ -1,16 +1,25 @@
 pub mod aptos_client;
 pub mod auth;
 pub mod call_error;
+pub mod ck_ecdsa;
 pub mod ck_eddsa;
+pub mod clock;
 pub mod config;
 pub mod constants;
+pub mod cycles;
+pub mod decimals;
 pub mod guard;
 pub mod handler;
+pub mod hub_client;
 pub mod ic_log;
 // pub mod ic_sui;
 pub mod lifecycle;
 pub mod memory;
+pub mod multisig;
+pub mod rate_limit;
+pub mod route_pool;
 pub mod service;
 pub mod state;
+pub mod ticket_quota;
 
 pub mod types;
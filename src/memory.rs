@@ -8,13 +8,21 @@ use std::cell::RefCell;
 
 use crate::aptos_client::TxReq;
 // use crate::aptos_client::TxStatus;
-use crate::ck_eddsa::KeyType;
+use crate::ck_eddsa::{KeyAuditRecord, RouteKeyId, RouteKeyRecord};
 use crate::config::RouteConfig;
+use crate::cycles::CycleStats;
 
 use crate::handler::gen_ticket::GenerateTicketReq;
+use crate::multisig::SigningSession;
+use crate::rate_limit::RateUsage;
+use crate::route_pool::RouteAccount;
 // use crate::handler::mint_token::MintTokenRequest;
 use crate::state::AptosPort;
-use crate::state::{AptosToken, UpdateTokenStatus};
+use crate::state::{
+    AptosToken, DirectiveRecord, HeldMint, PendingApproval, QuarantinedTicket, ResendStatus,
+    TicketTimeline, UpdateTokenStatus,
+};
+use crate::ticket_quota::CallerRecord;
 use crate::types::Ticket;
 use crate::types::{Chain, Token};
 
@@ -30,8 +38,22 @@ const COUNTERPARTIES: MemoryId = MemoryId::new(8);
 const MINT_TOKEN_REQUESTS: MemoryId = MemoryId::new(9);
 const GEN_TICKET_REQS: MemoryId = MemoryId::new(10);
 const SEEDS: MemoryId = MemoryId::new(11);
-const ROUTE_ADDRESSES: MemoryId = MemoryId::new(12);
+// the legacy route public key cache keyed by the key type only, not used anymore
+// const ROUTE_ADDRESSES: MemoryId = MemoryId::new(12);
 const TX_QUEUE: MemoryId = MemoryId::new(13);
+const RESEND_TICKETS: MemoryId = MemoryId::new(14);
+const TICKET_TIMELINES: MemoryId = MemoryId::new(15);
+const QUARANTINED_TICKETS: MemoryId = MemoryId::new(16);
+const RATE_USAGES: MemoryId = MemoryId::new(17);
+const HELD_MINTS: MemoryId = MemoryId::new(18);
+const PENDING_APPROVALS: MemoryId = MemoryId::new(19);
+const DIRECTIVE_RECORDS: MemoryId = MemoryId::new(20);
+const ROUTE_ACCOUNTS: MemoryId = MemoryId::new(21);
+const ROUTE_KEYS: MemoryId = MemoryId::new(22);
+const KEY_AUDITS: MemoryId = MemoryId::new(23);
+const MULTISIG_SESSIONS: MemoryId = MemoryId::new(24);
+const CYCLE_STATS: MemoryId = MemoryId::new(25);
+const TICKET_CALLERS: MemoryId = MemoryId::new(26);
 
 type InnerMemory = DefaultMemoryImpl;
 
@@ -96,8 +118,8 @@ pub fn get_aptos_tokens_memory() -> Memory {
 pub fn get_config_memory() -> Memory {
     with_memory_manager(|m| m.get(CONFIG))
 }
-pub fn get_route_addresses_memory() -> Memory {
-    with_memory_manager(|m| m.get(ROUTE_ADDRESSES))
+pub fn get_route_keys_memory() -> Memory {
+    with_memory_manager(|m| m.get(ROUTE_KEYS))
 }
 
 pub fn get_aptos_ports_memory() -> Memory {
@@ -108,6 +130,54 @@ pub fn get_tx_queue_memory() -> Memory {
     with_memory_manager(|m| m.get(TX_QUEUE))
 }
 
+pub fn get_resend_tickets_memory() -> Memory {
+    with_memory_manager(|m| m.get(RESEND_TICKETS))
+}
+
+pub fn get_ticket_timelines_memory() -> Memory {
+    with_memory_manager(|m| m.get(TICKET_TIMELINES))
+}
+
+pub fn get_quarantined_tickets_memory() -> Memory {
+    with_memory_manager(|m| m.get(QUARANTINED_TICKETS))
+}
+
+pub fn get_rate_usages_memory() -> Memory {
+    with_memory_manager(|m| m.get(RATE_USAGES))
+}
+
+pub fn get_held_mints_memory() -> Memory {
+    with_memory_manager(|m| m.get(HELD_MINTS))
+}
+
+pub fn get_pending_approvals_memory() -> Memory {
+    with_memory_manager(|m| m.get(PENDING_APPROVALS))
+}
+
+pub fn get_directive_records_memory() -> Memory {
+    with_memory_manager(|m| m.get(DIRECTIVE_RECORDS))
+}
+
+pub fn get_route_accounts_memory() -> Memory {
+    with_memory_manager(|m| m.get(ROUTE_ACCOUNTS))
+}
+
+pub fn get_key_audits_memory() -> Memory {
+    with_memory_manager(|m| m.get(KEY_AUDITS))
+}
+
+pub fn get_multisig_sessions_memory() -> Memory {
+    with_memory_manager(|m| m.get(MULTISIG_SESSIONS))
+}
+
+pub fn get_cycle_stats_memory() -> Memory {
+    with_memory_manager(|m| m.get(CYCLE_STATS))
+}
+
+pub fn get_ticket_callers_memory() -> Memory {
+    with_memory_manager(|m| m.get(TICKET_CALLERS))
+}
+
 pub fn init_ticket_queue() -> StableBTreeMap<u64, Ticket, Memory> {
     StableBTreeMap::init(get_ticket_queue_memory())
 }
@@ -149,8 +219,8 @@ pub fn init_config() -> StableCell<RouteConfig, Memory> {
         .expect("failed to init sui route config")
 }
 
-pub fn init_route_addresses() -> StableBTreeMap<KeyType, Vec<u8>, Memory> {
-    StableBTreeMap::init(get_route_addresses_memory())
+pub fn init_route_keys() -> StableBTreeMap<RouteKeyId, RouteKeyRecord, Memory> {
+    StableBTreeMap::init(get_route_keys_memory())
 }
 
 pub fn init_aptos_ports() -> StableBTreeMap<String, AptosPort, Memory> {
@@ -160,3 +230,51 @@ pub fn init_aptos_ports() -> StableBTreeMap<String, AptosPort, Memory> {
 pub fn init_tx_queue() -> StableBTreeMap<String, TxReq, Memory> {
     StableBTreeMap::init(get_tx_queue_memory())
 }
+
+pub fn init_resend_tickets() -> StableBTreeMap<String, ResendStatus, Memory> {
+    StableBTreeMap::init(get_resend_tickets_memory())
+}
+
+pub fn init_ticket_timelines() -> StableBTreeMap<String, TicketTimeline, Memory> {
+    StableBTreeMap::init(get_ticket_timelines_memory())
+}
+
+pub fn init_quarantined_tickets() -> StableBTreeMap<String, QuarantinedTicket, Memory> {
+    StableBTreeMap::init(get_quarantined_tickets_memory())
+}
+
+pub fn init_rate_usages() -> StableBTreeMap<String, RateUsage, Memory> {
+    StableBTreeMap::init(get_rate_usages_memory())
+}
+
+pub fn init_held_mints() -> StableBTreeMap<String, HeldMint, Memory> {
+    StableBTreeMap::init(get_held_mints_memory())
+}
+
+pub fn init_pending_approvals() -> StableBTreeMap<String, PendingApproval, Memory> {
+    StableBTreeMap::init(get_pending_approvals_memory())
+}
+
+pub fn init_directive_records() -> StableBTreeMap<u64, DirectiveRecord, Memory> {
+    StableBTreeMap::init(get_directive_records_memory())
+}
+
+pub fn init_route_accounts() -> StableBTreeMap<u32, RouteAccount, Memory> {
+    StableBTreeMap::init(get_route_accounts_memory())
+}
+
+pub fn init_key_audits() -> StableBTreeMap<u64, KeyAuditRecord, Memory> {
+    StableBTreeMap::init(get_key_audits_memory())
+}
+
+pub fn init_multisig_sessions() -> StableBTreeMap<u64, SigningSession, Memory> {
+    StableBTreeMap::init(get_multisig_sessions_memory())
+}
+
+pub fn init_cycle_stats() -> StableBTreeMap<String, CycleStats, Memory> {
+    StableBTreeMap::init(get_cycle_stats_memory())
+}
+
+pub fn init_ticket_callers() -> StableBTreeMap<String, CallerRecord, Memory> {
+    StableBTreeMap::init(get_ticket_callers_memory())
+}
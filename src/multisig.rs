@@ -0,0 +1,411 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use aptos_crypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use aptos_crypto::multi_ed25519::{MultiEd25519PublicKey, MultiEd25519Signature};
+use aptos_crypto::traits::signing_message;
+use aptos_crypto::Signature;
+use aptos_types::transaction::authenticator::AuthenticationKey;
+use aptos_types::transaction::{RawTransaction, SignedTransaction};
+use candid::{CandidType, Principal};
+use ic_canister_log::log;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+use crate::ck_eddsa::{self, hash_with_sha256, KeyType};
+use crate::config::read_config;
+use crate::constants::{DEFAULT_MULTISIG_TX_TIMEOUT_SECS, MAX_MULTISIG_SIGNERS};
+use crate::cycles::CostScope;
+use crate::ic_log::{DEBUG, INFO, WARNING};
+use crate::state::{mutate_state, read_state};
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SignerSource {
+    // the threshold schnorr key of this canister
+    ChainKey,
+    // the threshold key of another canister, `method(blob) -> (Result<blob, text>)`
+    Canister {
+        canister_id: Principal,
+        method: String,
+    },
+    // the key kept offline, its share is submitted by `submit_signature_share`
+    Offline,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigSigner {
+    pub source: SignerSource,
+    // the ed25519 public key, derived by the route for the `ChainKey` source
+    pub public_key: Vec<u8>,
+}
+
+/// The k-of-n `MultiEd25519` route account, one compromised key can't sign alone
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigConfig {
+    pub threshold: u8,
+    // the index of the signer is its position in the multisig public key
+    pub signers: Vec<MultisigSigner>,
+    // the multisig tx stays valid while the offline shares are collected
+    pub tx_timeout_secs: Option<u64>,
+}
+
+impl MultisigConfig {
+    pub fn public_key(&self) -> Result<MultiEd25519PublicKey, String> {
+        let public_keys = self
+            .signers
+            .iter()
+            .map(|signer| {
+                Ed25519PublicKey::try_from(signer.public_key.as_slice())
+                    .map_err(|e| format!("invalid signer public key: {}", e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        MultiEd25519PublicKey::new(public_keys, self.threshold).map_err(|e| e.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.signers.len() > MAX_MULTISIG_SIGNERS {
+            return Err(format!(
+                "the multisig account can't have more than {} signers",
+                MAX_MULTISIG_SIGNERS
+            ));
+        }
+        if self.threshold == 0 || self.threshold as usize > self.signers.len() {
+            return Err(format!(
+                "the threshold {} must be in 1..={}",
+                self.threshold,
+                self.signers.len()
+            ));
+        }
+        self.public_key().map(|_| ())
+    }
+
+    pub fn address(&self) -> Result<String, String> {
+        let public_key = self.public_key()?;
+        Ok(format!(
+            "{}",
+            AuthenticationKey::multi_ed25519(&public_key).account_address()
+        ))
+    }
+
+    // changes once the signers or the threshold change, it tells the key ids apart
+    pub fn fingerprint(&self) -> String {
+        let public_key = self
+            .public_key()
+            .map(|pk| pk.to_bytes())
+            .unwrap_or_default();
+        hash_with_sha256(&public_key)[..8].to_string()
+    }
+}
+
+/// The multisig tx waiting for the signature shares, keyed by its sequence number.
+/// The raw tx is kept so the shares stay valid when the tx is built again, the seq is
+/// pinned to the req until the tx is submitted or expires
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SigningSession {
+    pub seq: u64,
+    // the tx req, or the payload hash of the tx signed out of the tx queue
+    pub req_id: String,
+    // the bcs bytes of the raw tx
+    pub raw_txn: Vec<u8>,
+    // signed by every signer, the offline signers sign it as it is
+    pub signing_message: Vec<u8>,
+    pub shares: BTreeMap<u8, Vec<u8>>,
+    pub created_at: u64,
+    // the expiration timestamp of the tx, in seconds
+    pub expires_at: u64,
+}
+
+impl Storable for SigningSession {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize SigningSession");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize SigningSession")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl SigningSession {
+    pub fn new(txn: &RawTransaction, req_id: String) -> Result<Self, String> {
+        Ok(Self {
+            seq: txn.sequence_number(),
+            req_id,
+            raw_txn: bcs::to_bytes(txn).map_err(|e| e.to_string())?,
+            signing_message: signing_message(txn).map_err(|e| e.to_string())?,
+            shares: BTreeMap::new(),
+            created_at: crate::clock::now(),
+            expires_at: txn.expiration_timestamp_secs(),
+        })
+    }
+
+    pub fn raw_txn(&self) -> Result<RawTransaction, String> {
+        bcs::from_bytes(&self.raw_txn).map_err(|e| e.to_string())
+    }
+}
+
+pub fn multisig_config() -> Result<MultisigConfig, String> {
+    read_config(|s| s.get().multisig.to_owned())
+        .ok_or("the multisig route account is not configured".to_string())
+}
+
+/// the multisig tx timeout, or the default one for the other keys
+pub fn tx_timeout_secs(key_type: &KeyType, default: u64) -> u64 {
+    match key_type {
+        KeyType::MultiEd25519 => multisig_config()
+            .ok()
+            .and_then(|config| config.tx_timeout_secs)
+            .unwrap_or(DEFAULT_MULTISIG_TX_TIMEOUT_SECS),
+        _ => default,
+    }
+}
+
+pub fn verify_share(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let public_key =
+        Ed25519PublicKey::try_from(public_key).map_err(|e| format!("invalid public key: {}", e))?;
+    let signature =
+        Ed25519Signature::try_from(signature).map_err(|e| format!("invalid signature: {}", e))?;
+    signature
+        .verify_arbitrary_msg(message, &public_key)
+        .map_err(|e| format!("invalid signature share: {}", e))
+}
+
+/// combine the first `threshold` shares into the multisig signature
+pub fn combine(
+    shares: &BTreeMap<u8, Vec<u8>>,
+    threshold: u8,
+) -> Result<MultiEd25519Signature, String> {
+    if shares.len() < threshold as usize {
+        return Err(format!(
+            "{} signature shares collected, {} required",
+            shares.len(),
+            threshold
+        ));
+    }
+    let signatures = shares
+        .iter()
+        .take(threshold as usize)
+        .map(|(index, share)| {
+            Ed25519Signature::try_from(share.as_slice())
+                .map(|signature| (signature, *index))
+                .map_err(|e| format!("invalid signature share {}: {}", index, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    MultiEd25519Signature::new(signatures).map_err(|e| e.to_string())
+}
+
+/// collect the shares from the online signers and combine them once the threshold is reached,
+/// otherwise the session waits for the offline shares and the tx is signed in the next round
+pub async fn sign_transaction(
+    txn: RawTransaction,
+    req_id: Option<&String>,
+    scope: &CostScope,
+) -> Result<SignedTransaction, String> {
+    let config = multisig_config()?;
+    let public_key = config.public_key()?;
+    let seq = txn.sequence_number();
+    let req_id = match req_id {
+        Some(req_id) => req_id.to_owned(),
+        None => hash_with_sha256(&bcs::to_bytes(txn.payload()).map_err(|e| e.to_string())?),
+    };
+    let now_secs = crate::clock::now() / 1_000_000_000;
+    // the sessions of the used seqs or the expired txs can't be submitted anymore
+    mutate_state(|s| {
+        let stale: Vec<u64> = s
+            .multisig_sessions
+            .iter()
+            .filter(|(session_seq, session)| *session_seq < seq || session.expires_at <= now_secs)
+            .map(|(session_seq, _)| session_seq)
+            .collect();
+        for session_seq in stale {
+            s.multisig_sessions.remove(&session_seq);
+        }
+    });
+
+    // the other reqs wait, the shares collected for the seq are not overwritten
+    let mut session = match read_state(|s| s.multisig_sessions.get(&seq)) {
+        Some(session) if session.req_id != req_id => {
+            return Err(format!(
+                "the multisig tx {} is pinned to the req {} until it expires at {}",
+                seq, session.req_id, session.expires_at
+            ));
+        }
+        Some(session) if session.raw_txn()?.payload() == txn.payload() => session,
+        _ => SigningSession::new(&txn, req_id)?,
+    };
+    let chain_id = read_config(|s| s.get().chain_id.to_owned());
+    for (index, signer) in config.signers.iter().enumerate() {
+        if session.shares.len() >= config.threshold as usize {
+            break;
+        }
+        let index = index as u8;
+        if session.shares.contains_key(&index) {
+            continue;
+        }
+        let message = session.signing_message.to_owned();
+        let share = match &signer.source {
+            SignerSource::ChainKey => {
+                ck_eddsa::sign_with_path(
+                    message,
+                    KeyType::ChainKey,
+                    ck_eddsa::derivation_path(&chain_id, None),
+                    scope,
+                )
+                .await
+            }
+            SignerSource::Canister {
+                canister_id,
+                method,
+            } => remote_sign(*canister_id, method, message).await,
+            SignerSource::Offline => continue,
+        };
+        match share.and_then(|share| {
+            verify_share(&signer.public_key, &session.signing_message, &share).map(|_| share)
+        }) {
+            Ok(share) => {
+                session.shares.insert(index, share);
+            }
+            Err(e) => log!(
+                WARNING,
+                "[multisig::sign_transaction] signer {} failed to sign tx {}: {}",
+                index,
+                seq,
+                e
+            ),
+        }
+    }
+
+    let collected = session.shares.len();
+    let raw_txn = session.raw_txn()?;
+    let signature = combine(&session.shares, config.threshold);
+    // keep the session until the seq is used, the combined shares are reused if the submission fails
+    mutate_state(|s| s.multisig_sessions.insert(seq, session));
+    match signature {
+        Ok(signature) => {
+            log!(
+                DEBUG,
+                "[multisig::sign_transaction] tx {} signed by {} signers",
+                seq,
+                collected
+            );
+            Ok(SignedTransaction::new_multisig(
+                raw_txn, public_key, signature,
+            ))
+        }
+        Err(_) => Err(format!(
+            "the multisig tx {} is waiting for {} more signature shares",
+            seq,
+            config.threshold as usize - collected
+        )),
+    }
+}
+
+async fn remote_sign(
+    canister_id: Principal,
+    method: &str,
+    message: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    let res: Result<(Result<ByteBuf, String>,), _> =
+        ic_cdk::call(canister_id, method, (ByteBuf::from(message),)).await;
+    let (res,) = res.map_err(|(code, msg)| {
+        format!(
+            "failed to call {}.{}: {:?}, {}",
+            canister_id, method, code, msg
+        )
+    })?;
+    res.map(|signature| signature.into_vec())
+}
+
+/// the share signed by the offline signer
+pub fn submit_share(seq: u64, index: u8, signature: Vec<u8>) -> Result<SigningSession, String> {
+    let config = multisig_config()?;
+    let signer = config
+        .signers
+        .get(index as usize)
+        .ok_or(format!("not found the signer: {}", index))?;
+    let mut session = read_state(|s| s.multisig_sessions.get(&seq))
+        .ok_or(format!("not found the signing session: {}", seq))?;
+    verify_share(&signer.public_key, &session.signing_message, &signature)?;
+    session.shares.insert(index, signature);
+    log!(
+        INFO,
+        "[multisig::submit_share] signer {} submitted the share for tx {}, {}/{} collected",
+        index,
+        seq,
+        session.shares.len(),
+        config.threshold
+    );
+    mutate_state(|s| s.multisig_sessions.insert(seq, session.to_owned()));
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_crypto::ed25519::Ed25519PrivateKey;
+
+    fn private_key(seed: u8) -> Ed25519PrivateKey {
+        Ed25519PrivateKey::try_from(&[seed; 32][..]).unwrap()
+    }
+
+    fn sign(private_key: &Ed25519PrivateKey, message: &[u8]) -> Vec<u8> {
+        let public_key = Ed25519PublicKey::from(private_key);
+        let expanded_secret_key = ed25519_dalek::ExpandedSecretKey::from(&private_key.0);
+        expanded_secret_key
+            .sign(message, &public_key.0)
+            .to_bytes()
+            .to_vec()
+    }
+
+    fn multisig_of(threshold: u8, keys: &[Ed25519PrivateKey]) -> MultisigConfig {
+        MultisigConfig {
+            threshold,
+            signers: keys
+                .iter()
+                .map(|key| MultisigSigner {
+                    source: SignerSource::Offline,
+                    public_key: Ed25519PublicKey::from(key).to_bytes().to_vec(),
+                })
+                .collect(),
+            tx_timeout_secs: None,
+        }
+    }
+
+    #[test]
+    fn validate_threshold() {
+        let keys = vec![private_key(1), private_key(2), private_key(3)];
+        assert!(multisig_of(2, &keys).validate().is_ok());
+        assert!(multisig_of(0, &keys).validate().is_err());
+        assert!(multisig_of(4, &keys).validate().is_err());
+        assert_ne!(
+            multisig_of(2, &keys).fingerprint(),
+            multisig_of(3, &keys).fingerprint()
+        );
+    }
+
+    #[test]
+    fn combine_two_of_three() {
+        let keys = vec![private_key(1), private_key(2), private_key(3)];
+        let config = multisig_of(2, &keys);
+        let message = b"aptos route multisig".to_vec();
+        let share = |index: usize| sign(&keys[index], &message);
+
+        // a share signed by the other key is rejected
+        assert!(verify_share(&config.signers[0].public_key, &message, &share(1)).is_err());
+
+        let mut shares = BTreeMap::new();
+        shares.insert(2, share(2));
+        assert!(combine(&shares, config.threshold).is_err());
+
+        shares.insert(0, share(0));
+        let signature = combine(&shares, config.threshold).unwrap();
+        let public_key = config.public_key().unwrap();
+        assert!(signature
+            .verify_arbitrary_msg(&message, &public_key)
+            .is_ok());
+    }
+}
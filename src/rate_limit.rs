@@ -0,0 +1,309 @@
+#![allow(unused)]
+use std::borrow::Cow;
+
+use candid::CandidType;
+use ic_canister_log::log;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{mutate_config, read_config};
+use crate::ic_log::{CRITICAL, WARNING};
+use crate::state::{mutate_state, read_state};
+use crate::types::TokenId;
+
+#[derive(
+    CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Direction {
+    // mint on aptos for the tickets from hub
+    Mint,
+    // redeem from aptos to the other chains
+    Redeem,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LimitScope {
+    // all the tokens, only `max_count` is allowed as the tokens have different decimals
+    Global,
+    Token(TokenId),
+    // each recipient of the token is limited separately
+    Recipient(TokenId),
+}
+
+/// A rolling window limit, the amounts are in the token's smallest unit
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RateLimitRule {
+    pub scope: LimitScope,
+    pub direction: Direction,
+    pub window_secs: u64,
+    pub max_amount: Option<u128>,
+    pub max_count: Option<u64>,
+    // a single transfer above this amount is an anomaly, a mint pauses the token
+    // while a redeem is only rejected, anyone can redeem and must not pause the token for others
+    pub max_single_amount: Option<u128>,
+}
+
+impl RateLimitRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.scope == LimitScope::Global
+            && (self.max_amount.is_some() || self.max_single_amount.is_some())
+        {
+            return Err(format!(
+                "the amounts of different tokens can't be added up, the global rule only limits the count: {:?}",
+                self
+            ));
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, direction: Direction, token_id: &TokenId) -> bool {
+        self.direction == direction
+            && match &self.scope {
+                LimitScope::Global => true,
+                LimitScope::Token(token) | LimitScope::Recipient(token) => token.eq(token_id),
+            }
+    }
+
+    // the rules with the same scope and window are counted separately by their index
+    fn usage_key(&self, index: usize, recipient: &str) -> String {
+        let scope = match &self.scope {
+            LimitScope::Global => "global".to_string(),
+            LimitScope::Token(token) => format!("token:{}", token),
+            LimitScope::Recipient(token) => format!("recipient:{}:{}", token, recipient),
+        };
+        format!(
+            "rule:{}:{:?}:{}:{}",
+            index, self.direction, self.window_secs, scope
+        )
+    }
+}
+
+/// The transfers recorded in the rolling window of a usage key
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct RateUsage {
+    // (timestamp in nanoseconds, amount)
+    pub entries: Vec<(u64, u128)>,
+}
+
+impl RateUsage {
+    pub(crate) fn prune(&mut self, since: u64) {
+        self.entries.retain(|(timestamp, _)| *timestamp >= since);
+    }
+
+    fn amount(&self) -> u128 {
+        self.entries
+            .iter()
+            .fold(0u128, |total, (_, amount)| total.saturating_add(*amount))
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.entries.len() as u64
+    }
+}
+
+impl Storable for RateUsage {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize RateUsage");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize RateUsage")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PausedToken {
+    pub reason: String,
+    pub paused_at: u64,
+    // paused by the breaker rather than the admin
+    pub auto: bool,
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LimitError {
+    TokenPaused(TokenId),
+    LimitExceeded(String),
+    Anomaly(String),
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitError::TokenPaused(token_id) => write!(f, "the token {} is paused", token_id),
+            LimitError::LimitExceeded(msg) => write!(f, "rate limit exceeded: {}", msg),
+            LimitError::Anomaly(msg) => write!(f, "anomaly detected: {}", msg),
+        }
+    }
+}
+
+pub fn is_paused(token_id: &TokenId) -> bool {
+    read_config(|s| s.get().paused_tokens.contains_key(token_id))
+}
+
+pub fn pause_token(token_id: TokenId, reason: String, auto: bool) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.paused_tokens.insert(
+            token_id,
+            PausedToken {
+                reason,
+                paused_at: crate::clock::now(),
+                auto,
+            },
+        );
+        s.set(config);
+    })
+}
+
+pub fn update_rules(rules: Vec<RateLimitRule>) -> Result<(), String> {
+    for rule in &rules {
+        rule.validate()?;
+    }
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.rate_limits = rules;
+        s.set(config);
+    });
+    Ok(())
+}
+
+/// the longest window of the rate usages, the usage without an entry in it is not used
+/// by any rule anymore
+pub fn max_window_secs() -> u64 {
+    read_config(|s| {
+        let config = s.get();
+        config
+            .rate_limits
+            .iter()
+            .map(|rule| rule.window_secs)
+            .chain([config.ticket_quota.window_secs])
+            .max()
+            .unwrap_or_default()
+    })
+}
+
+pub fn resume_token(token_id: &TokenId) -> Option<PausedToken> {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        let paused = config.paused_tokens.remove(token_id);
+        s.set(config);
+        paused
+    })
+}
+
+/// check the transfer against the configured limits, and record it if all of them pass
+pub fn check_and_record(
+    direction: Direction,
+    token_id: &TokenId,
+    recipient: &str,
+    amount: u128,
+) -> Result<(), LimitError> {
+    if is_paused(token_id) {
+        return Err(LimitError::TokenPaused(token_id.to_owned()));
+    }
+    let now = crate::clock::now();
+    let rules: Vec<(usize, RateLimitRule)> = read_config(|s| {
+        s.get()
+            .rate_limits
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.applies_to(direction, token_id))
+            .map(|(index, rule)| (index, rule.to_owned()))
+            .collect()
+    });
+
+    let mut usages = vec![];
+    for (index, rule) in &rules {
+        if rule
+            .max_single_amount
+            .is_some_and(|max_single_amount| amount > max_single_amount)
+        {
+            let reason = format!(
+                "{:?} {} {} to {} exceeds the single transfer limit {:?}",
+                direction, amount, token_id, recipient, rule.max_single_amount
+            );
+            if direction == Direction::Mint {
+                log!(
+                    CRITICAL,
+                    "[rate_limit::check_and_record] pause token: {}",
+                    reason
+                );
+                pause_token(token_id.to_owned(), reason.to_owned(), true);
+            } else {
+                log!(WARNING, "[rate_limit::check_and_record] {}", reason);
+            }
+            return Err(LimitError::Anomaly(reason));
+        }
+
+        let key = rule.usage_key(*index, recipient);
+        let mut usage = read_state(|s| s.rate_usages.get(&key)).unwrap_or_default();
+        usage.prune(now.saturating_sub(rule.window_secs.saturating_mul(1_000_000_000)));
+        let exceeded = rule
+            .max_amount
+            .is_some_and(|max_amount| usage.amount().saturating_add(amount) > max_amount)
+            || rule
+                .max_count
+                .is_some_and(|max_count| usage.count() + 1 > max_count);
+        if exceeded {
+            let reason = format!(
+                "{} in the last {}s, used amount: {}, used count: {}",
+                key,
+                rule.window_secs,
+                usage.amount(),
+                usage.count()
+            );
+            log!(WARNING, "[rate_limit::check_and_record] {}", reason);
+            return Err(LimitError::LimitExceeded(reason));
+        }
+        usages.push((key, usage));
+    }
+
+    mutate_state(|s| {
+        for (key, mut usage) in usages {
+            usage.entries.push((now, amount));
+            s.rate_usages.insert(key, usage);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(scope: LimitScope) -> RateLimitRule {
+        RateLimitRule {
+            scope,
+            direction: Direction::Mint,
+            window_secs: 3600,
+            max_amount: None,
+            max_count: Some(10),
+            max_single_amount: None,
+        }
+    }
+
+    #[test]
+    fn global_rule_only_limits_the_count() {
+        assert!(rule(LimitScope::Global).validate().is_ok());
+        let global_amount = RateLimitRule {
+            max_amount: Some(1_000),
+            ..rule(LimitScope::Global)
+        };
+        assert!(global_amount.validate().is_err());
+        let token_amount = RateLimitRule {
+            max_amount: Some(1_000),
+            ..rule(LimitScope::Token("APT".to_string()))
+        };
+        assert!(token_amount.validate().is_ok());
+    }
+
+    #[test]
+    fn same_rules_are_counted_separately() {
+        let rule = rule(LimitScope::Token("APT".to_string()));
+        assert_ne!(rule.usage_key(0, "0x1"), rule.usage_key(1, "0x1"));
+    }
+}
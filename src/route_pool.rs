@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+
+use candid::CandidType;
+use ic_canister_log::log;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::aptos_client::rest_client::RestClient;
+use crate::aptos_client::{ReqType, RoutePublicKey, TxReq, TxStatus};
+use crate::ck_eddsa::{self, hash_with_sha256, KeyType};
+use crate::config::read_config;
+use crate::constants::{
+    MAX_ROUTE_ACCOUNTS, MIN_ROUTE_ACCOUNT_BALANCE, ROUTE_ACCOUNT_STUCK_TIMEOUT,
+};
+use crate::cycles::CostScope;
+use crate::guard::TaskType;
+use crate::ic_log::{DEBUG, ERROR, INFO, WARNING};
+use crate::state::{mutate_state, read_state};
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RouteAccountStatus {
+    Active,
+    // the apt balance is not enough to pay the gas
+    LowBalance,
+    // the submitted tx is not committed in time, its reqs are moved to the other accounts
+    Stuck { since: u64 },
+    // disabled by the admin
+    Disabled,
+}
+
+/// The route account derived from `[chain_id, "mint", index]`, it signs the mint txs
+/// with its own sequence number so one stuck account doesn't block the others
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RouteAccount {
+    pub index: u32,
+    pub address: String,
+    pub public_key: Vec<u8>,
+    // authorized to mint on the port
+    pub minter: bool,
+    // the latest sequence number on chain
+    pub sequence_number: u64,
+    pub last_submitted_seq: Option<u64>,
+    pub last_submitted_at: u64,
+    pub balance: u64,
+    // the tx reqs signed by the account and not finalized yet
+    pub pending_reqs: Vec<String>,
+    pub status: RouteAccountStatus,
+}
+
+impl Storable for RouteAccount {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize RouteAccount");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize RouteAccount")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl RouteAccount {
+    pub fn is_available(&self) -> bool {
+        self.minter && self.status == RouteAccountStatus::Active
+    }
+
+    /// the submitted tx is not committed and the sequence number doesn't move on
+    /// until the timeout, in nanoseconds
+    pub fn is_stuck(&self, onchain_seq: u64, now: u64, timeout: u64) -> bool {
+        self.last_submitted_seq
+            .is_some_and(|seq| seq >= onchain_seq)
+            && now.saturating_sub(self.last_submitted_at) > timeout
+    }
+}
+
+/// the account is stuck only after its submitted tx expires, the stale tx can't be committed
+/// once its reqs are signed again by the other accounts
+pub fn stuck_timeout() -> u64 {
+    let tx_timeout = read_config(|s| s.get().tx_opt.timeout_secs).saturating_mul(1_000_000_000);
+    tx_timeout.max(ROUTE_ACCOUNT_STUCK_TIMEOUT.as_nanos() as u64)
+}
+
+/// the available account with the least pending reqs
+pub fn least_loaded(accounts: impl IntoIterator<Item = RouteAccount>) -> Option<RouteAccount> {
+    accounts
+        .into_iter()
+        .filter(RouteAccount::is_available)
+        .min_by_key(|account| (account.pending_reqs.len(), account.index))
+}
+
+/// assign the mint req to a route pool account, the req keeps the account until it is released.
+/// None means no pool account is available and the default route account signs it
+pub fn assign(req_id: &String) -> Option<u32> {
+    mutate_state(|s| {
+        if let Some((index, _)) = s
+            .route_accounts
+            .iter()
+            .find(|(_, account)| account.pending_reqs.contains(req_id))
+        {
+            return Some(index);
+        }
+        let mut account = least_loaded(s.route_accounts.iter().map(|(_, account)| account))?;
+        account.pending_reqs.push(req_id.to_owned());
+        let index = account.index;
+        s.route_accounts.insert(index, account);
+        Some(index)
+    })
+}
+
+/// release the req from its account, e.g. it is finalized or will be signed again
+pub fn release(req_id: &String) {
+    mutate_state(|s| {
+        let accounts: Vec<_> = s
+            .route_accounts
+            .iter()
+            .filter(|(_, account)| account.pending_reqs.contains(req_id))
+            .collect();
+        for (index, mut account) in accounts {
+            account.pending_reqs.retain(|id| id != req_id);
+            s.route_accounts.insert(index, account);
+        }
+    })
+}
+
+pub fn record_submitted(index: u32, seq: u64) {
+    mutate_state(|s| {
+        if let Some(mut account) = s.route_accounts.get(&index) {
+            account.last_submitted_seq = Some(seq);
+            account.last_submitted_at = crate::clock::now();
+            s.route_accounts.insert(index, account);
+        }
+    })
+}
+
+/// the `add_minter` tx for the account is finalized
+pub fn on_minter_added(address: &String) {
+    mutate_state(|s| {
+        let accounts: Vec<_> = s
+            .route_accounts
+            .iter()
+            .filter(|(_, account)| account.address == *address)
+            .collect();
+        for (index, mut account) in accounts {
+            log!(
+                INFO,
+                "[route_pool::on_minter_added] route account {} ({}) is a minter now",
+                index,
+                address
+            );
+            account.minter = true;
+            s.route_accounts.insert(index, account);
+        }
+    })
+}
+
+/// derive the new pool accounts and enqueue the `add_minter` txs for them
+pub async fn add_route_accounts(count: u32) -> Result<Vec<RouteAccount>, String> {
+    let start = read_state(|s| s.route_accounts.len()) as u32;
+    if start + count > MAX_ROUTE_ACCOUNTS {
+        return Err(format!(
+            "the route pool has {} accounts, it can't exceed {}",
+            start, MAX_ROUTE_ACCOUNTS
+        ));
+    }
+    let key_type = read_config(|s| s.get().key_type.to_owned());
+    // the multisig shares are signed for the route account only
+    if key_type == KeyType::MultiEd25519 {
+        return Err("the route pool accounts can't be derived from the multisig key".to_string());
+    }
+    let mut accounts = vec![];
+    for index in start..start + count {
+        let public_key = ck_eddsa::pool_public_key(key_type.to_owned(), index).await?;
+        let pk = RoutePublicKey::from_bytes(&key_type, &public_key).map_err(|e| e.to_string())?;
+        let address = format!("{}", pk.authentication_key().account_address());
+        let account = RouteAccount {
+            index,
+            address: address.to_owned(),
+            public_key,
+            minter: false,
+            sequence_number: 0,
+            last_submitted_seq: None,
+            last_submitted_at: 0,
+            balance: 0,
+            pending_reqs: vec![],
+            status: RouteAccountStatus::Active,
+        };
+        let req_type = ReqType::AddMinter(address);
+        let req_id = hash_with_sha256(
+            &bincode::serialize(&req_type).expect("failed to serialize add_minter req"),
+        );
+        let tx_req = TxReq {
+            req_type,
+            tx_hash: None,
+            tx_status: TxStatus::New,
+            retry: 0,
+        };
+        mutate_state(|s| {
+            s.route_accounts.insert(index, account.to_owned());
+            s.tx_queue.insert(req_id, tx_req);
+        });
+        accounts.push(account);
+    }
+    Ok(accounts)
+}
+
+pub fn set_enabled(index: u32, enabled: bool) -> Result<(), String> {
+    let mut account = read_state(|s| s.route_accounts.get(&index))
+        .ok_or(format!("not found the route account: {}", index))?;
+    if enabled {
+        account.status = RouteAccountStatus::Active;
+        mutate_state(|s| s.route_accounts.insert(index, account));
+    } else {
+        // the pending reqs are still signed by the account, the rest are moved to the
+        // other accounts once its submitted txs expire, see `refresh_route_accounts`
+        account.status = RouteAccountStatus::Disabled;
+        mutate_state(|s| s.route_accounts.insert(index, account));
+    }
+    Ok(())
+}
+
+/// refresh the sequence number and the balance of the pool accounts,
+/// and move the reqs of the stuck accounts to the others
+pub async fn refresh_route_accounts() {
+    let client = RestClient::client().with_scope(CostScope::task(TaskType::RefreshRouteAccounts));
+    let accounts: Vec<_> = read_state(|s| s.route_accounts.iter().map(|(_, a)| a).collect());
+    let stuck_timeout = stuck_timeout();
+    for mut account in accounts {
+        let onchain = match client.get_account(account.address.to_owned(), None).await {
+            Ok(onchain) => onchain,
+            Err(e) => {
+                log!(
+                    WARNING,
+                    "[route_pool::refresh_route_accounts] failed to get the route account {}: {}",
+                    account.index,
+                    e
+                );
+                continue;
+            }
+        };
+        match client
+            .get_account_balance(account.address.to_owned(), None)
+            .await
+        {
+            Ok(balance) => account.balance = balance,
+            Err(e) => log!(
+                WARNING,
+                "[route_pool::refresh_route_accounts] failed to get the balance of {}: {}",
+                account.address,
+                e
+            ),
+        }
+
+        let now = crate::clock::now();
+        account.sequence_number = onchain.sequence_number;
+        if account.status != RouteAccountStatus::Disabled
+            && account.is_stuck(onchain.sequence_number, now, stuck_timeout)
+        {
+            log!(
+                ERROR,
+                "[route_pool::refresh_route_accounts] route account {} is stuck at seq {}",
+                account.index,
+                onchain.sequence_number
+            );
+            account.last_submitted_seq = None;
+            account.status = RouteAccountStatus::Stuck { since: now };
+            reassign_pending_reqs(account, "the route account is stuck");
+            continue;
+        }
+        if account.status == RouteAccountStatus::Disabled
+            && !account.pending_reqs.is_empty()
+            && now.saturating_sub(account.last_submitted_at) > stuck_timeout
+        {
+            reassign_pending_reqs(account, "the route account is disabled");
+            continue;
+        }
+        account.status = match &account.status {
+            RouteAccountStatus::Disabled => RouteAccountStatus::Disabled,
+            // the stale txs expire after the timeout, then the account signs with the latest seq
+            RouteAccountStatus::Stuck { since }
+                if now.saturating_sub(*since) <= ROUTE_ACCOUNT_STUCK_TIMEOUT.as_nanos() as u64 =>
+            {
+                RouteAccountStatus::Stuck { since: *since }
+            }
+            _ if account.balance < MIN_ROUTE_ACCOUNT_BALANCE => {
+                log!(
+                    WARNING,
+                    "[route_pool::refresh_route_accounts] route account {} ({}) balance is low: {}",
+                    account.index,
+                    account.address,
+                    account.balance
+                );
+                RouteAccountStatus::LowBalance
+            }
+            _ => RouteAccountStatus::Active,
+        };
+        log!(
+            DEBUG,
+            "[route_pool::refresh_route_accounts] route account: {:?}",
+            account
+        );
+        mutate_state(|s| s.route_accounts.insert(account.index, account));
+    }
+}
+
+// the stale txs of the stuck account are expired, see `stuck_timeout`, so the req signed
+// again by the other account can't mint twice, whether or not the port dedups the ticket
+fn reassign_pending_reqs(mut account: RouteAccount, reason: &str) {
+    let reqs = std::mem::take(&mut account.pending_reqs);
+    mutate_state(|s| {
+        for req_id in reqs {
+            if let Some(mut req) = s.tx_queue.get(&req_id) {
+                req.tx_status = TxStatus::TxFailed {
+                    e: reason.to_string(),
+                };
+                req.tx_hash = None;
+                s.tx_queue.insert(req_id, req);
+            }
+        }
+        s.route_accounts.insert(account.index, account);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(index: u32, pending: usize, status: RouteAccountStatus) -> RouteAccount {
+        RouteAccount {
+            index,
+            address: format!("0x{}", index),
+            public_key: vec![],
+            minter: true,
+            sequence_number: 0,
+            last_submitted_seq: None,
+            last_submitted_at: 0,
+            balance: MIN_ROUTE_ACCOUNT_BALANCE,
+            pending_reqs: (0..pending).map(|i| i.to_string()).collect(),
+            status,
+        }
+    }
+
+    #[test]
+    fn assign_to_least_loaded() {
+        let accounts = vec![
+            account(0, 3, RouteAccountStatus::Active),
+            account(1, 1, RouteAccountStatus::Active),
+            account(2, 0, RouteAccountStatus::Stuck { since: 0 }),
+            account(3, 1, RouteAccountStatus::Active),
+        ];
+        assert_eq!(least_loaded(accounts).map(|a| a.index), Some(1));
+
+        let mut not_minter = account(0, 0, RouteAccountStatus::Active);
+        not_minter.minter = false;
+        assert_eq!(least_loaded(vec![not_minter]), None);
+    }
+
+    #[test]
+    fn stuck_detection() {
+        let timeout = ROUTE_ACCOUNT_STUCK_TIMEOUT.as_nanos() as u64;
+        let mut account = account(0, 1, RouteAccountStatus::Active);
+        assert!(!account.is_stuck(5, timeout * 2, timeout));
+
+        account.last_submitted_seq = Some(5);
+        account.last_submitted_at = 1;
+        // not committed yet but still in time
+        assert!(!account.is_stuck(5, timeout, timeout));
+        assert!(account.is_stuck(5, timeout + 2, timeout));
+        // the submitted tx is committed
+        assert!(!account.is_stuck(6, timeout + 2, timeout));
+    }
+}
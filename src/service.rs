@@ -2,23 +2,34 @@
 
 use crate::aptos_client::aptos_providers::Provider;
 use crate::aptos_client::constants::DEVNET_CHAIN_ID;
+use crate::aptos_client::pricing::{self, RequestCostEstimate};
 use crate::aptos_client::rest_client::{RestClient, RpcResult};
+use crate::constants::{FEE_QUOTE_SEED, MAX_PAGE_SIZE};
 
 use crate::aptos_client::{
-    rest_client, tx_builder, Account, AccountKey, AptosResult, CreateTokenReq, LocalAccount,
-    ReqType, TxOptions, TxReq, TxStatus,
+    rest_client, route_address, tx_builder, Account, AccountKey, AptosResult, CreateTokenReq,
+    LocalAccount, ReqType, State, TxOptions, TxReq, TxStatus, UpdateMetaReq,
+};
+use crate::auth::{
+    auth_approve, auth_query, is_admin, is_hub, remove_perms, set_perms, Permission,
 };
-use crate::auth::{is_admin, set_perms, Permission};
 use crate::call_error::{CallError, Reason};
-use crate::ck_eddsa::KeyType;
+use crate::ck_eddsa::{KeyAction, KeyAuditRecord, KeyType, RouteKeyId, RouteKeyInfo};
+use crate::cycles::{self, CostScope, CyclesPolicy, CyclesReport};
 use crate::guard::TaskType;
 use crate::handler::gen_ticket::{
-    self, query_tx_from_multi_rpc, GenerateTicketError, GenerateTicketOk, GenerateTicketReq,
+    self, query_tx_from_multi_rpc, FeeQuote, GenerateTicketFailure, GenerateTicketOk,
+    GenerateTicketReq, LegacyGenerateTicketError, RedeemMemo,
 };
-use crate::handler::scheduler;
-use crate::ic_log::{DEBUG, ERROR};
+use crate::handler::key_rotation::{self, KeyRotation};
+use crate::handler::{approval, fecth_directive, fetch_ticket, scheduler};
+use crate::ic_log::{DEBUG, ERROR, WARNING};
 
 use crate::memory::init_config;
+use crate::multisig::{self, MultisigConfig, SignerSource, SigningSession};
+use crate::rate_limit::{self, PausedToken, RateLimitRule};
+use crate::route_pool::{self, RouteAccount};
+use crate::ticket_quota::{self, CallerRecord, TicketQuota};
 use crate::{aptos_client, ck_eddsa};
 
 use aptos_api_types::transaction::Transaction;
@@ -33,10 +44,15 @@ use serde_json::json;
 use crate::lifecycle::{self, RouteArg, UpgradeArgs};
 
 use crate::config::{
-    mutate_config, read_config, MultiRpcConfig, RouteConfig, Seqs, SnorKeyType, NATIVE_KEY_TYPE,
+    mutate_config, read_config, FeeAsset, MultiRpcConfig, RouteConfig, Seqs, SnorKeyType,
+    NATIVE_KEY_TYPE,
+};
+use crate::state::{
+    replace_state, AptosPort, AptosToken, DirectiveRecord, DirectiveStatus, GenTicketStatus,
+    HeldMint, PendingApproval, QuarantinedTicket, ResendStatus, RouteState, TicketTimeline,
+    TokenResp, UpdateTokenStatus, UpdateType,
 };
-use crate::state::{replace_state, AptosPort, AptosToken, RouteState, TokenResp, UpdateType};
-use crate::types::{TicketId, Token, TokenId};
+use crate::types::{Directive, Seq, TicketId, Token, TokenId};
 // use crate::service::mint_token::MintTokenRequest;
 
 use crate::state::{mutate_state, read_state};
@@ -52,6 +68,7 @@ use ic_cdk::api::management_canister::http_request::{
 
 use std::str::FromStr;
 use std::time::Duration;
+use strum::IntoEnumIterator;
 
 async fn get_random_seed() -> [u8; 64] {
     match ic_cdk::api::management_canister::main::raw_rand().await {
@@ -78,11 +95,29 @@ fn init(args: RouteArg) {
             panic!("expected InitArgs got UpgradeArgs");
         }
     }
-    // init seeds
+    // init seeds, the native key seed is only created in the test builds
+    let mut seeds = vec![FEE_QUOTE_SEED.to_string()];
+    if cfg!(feature = "native-key") {
+        seeds.push(NATIVE_KEY_TYPE.to_string());
+    }
+    init_seeds(seeds);
+}
+
+// create the missing seeds with random bytes
+fn init_seeds(names: Vec<String>) {
+    let names: Vec<String> = names
+        .into_iter()
+        .filter(|name| read_state(|s| !s.seeds.contains_key(name)))
+        .collect();
+    if names.is_empty() {
+        return;
+    }
     ic_cdk_timers::set_timer(Duration::ZERO, || {
         ic_cdk::spawn(async move {
-            let seed = get_random_seed().await;
-            mutate_state(|s| s.seeds.insert(NATIVE_KEY_TYPE.to_string(), seed));
+            for name in names {
+                let seed = get_random_seed().await;
+                mutate_state(|s| s.seeds.insert(name, seed));
+            }
         });
     });
 }
@@ -107,14 +142,30 @@ fn post_upgrade(args: Option<RouteArg>) {
     }
 
     lifecycle::post_upgrade(upgrade_arg);
-    scheduler::start_schedule(None);
+    // the fee quote key is only drawn if it's missing, the issued quotes stay valid
+    init_seeds(vec![FEE_QUOTE_SEED.to_string()]);
+    // no other tx may take the seq of the rotation tx until it's confirmed
+    if key_rotation::in_progress() {
+        scheduler::start_schedule(Some(
+            TaskType::iter()
+                .filter(|task| *task != TaskType::HandleTx)
+                .collect(),
+        ));
+    } else {
+        scheduler::start_schedule(None);
+    }
     log!(DEBUG, "upgrade successfully!");
 }
 
 // devops method
 #[query(guard = "is_admin")]
 pub async fn get_route_config() -> RouteConfig {
-    read_config(|s| s.get().to_owned())
+    let mut config = read_config(|s| s.get().to_owned());
+    // never expose the native key seed
+    if let KeyType::Native(_) = &config.key_type {
+        config.key_type = KeyType::Native(vec![]);
+    }
+    config
 }
 // devops method
 #[update(guard = "is_admin", hidden = true)]
@@ -130,6 +181,45 @@ pub fn stop_schedule(tasks: Option<Vec<TaskType>>) {
     scheduler::stop_schedule(tasks);
 }
 
+// the hub pushes the tickets to the route, return the next expected ticket seq
+#[update(guard = "is_hub")]
+pub fn push_tickets(tickets: Vec<(Seq, Ticket)>) -> Seq {
+    let (next_seq, gap) = fetch_ticket::receive_pushed_tickets(tickets);
+    fetch_ticket::process_tickets();
+    if gap {
+        scheduler::reconcile_now(TaskType::GetTickets);
+    }
+    next_seq
+}
+
+// the hub pushes the directives to the route, return the next expected directive seq
+#[update(guard = "is_hub")]
+pub fn push_directives(directives: Vec<(Seq, Directive)>) -> Seq {
+    let (next_seq, gap) = fecth_directive::receive_pushed_directives(directives);
+    if gap {
+        scheduler::reconcile_now(TaskType::GetDirectives);
+    }
+    next_seq
+}
+
+#[query]
+pub fn hub_push_enabled() -> bool {
+    read_config(|s| s.get().hub_push_enabled)
+}
+
+// devops method, the polling tasks are restarted with the new interval
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_hub_push_enabled(enabled: bool) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.hub_push_enabled = enabled;
+        s.set(config);
+    });
+    let tasks = vec![TaskType::GetTickets, TaskType::GetDirectives];
+    scheduler::stop_schedule(Some(tasks.to_owned()));
+    scheduler::start_schedule(Some(tasks));
+}
+
 // devops method
 #[query(guard = "is_admin", hidden = true)]
 pub async fn active_tasks() -> Vec<TaskType> {
@@ -138,12 +228,45 @@ pub async fn active_tasks() -> Vec<TaskType> {
 
 // devops method
 #[update(guard = "is_admin", hidden = true)]
-pub async fn update_schnorr_key(key_name: String) {
+pub async fn update_schnorr_key(key_name: String) -> Result<(), String> {
+    check_key_switch()?;
+    let from = ck_eddsa::current_route_key_id();
+    let (key_type, chain_id) =
+        read_config(|s| (s.get().key_type.to_owned(), s.get().chain_id.to_owned()));
+    let derivation_path = ck_eddsa::derivation_path(&chain_id, None);
+    let to = RouteKeyId::new(&key_type, &key_name, &derivation_path);
+    let address = ck_eddsa::derived_address(key_type, key_name.to_owned(), derivation_path).await?;
     mutate_config(|s| {
         let mut config = s.get().to_owned();
         config.schnorr_key_name = key_name;
         s.set(config);
-    })
+    });
+    ck_eddsa::record_key_audit(KeyAction::SwitchKeyName, from, to, address);
+    Ok(())
+}
+
+// switching the key changes the route account address, the rotated account must
+// use `start_key_rotation` to keep its address
+fn check_key_switch() -> Result<(), String> {
+    if key_rotation::in_progress() {
+        return Err("a key rotation is in progress".to_string());
+    }
+    if read_config(|s| s.get().route_address.is_some()) {
+        return Err("the route account key is rotated, use start_key_rotation".to_string());
+    }
+    // the pool accounts keep the public keys derived with the current key
+    let pool_accounts = read_state(|s| s.route_accounts.len());
+    if pool_accounts > 0 {
+        return Err(format!(
+            "the route pool has {} accounts derived with the current key",
+            pool_accounts
+        ));
+    }
+    log!(
+        WARNING,
+        "[service::check_key_switch] the route account address changes with the key"
+    );
+    Ok(())
 }
 
 // devops method
@@ -168,50 +291,113 @@ pub async fn query_key_type() -> SnorKeyType {
     read_config(|s| s.get().key_type.to_owned().into())
 }
 
-// devops method
+// devops method, the route keys derived for every key config
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_route_keys() -> Vec<RouteKeyInfo> {
+    ck_eddsa::route_keys()
+}
+
+// devops method, the route account moves to the address of the new key type,
+// `confirm_address` must be that address, see `aptos_route_address`
 #[update(guard = "is_admin", hidden = true)]
-pub async fn update_key_type(key_type: SnorKeyType) {
+pub async fn update_key_type(key_type: SnorKeyType, confirm_address: String) -> Result<(), String> {
+    check_key_switch()?;
     let key_type = match key_type {
         SnorKeyType::ChainKey => KeyType::ChainKey,
-        SnorKeyType::Native => {
-            let seed = read_state(|s| {
-                s.seeds
-                    .get(&NATIVE_KEY_TYPE.to_string())
-                    .unwrap_or_else(|| {
-                        panic!("No key with name {:?}", &NATIVE_KEY_TYPE.to_string())
-                    })
-            });
-            KeyType::Native(seed.to_vec())
-        }
+        SnorKeyType::Native => ck_eddsa::native_key()?,
+        SnorKeyType::ChainKeySecp256k1 => KeyType::ChainKeySecp256k1,
+        SnorKeyType::MultiEd25519 => KeyType::MultiEd25519,
     };
+    let from = ck_eddsa::current_route_key_id();
+    let (chain_id, key_name) = read_config(|s| {
+        (
+            s.get().chain_id.to_owned(),
+            s.get().schnorr_key_name.to_owned(),
+        )
+    });
+    let derivation_path = ck_eddsa::derivation_path(&chain_id, None);
+    let to = RouteKeyId::new(&key_type, &key_name, &derivation_path);
+    let address = ck_eddsa::derived_address(key_type.to_owned(), key_name, derivation_path).await?;
+    let confirmed = AccountAddress::from_str(&confirm_address)
+        .map_err(|e| format!("invalid confirm address {}: {}", confirm_address, e))?;
+    if format!("{}", confirmed) != address {
+        return Err(format!(
+            "the confirm address {} doesn't match the new route address {}",
+            confirm_address, address
+        ));
+    }
     mutate_config(|s| {
         let mut config = s.get().to_owned();
         config.key_type = key_type;
         s.set(config);
+    });
+    ck_eddsa::record_key_audit(KeyAction::SwitchKeyType, from, to, address);
+    Ok(())
+}
+
+// devops method, the audit trail of the route key changes
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_key_audits(offset: u64, limit: u64) -> Vec<KeyAuditRecord> {
+    read_state(|s| {
+        s.key_audits
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(_, record)| record)
+            .collect()
     })
 }
 
+// devops method, the cycles spent on the outcalls and the signatures with the runway
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_cycles_report() -> CyclesReport {
+    cycles::report(ic_cdk::api::canister_balance128())
+}
+
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_cycles_policy() -> CyclesPolicy {
+    read_config(|s| s.get().cycles_policy.to_owned())
+}
+
+// devops method, the level is checked again with the new thresholds
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_cycles_policy(policy: CyclesPolicy) -> Result<(), String> {
+    policy.validate()?;
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.cycles_policy = policy;
+        s.set(config);
+    });
+    cycles::current_level();
+    Ok(())
+}
+
+// devops method, the dry run of the outcall and signing cost of each request type
+#[query(guard = "is_admin", hidden = true)]
+pub fn estimate_request_costs() -> Vec<RequestCostEstimate> {
+    pricing::estimate_request_costs()
+}
+
 // devops method
 #[update]
 pub async fn aptos_route_address(key_type: SnorKeyType) -> Result<String, String> {
     let key_type = match key_type {
         SnorKeyType::ChainKey => KeyType::ChainKey,
-        SnorKeyType::Native => {
-            let seed = read_state(|s| {
-                s.seeds
-                    .get(&NATIVE_KEY_TYPE.to_string())
-                    .unwrap_or_else(|| {
-                        panic!("No key with name {:?}", &NATIVE_KEY_TYPE.to_string())
-                    })
-            });
-            KeyType::Native(seed.to_vec())
-        }
+        SnorKeyType::Native => ck_eddsa::native_key()?,
+        SnorKeyType::ChainKeySecp256k1 => KeyType::ChainKeySecp256k1,
+        SnorKeyType::MultiEd25519 => KeyType::MultiEd25519,
     };
     // let address = ck_eddsa::aptos_route_address(key_type).await?;
-    let account_key = AccountKey::account_key(key_type)
+    let account_key = AccountKey::account_key(key_type.to_owned())
         .await
         .map_err(|e| e.to_string())?;
-    let account_address = account_key.authentication_key().account_address();
+    // the rotated route account keeps its address
+    let is_current = read_config(|s| s.get().key_type == key_type);
+    let account_address = if is_current {
+        route_address(&account_key).map_err(|e| e.to_string())?
+    } else {
+        account_key.authentication_key().account_address()
+    };
     let address = format!("{}", account_address);
     Ok(address)
 }
@@ -258,6 +444,22 @@ pub async fn update_tx_option(tx_opt: TxOptions) {
     })
 }
 
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn finality_depth() -> u64 {
+    read_config(|s| s.get().finality_depth())
+}
+
+// devops method
+#[update(guard = "is_admin", hidden = true)]
+pub async fn update_finality_depth(provider: Provider, depth: u64) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.finality_depths.insert(provider, depth);
+        s.set(config);
+    })
+}
+
 // query supported chain list
 #[query]
 fn get_chain_list() -> Vec<Chain> {
@@ -345,9 +547,14 @@ fn update_token(token: Token) -> Result<Option<Token>, CallError> {
                 token.token_id.to_string()
             )),
         }),
-        Some(_) => Ok(s
-            .tokens
-            .insert(token.token_id.to_string(), token.to_owned())),
+        Some(current_token) => {
+            // the changed metadata is synced to aptos by the UpdateToken task
+            s.update_token(token).map_err(|e| CallError {
+                method: "[service::update_token] update_token".to_string(),
+                reason: Reason::CanisterError(e),
+            })?;
+            Ok(Some(current_token))
+        }
     })
     // Ok(())
 }
@@ -405,6 +612,149 @@ pub async fn remove_ticket_from_quene(ticket_id: String) -> Option<Ticket> {
     })
 }
 
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_quarantined_tickets() -> Vec<QuarantinedTicket> {
+    read_state(|s| {
+        s.quarantined_tickets
+            .iter()
+            .map(|(_, quarantined)| quarantined)
+            .collect()
+    })
+}
+
+// devops method, move the quarantined ticket back to the queue to validate it again
+#[update(guard = "is_admin", hidden = true)]
+pub fn release_quarantined_ticket(ticket_id: TicketId) -> Result<(), String> {
+    fetch_ticket::release_quarantined_ticket(&ticket_id)
+}
+
+// devops method
+#[update(guard = "is_admin", hidden = true)]
+pub fn remove_quarantined_ticket(ticket_id: TicketId) -> Option<QuarantinedTicket> {
+    mutate_state(|s| s.quarantined_tickets.remove(&ticket_id))
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_held_mints() -> Vec<HeldMint> {
+    read_state(|s| s.held_mints.iter().map(|(_, held)| held).collect())
+}
+
+// devops method, approve the held mint regardless of the rate limits
+#[update(guard = "is_admin", hidden = true)]
+pub fn release_held_mint(ticket_id: TicketId) -> Result<(), String> {
+    fetch_ticket::release_held_mint(&ticket_id)
+}
+
+// devops method
+#[update(guard = "is_admin", hidden = true)]
+pub fn remove_held_mint(ticket_id: TicketId) -> Option<HeldMint> {
+    mutate_state(|s| s.held_mints.remove(&ticket_id))
+}
+
+#[query(guard = "auth_query")]
+pub fn get_pending_approvals() -> Vec<PendingApproval> {
+    read_state(|s| {
+        s.pending_approvals
+            .iter()
+            .map(|(_, pending)| pending)
+            .collect()
+    })
+}
+
+// return true once the mint has enough approvals and is queued
+#[update(guard = "auth_approve")]
+pub fn approve_mint(ticket_id: TicketId) -> Result<bool, String> {
+    approval::approve(&ticket_id, ic_cdk::caller().to_string())
+}
+
+// reject the mint and refund it to the source chain
+#[update(guard = "auth_approve")]
+pub async fn reject_mint(ticket_id: TicketId, reason: String) -> Result<(), String> {
+    approval::reject(&ticket_id, ic_cdk::caller().to_string(), reason).await
+}
+
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_approval_thresholds() -> Vec<(TokenId, u128)> {
+    read_config(|s| s.get().approval_thresholds.to_owned().into_iter().collect())
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_approval_threshold(token_id: TokenId, threshold: Option<u128>) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        match threshold {
+            Some(threshold) => config.approval_thresholds.insert(token_id, threshold),
+            None => config.approval_thresholds.remove(&token_id),
+        };
+        s.set(config);
+    })
+}
+
+// the distinct approvers required, 1 or 2
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_required_approvals(required_approvals: u8) -> Result<(), String> {
+    if !(1..=2).contains(&required_approvals) {
+        return Err(format!(
+            "required approvals must be 1 or 2, got {}",
+            required_approvals
+        ));
+    }
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.required_approvals = required_approvals;
+        s.set(config);
+    });
+    Ok(())
+}
+
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_rate_limits() -> Vec<RateLimitRule> {
+    read_config(|s| s.get().rate_limits.to_owned())
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_rate_limits(rate_limits: Vec<RateLimitRule>) -> Result<(), String> {
+    rate_limit::update_rules(rate_limits)
+}
+
+#[query]
+pub fn get_ticket_quota() -> TicketQuota {
+    read_config(|s| s.get().ticket_quota.to_owned())
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_ticket_quota(quota: TicketQuota) {
+    ticket_quota::update_quota(quota)
+}
+
+// devops method, the failed verifications and the ban of the generate_ticket caller
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_ticket_caller(caller: Principal) -> Option<CallerRecord> {
+    read_state(|s| s.ticket_callers.get(&caller.to_string()))
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn unban_ticket_caller(caller: Principal) -> Option<CallerRecord> {
+    ticket_quota::unban(&caller)
+}
+
+#[query]
+pub fn get_paused_tokens() -> Vec<(TokenId, PausedToken)> {
+    read_config(|s| s.get().paused_tokens.to_owned().into_iter().collect())
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn pause_token(token_id: TokenId, reason: String) {
+    rate_limit::pause_token(token_id, reason, false)
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub fn resume_token(token_id: TokenId) -> Option<PausedToken> {
+    rate_limit::resume_token(&token_id)
+}
+
 // query collect fee account
 #[query]
 pub async fn get_fee_account() -> String {
@@ -427,6 +777,63 @@ pub fn get_redeem_fee(chain_id: ChainId) -> Option<u128> {
     read_config(|s| s.get().get_fee(chain_id))
 }
 
+// quote the redeem fee for the dst chain, the quote is accepted until it expires
+#[query]
+pub fn get_redeem_fee_quote(chain_id: ChainId, fee_token: Option<TokenId>) -> Option<FeeQuote> {
+    gen_ticket::quote_fee(chain_id, fee_token)
+}
+
+// query the validity period of the redeem fee quote, in seconds
+#[query(guard = "is_admin", hidden = true)]
+pub fn fee_quote_ttl() -> u64 {
+    read_config(|s| s.get().fee_quote_ttl)
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub async fn update_fee_quote_ttl(fee_quote_ttl: u64) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.fee_quote_ttl = fee_quote_ttl;
+        s.set(config);
+    })
+}
+
+// query the redeem fee paid in the given fee token for the dst chain
+#[query]
+pub fn get_redeem_fee_in(chain_id: ChainId, fee_token: TokenId) -> Option<u128> {
+    read_config(|s| s.get().get_fee_in(chain_id, &fee_token))
+}
+
+// query the fee assets accepted to pay the redeem fee
+#[query]
+pub fn get_fee_assets() -> Vec<(TokenId, FeeAsset)> {
+    read_config(|s| {
+        s.get()
+            .fee_assets
+            .iter()
+            .map(|(fee_token, fee_asset)| (fee_token.to_owned(), fee_asset.to_owned()))
+            .collect()
+    })
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub async fn add_fee_asset(fee_token: TokenId, fee_asset: FeeAsset) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.fee_assets.insert(fee_token, fee_asset);
+        s.set(config);
+    })
+}
+
+#[update(guard = "is_admin", hidden = true)]
+pub async fn remove_fee_asset(fee_token: TokenId) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.fee_assets.remove(&fee_token);
+        s.set(config);
+    })
+}
+
 #[update(guard = "is_admin", hidden = true)]
 pub async fn update_redeem_fee(fee: Factor) {
     mutate_config(|s| {
@@ -456,7 +863,221 @@ pub fn get_failed_ticket_to_hub(ticket_id: String) -> Option<Ticket> {
 // devops method
 #[update(guard = "is_admin", hidden = true)]
 pub async fn remove_failed_tickets_to_hub(ticket_id: String) -> Option<Ticket> {
-    mutate_state(|state| state.tickets_failed_to_hub.remove(&ticket_id))
+    mutate_state(|state| {
+        state.resend_tickets.remove(&ticket_id);
+        state.tickets_failed_to_hub.remove(&ticket_id)
+    })
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_resend_tickets() -> Vec<(TicketId, ResendStatus)> {
+    read_state(|s| s.resend_tickets.iter().collect())
+}
+
+// query the timeline of the ticket, both for the inbound and the redeem tickets
+#[query]
+pub fn get_ticket_status(ticket_id: TicketId) -> Option<TicketTimeline> {
+    read_state(|s| s.ticket_timelines.get(&ticket_id))
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_ticket_timelines(offset: u64, limit: u64) -> Vec<TicketTimeline> {
+    read_state(|s| {
+        s.ticket_timelines
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(_, timeline)| timeline)
+            .collect()
+    })
+}
+
+// devops method, the directives from hub with their outcomes
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_directive_records(offset: u64, limit: u64) -> Vec<DirectiveRecord> {
+    read_state(|s| {
+        s.directive_records
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(_, record)| record)
+            .collect()
+    })
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_failed_directives() -> Vec<DirectiveRecord> {
+    read_state(|s| {
+        s.directive_records
+            .iter()
+            .filter(|(_, record)| matches!(record.status, DirectiveStatus::Failed { .. }))
+            .map(|(_, record)| record)
+            .collect()
+    })
+}
+
+// devops method, validate and apply the failed directive again
+#[update(guard = "is_admin", hidden = true)]
+pub fn retry_directive(seq: Seq) -> Result<(), String> {
+    fecth_directive::apply_directive(seq)
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_token_meta_updates() -> Vec<UpdateTokenStatus> {
+    read_state(|s| {
+        s.update_token_queue
+            .iter()
+            .map(|(_, update)| update)
+            .collect()
+    })
+}
+
+// devops method, compare the token with the on-chain fa metadata again
+#[update(guard = "is_admin", hidden = true)]
+pub fn sync_token_meta(token_id: TokenId) -> Result<(), String> {
+    mutate_state(|s| {
+        let token = s
+            .tokens
+            .get(&token_id)
+            .ok_or(format!("not found token: {}", token_id))?;
+        let fa_obj = s
+            .aptos_tokens
+            .get(&token_id)
+            .and_then(|aptos_token| aptos_token.fa_obj_id)
+            .ok_or(format!("the fa obj of {} is not created yet", token_id))?;
+        let req = UpdateMetaReq {
+            token_id: token_id.to_owned(),
+            fa_obj,
+            name: Some(token.name),
+            symbol: Some(token.symbol),
+            decimals: None,
+            icon_uri: token.icon,
+            project_uri: None,
+        };
+        s.update_token_queue
+            .insert(token_id.to_owned(), UpdateTokenStatus::new(token_id, req));
+        Ok(())
+    })
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_route_accounts() -> Vec<RouteAccount> {
+    read_state(|s| {
+        s.route_accounts
+            .iter()
+            .map(|(_, account)| account)
+            .collect()
+    })
+}
+
+// devops method, derive the new route pool accounts and authorize them to mint on the port
+#[update(guard = "is_admin", hidden = true)]
+pub async fn add_route_accounts(count: u32) -> Result<Vec<RouteAccount>, String> {
+    route_pool::add_route_accounts(count).await
+}
+
+// devops method, the disabled account's pending reqs are signed by the others once its txs expire
+#[update(guard = "is_admin", hidden = true)]
+pub fn update_route_account_status(index: u32, enabled: bool) -> Result<(), String> {
+    route_pool::set_enabled(index, enabled)
+}
+
+// devops method, rotate the route account authentication key and keep its address
+#[update(guard = "is_admin", hidden = true)]
+pub async fn start_key_rotation(
+    key_type: SnorKeyType,
+    key_name: Option<String>,
+) -> Result<KeyRotation, String> {
+    key_rotation::start(key_type, key_name).await
+}
+
+// devops method, switch to the new key once the rotation tx is committed
+#[update(guard = "is_admin", hidden = true)]
+pub async fn confirm_key_rotation() -> Result<KeyRotation, String> {
+    key_rotation::confirm().await
+}
+
+// devops method, give up the rotation once its tx can't be committed anymore
+#[update(guard = "is_admin", hidden = true)]
+pub async fn cancel_key_rotation() -> Result<KeyRotation, String> {
+    key_rotation::cancel().await
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_key_rotation() -> Option<KeyRotation> {
+    read_config(|s| s.get().key_rotation.to_owned())
+}
+
+// devops method, set the signers of the multisig route account, the key of the `ChainKey`
+// signer is derived by the route. It returns the address to confirm `update_key_type`
+#[update(guard = "is_admin", hidden = true)]
+pub async fn update_multisig(mut multisig: MultisigConfig) -> Result<String, String> {
+    if read_config(|s| s.get().key_type == KeyType::MultiEd25519) {
+        return Err("the multisig route account is in use, switch the key type first".to_string());
+    }
+    let (chain_id, key_name) = read_config(|s| {
+        (
+            s.get().chain_id.to_owned(),
+            s.get().schnorr_key_name.to_owned(),
+        )
+    });
+    for signer in multisig.signers.iter_mut() {
+        if signer.source == SignerSource::ChainKey {
+            signer.public_key = ck_eddsa::cached_public_key(
+                KeyType::ChainKey,
+                key_name.to_owned(),
+                ck_eddsa::derivation_path(&chain_id, None),
+            )
+            .await?;
+        }
+    }
+    multisig.validate()?;
+    let address = multisig.address()?;
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.multisig = Some(multisig);
+        s.set(config);
+    });
+    Ok(address)
+}
+
+// devops method
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_multisig() -> Option<MultisigConfig> {
+    read_config(|s| s.get().multisig.to_owned())
+}
+
+// devops method, the multisig txs waiting for the signature shares
+#[query(guard = "is_admin", hidden = true)]
+pub fn get_signing_sessions() -> Vec<SigningSession> {
+    read_state(|s| {
+        s.multisig_sessions
+            .iter()
+            .map(|(_, session)| session)
+            .collect()
+    })
+}
+
+// devops method, the offline signer signs the `signing_message` of the session
+#[update(guard = "is_admin", hidden = true)]
+pub fn submit_signature_share(
+    seq: u64,
+    signer_index: u8,
+    signature: Vec<u8>,
+) -> Result<SigningSession, String> {
+    multisig::submit_share(seq, signer_index, signature)
+}
+
+// query the status of the generate ticket request
+#[query]
+pub fn get_gen_ticket_status(ticket_id: TicketId) -> GenTicketStatus {
+    read_state(|s| s.gen_ticket_status(&ticket_id))
 }
 
 // devops method
@@ -481,6 +1102,12 @@ pub async fn set_permissions(caller: Principal, perm: Permission) {
     set_perms(caller.to_string(), perm)
 }
 
+// devops method
+#[update(guard = "is_admin", hidden = true)]
+pub async fn remove_permissions(caller: Principal, perm: Permission) {
+    remove_perms(caller.to_string(), perm)
+}
+
 // devops method
 #[update(guard = "is_admin", hidden = true)]
 pub fn debug(enable: bool) {
@@ -576,7 +1203,7 @@ pub async fn fa_obj_from_port(view_func: String, token_id: String) -> Result<Vec
 pub async fn submit_tx(req: ReqType) -> Result<String, String> {
     log!(DEBUG, "[service::submit_tx] TxReq: {:?} ", req);
 
-    let mut local_account = LocalAccount::local_account()
+    let mut local_account = LocalAccount::local_account(CostScope::default().with_req(&req))
         .await
         .map_err(|e| e.to_string())?;
 
@@ -696,15 +1323,44 @@ async fn valid_tx_from_multi_rpc(tx_hash: String) -> Result<String, String> {
     // let tx_digest = TransactionDigest::from_str(digest.as_ref()).unwrap();
     let tx_response =
         query_tx_from_multi_rpc(&client, tx_hash, multi_rpc_config.rpc_list.to_owned()).await;
-    let json_response = multi_rpc_config.valid_and_get_result(&tx_response)?;
-    let ret = serde_json::to_string(&json_response).map_err(|err| err.to_string())?;
+    let valid_tx = multi_rpc_config.valid_and_get_result(&tx_response)?;
+    let ret = serde_json::to_string(&valid_tx).map_err(|err| err.to_string())?;
     Ok(ret)
 }
 
-// generate ticket ,called by front end or other sys
+// generate ticket ,called by front end or other sys.
+// the caller may attach `TicketQuota.fee_cycles` to skip the call limits
+#[update]
+async fn generate_ticket(
+    args: GenerateTicketReq,
+) -> Result<GenerateTicketOk, LegacyGenerateTicketError> {
+    gen_ticket::generate_ticket(ic_cdk::caller(), args)
+        .await
+        .map_err(LegacyGenerateTicketError::from)
+}
+
+// generate ticket with the structured failure reason and the retryable flag
 #[update]
-async fn generate_ticket(args: GenerateTicketReq) -> Result<GenerateTicketOk, GenerateTicketError> {
-    gen_ticket::generate_ticket(args).await
+async fn generate_ticket_v2(
+    args: GenerateTicketReq,
+) -> Result<GenerateTicketOk, GenerateTicketFailure> {
+    gen_ticket::generate_ticket(ic_cdk::caller(), args)
+        .await
+        .map_err(GenerateTicketFailure::from)
+}
+
+// build the memo for `BurnTokenReq.memo`, it binds the redeem target chain, receiver
+// and the optional fee quote
+#[query]
+fn redeem_memo(
+    target_chain_id: ChainId,
+    receiver: String,
+    memo: Option<String>,
+    fee_quote: Option<FeeQuote>,
+) -> String {
+    RedeemMemo::new(target_chain_id, receiver, memo)
+        .with_fee_quote(fee_quote)
+        .encode()
 }
 
 // devops method
@@ -725,6 +1381,16 @@ fn cleanup_response(mut args: TransformArgs) -> TransformedHttpResponse {
     args.response
 }
 
+// keep only the ledger info headers, rounded down so that the replicas reach consensus
+#[query(hidden = true)]
+fn cleanup_ledger_response(mut args: TransformArgs) -> TransformedHttpResponse {
+    args.response.headers = State::from_headers(&args.response.headers)
+        .map(|state| state.rounded().to_headers())
+        .unwrap_or_default();
+    args.response.body.clear();
+    args.response
+}
+
 #[query(hidden = true)]
 fn http_request(req: HttpRequest) -> HttpResponse {
     match req.path() {
@@ -1,7 +1,10 @@
 #![allow(unused)]
-use crate::aptos_client::{CreateTokenReq, ReqType, TxReq, TxStatus, UpdateMetaReq};
-use crate::ck_eddsa::{hash_with_sha256, KeyType};
+use crate::aptos_client::{CreateTokenReq, MintTokenReq, ReqType, TxReq, TxStatus, UpdateMetaReq};
+use crate::ck_eddsa::{hash_with_sha256, KeyAuditRecord, KeyType, RouteKeyId, RouteKeyRecord};
 use crate::config::{mutate_config, read_config, RouteConfig};
+use crate::constants::{MAX_TICKET_EVENTS, RESEND_TICKET_BACKOFF_BASE, RESEND_TICKET_BACKOFF_MAX};
+use crate::cycles::CycleStats;
+use crate::decimals;
 
 use crate::handler::gen_ticket::GenerateTicketReq;
 // use crate::handler::mint_token::MintTokenRequest;
@@ -11,13 +14,17 @@ use crate::ic_log::DEBUG;
 // use crate::handler::gen_ticket::GenerateTicketReq;
 use crate::lifecycle::InitArgs;
 use crate::memory::Memory;
+use crate::multisig::SigningSession;
+use crate::rate_limit::RateUsage;
+use crate::route_pool::RouteAccount;
+use crate::ticket_quota::CallerRecord;
 use candid::{CandidType, Principal};
 use ic_canister_log::log;
 use ic_stable_structures::StableBTreeMap;
 use ic_stable_structures::StableCell;
 
 // use crate::handler::mint_token::MintTokenRequest;
-use crate::types::{Chain, ChainId, Ticket, TicketId, ToggleState, Token, TokenId};
+use crate::types::{Chain, ChainId, Directive, Seq, Ticket, TicketId, ToggleState, Token, TokenId};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::Storable;
 use serde::{Deserialize, Serialize};
@@ -182,6 +189,261 @@ impl Storable for AptosToken {
     const BOUND: Bound = Bound::Unbounded;
 }
 
+/// The resend status of a ticket failed to send to hub
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResendStatus {
+    pub retry: u64,
+    pub first_failed_at: u64,
+    pub next_retry_at: u64,
+    pub last_error: Option<String>,
+    pub sent_at: Option<u64>,
+    pub alerted: bool,
+}
+
+impl ResendStatus {
+    pub fn new(now: u64, error: String) -> Self {
+        Self {
+            retry: 0,
+            first_failed_at: now,
+            next_retry_at: now + RESEND_TICKET_BACKOFF_BASE.as_nanos() as u64,
+            last_error: Some(error),
+            sent_at: None,
+            alerted: false,
+        }
+    }
+
+    /// exponential backoff for the next retry, capped by `RESEND_TICKET_BACKOFF_MAX`
+    pub fn failed(&mut self, now: u64, error: String) {
+        self.retry += 1;
+        let backoff = RESEND_TICKET_BACKOFF_BASE
+            .saturating_mul(2u32.saturating_pow(self.retry.min(16) as u32))
+            .min(RESEND_TICKET_BACKOFF_MAX);
+        self.next_retry_at = now + backoff.as_nanos() as u64;
+        self.last_error = Some(error);
+    }
+}
+
+impl Storable for ResendStatus {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize ResendStatus");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize ResendStatus")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TicketDirection {
+    // from hub to aptos
+    Inbound,
+    // from aptos to hub
+    Outbound,
+}
+
+/// The stage a ticket went through
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TicketStage {
+    ReceivedFromHub {
+        seq: u64,
+    },
+    Quarantined {
+        reason: String,
+    },
+    Queued {
+        req_id: String,
+    },
+    Signed,
+    Submitted {
+        tx_hash: String,
+    },
+    Committed {
+        tx_hash: String,
+        version: Option<u64>,
+    },
+    TxFailed {
+        error: String,
+    },
+    ReportedToHub {
+        tx_hash: String,
+    },
+    ReportToHubFailed {
+        error: String,
+    },
+    RedeemRequested,
+    VerifyFailed {
+        error: String,
+    },
+    Verified {
+        fee_token: String,
+        fee_amount: u128,
+    },
+    SentToHub,
+    SendToHubFailed {
+        error: String,
+    },
+    Released,
+    Held {
+        reason: String,
+    },
+    PendingApproval {
+        threshold: u128,
+    },
+    Approved {
+        approver: String,
+    },
+    Rejected {
+        approver: String,
+        reason: String,
+    },
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TicketEvent {
+    pub stage: TicketStage,
+    pub timestamp: u64,
+}
+
+/// The timeline of a ticket, keeps the latest `MAX_TICKET_EVENTS` transitions
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TicketTimeline {
+    pub ticket_id: TicketId,
+    pub direction: TicketDirection,
+    pub events: Vec<TicketEvent>,
+}
+
+impl TicketTimeline {
+    pub fn latest(&self) -> Option<&TicketEvent> {
+        self.events.last()
+    }
+}
+
+impl Storable for TicketTimeline {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize TicketTimeline");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize TicketTimeline")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The ticket from hub failed to pass the validation, it's kept for review
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuarantinedTicket {
+    pub seq: u64,
+    pub ticket: Ticket,
+    pub reason: String,
+    pub quarantined_at: u64,
+}
+
+impl Storable for QuarantinedTicket {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize QuarantinedTicket");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize QuarantinedTicket")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DirectiveStatus {
+    Pending,
+    Applied,
+    Failed { error: String },
+    // a later directive for the same target is applied, the failed one is stale
+    Superseded { by: Seq },
+}
+
+/// The directive from hub with its outcome, kept as the audit trail
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DirectiveRecord {
+    pub seq: Seq,
+    pub hash: String,
+    pub directive: Directive,
+    pub status: DirectiveStatus,
+    pub attempts: u64,
+    pub received_at: u64,
+    pub updated_at: u64,
+}
+
+impl Storable for DirectiveRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize DirectiveRecord");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize DirectiveRecord")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The mint held by the rate limits or the paused token, released by the admin
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeldMint {
+    pub seq: u64,
+    pub ticket: Ticket,
+    pub reason: String,
+    pub held_at: u64,
+}
+
+impl Storable for HeldMint {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize HeldMint");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize HeldMint")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The mint above the approval threshold, waiting for the approvers
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingApproval {
+    pub ticket: Ticket,
+    pub mint_req: MintTokenReq,
+    pub threshold: u128,
+    pub approvers: Vec<String>,
+    pub created_at: u64,
+}
+
+impl Storable for PendingApproval {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize PendingApproval");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize PendingApproval")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The user-facing status of a generate ticket request
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GenTicketStatus {
+    Unknown,
+    Pending,
+    WaitingForHub(ResendStatus),
+    SentToHub,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct RouteState {
     // stable storage
@@ -202,8 +464,8 @@ pub struct RouteState {
     pub gen_ticket_reqs: StableBTreeMap<TicketId, GenerateTicketReq, Memory>,
     #[serde(skip, default = "crate::memory::init_seed")]
     pub seeds: StableBTreeMap<String, [u8; 64], Memory>,
-    #[serde(skip, default = "crate::memory::init_route_addresses")]
-    pub route_addresses: StableBTreeMap<KeyType, Vec<u8>, Memory>,
+    #[serde(skip, default = "crate::memory::init_route_keys")]
+    pub route_keys: StableBTreeMap<RouteKeyId, RouteKeyRecord, Memory>,
     #[serde(skip, default = "crate::memory::init_aptos_ports")]
     pub aptos_ports: StableBTreeMap<String, AptosPort, Memory>,
     #[serde(skip, default = "crate::memory::init_aptos_tokens")]
@@ -212,6 +474,30 @@ pub struct RouteState {
     //TODO: refactor tx queue key as hash_with_sha256
     #[serde(skip, default = "crate::memory::init_tx_queue")]
     pub tx_queue: StableBTreeMap<String, TxReq, Memory>,
+    #[serde(skip, default = "crate::memory::init_resend_tickets")]
+    pub resend_tickets: StableBTreeMap<TicketId, ResendStatus, Memory>,
+    #[serde(skip, default = "crate::memory::init_ticket_timelines")]
+    pub ticket_timelines: StableBTreeMap<TicketId, TicketTimeline, Memory>,
+    #[serde(skip, default = "crate::memory::init_quarantined_tickets")]
+    pub quarantined_tickets: StableBTreeMap<TicketId, QuarantinedTicket, Memory>,
+    #[serde(skip, default = "crate::memory::init_rate_usages")]
+    pub rate_usages: StableBTreeMap<String, RateUsage, Memory>,
+    #[serde(skip, default = "crate::memory::init_held_mints")]
+    pub held_mints: StableBTreeMap<TicketId, HeldMint, Memory>,
+    #[serde(skip, default = "crate::memory::init_pending_approvals")]
+    pub pending_approvals: StableBTreeMap<TicketId, PendingApproval, Memory>,
+    #[serde(skip, default = "crate::memory::init_directive_records")]
+    pub directive_records: StableBTreeMap<Seq, DirectiveRecord, Memory>,
+    #[serde(skip, default = "crate::memory::init_route_accounts")]
+    pub route_accounts: StableBTreeMap<u32, RouteAccount, Memory>,
+    #[serde(skip, default = "crate::memory::init_key_audits")]
+    pub key_audits: StableBTreeMap<u64, KeyAuditRecord, Memory>,
+    #[serde(skip, default = "crate::memory::init_multisig_sessions")]
+    pub multisig_sessions: StableBTreeMap<u64, SigningSession, Memory>,
+    #[serde(skip, default = "crate::memory::init_cycle_stats")]
+    pub cycle_stats: StableBTreeMap<String, CycleStats, Memory>,
+    #[serde(skip, default = "crate::memory::init_ticket_callers")]
+    pub ticket_callers: StableBTreeMap<String, CallerRecord, Memory>,
 }
 
 impl RouteState {
@@ -228,12 +514,84 @@ impl RouteState {
             // ),
             gen_ticket_reqs: StableBTreeMap::init(crate::memory::get_gen_ticket_req_memory()),
             seeds: StableBTreeMap::init(crate::memory::get_seeds_memory()),
-            route_addresses: StableBTreeMap::init(crate::memory::get_route_addresses_memory()),
+            route_keys: StableBTreeMap::init(crate::memory::get_route_keys_memory()),
             aptos_ports: StableBTreeMap::init(crate::memory::get_aptos_ports_memory()),
             aptos_tokens: StableBTreeMap::init(crate::memory::get_aptos_tokens_memory()),
             tx_queue: StableBTreeMap::init(crate::memory::get_tx_queue_memory()),
+            resend_tickets: StableBTreeMap::init(crate::memory::get_resend_tickets_memory()),
+            ticket_timelines: StableBTreeMap::init(crate::memory::get_ticket_timelines_memory()),
+            quarantined_tickets: StableBTreeMap::init(
+                crate::memory::get_quarantined_tickets_memory(),
+            ),
+            rate_usages: StableBTreeMap::init(crate::memory::get_rate_usages_memory()),
+            held_mints: StableBTreeMap::init(crate::memory::get_held_mints_memory()),
+            pending_approvals: StableBTreeMap::init(crate::memory::get_pending_approvals_memory()),
+            directive_records: StableBTreeMap::init(crate::memory::get_directive_records_memory()),
+            route_accounts: StableBTreeMap::init(crate::memory::get_route_accounts_memory()),
+            key_audits: StableBTreeMap::init(crate::memory::get_key_audits_memory()),
+            multisig_sessions: StableBTreeMap::init(crate::memory::get_multisig_sessions_memory()),
+            cycle_stats: StableBTreeMap::init(crate::memory::get_cycle_stats_memory()),
+            ticket_callers: StableBTreeMap::init(crate::memory::get_ticket_callers_memory()),
+        }
+    }
+    /// keep the ticket failed to send to hub, it will be resent by the `ResendTickets` task
+    pub fn add_failed_ticket(&mut self, ticket: Ticket, error: String) {
+        self.resend_tickets.insert(
+            ticket.ticket_id.to_owned(),
+            ResendStatus::new(crate::clock::now(), error),
+        );
+        self.tickets_failed_to_hub
+            .insert(ticket.ticket_id.to_owned(), ticket);
+    }
+
+    /// append the stage to the ticket timeline
+    pub fn record_ticket_stage(
+        &mut self,
+        ticket_id: &TicketId,
+        direction: TicketDirection,
+        stage: TicketStage,
+    ) {
+        let mut timeline = self
+            .ticket_timelines
+            .get(ticket_id)
+            .unwrap_or(TicketTimeline {
+                ticket_id: ticket_id.to_owned(),
+                direction,
+                events: vec![],
+            });
+        timeline.events.push(TicketEvent {
+            stage,
+            timestamp: crate::clock::now(),
+        });
+        if timeline.events.len() > MAX_TICKET_EVENTS {
+            let overflow = timeline.events.len() - MAX_TICKET_EVENTS;
+            timeline.events.drain(..overflow);
+        }
+        self.ticket_timelines.insert(ticket_id.to_owned(), timeline);
+    }
+
+    /// the resent ticket leaves `resend_tickets` once it's sent, the timeline keeps the
+    /// `SentToHub` stage until it's pruned
+    pub fn gen_ticket_status(&self, ticket_id: &TicketId) -> GenTicketStatus {
+        let sent = || {
+            self.ticket_timelines
+                .get(ticket_id)
+                .is_some_and(|timeline| {
+                    timeline
+                        .events
+                        .iter()
+                        .any(|event| event.stage == TicketStage::SentToHub)
+                })
+        };
+        match self.resend_tickets.get(ticket_id) {
+            Some(status) if status.sent_at.is_some() => GenTicketStatus::SentToHub,
+            Some(status) => GenTicketStatus::WaitingForHub(status),
+            None if sent() => GenTicketStatus::SentToHub,
+            None if self.gen_ticket_reqs.contains_key(ticket_id) => GenTicketStatus::Pending,
+            None => GenTicketStatus::Unknown,
         }
     }
+
     pub fn add_chain(&mut self, chain: Chain) {
         self.counterparties
             .insert(chain.chain_id.to_owned(), chain.to_owned());
@@ -246,11 +604,19 @@ impl RouteState {
             let aptos_token = AptosToken::default();
             self.aptos_tokens
                 .insert(token.token_id.to_owned(), aptos_token.to_owned());
+            let fa_decimals = decimals::fa_decimals_for(token.decimals);
+            mutate_config(|s| {
+                let mut config = s.get().to_owned();
+                config
+                    .fa_decimals
+                    .insert(token.token_id.to_owned(), fa_decimals);
+                s.set(config);
+            });
             let create_token_req = CreateTokenReq {
                 token_id: token.token_id.to_owned(),
                 name: token.name.to_owned(),
                 symbol: token.symbol.to_owned(),
-                decimals: token.decimals.to_owned(),
+                decimals: fa_decimals,
                 icon_uri: token.icon.to_owned().unwrap_or_default(),
                 max_supply: None,
                 project_uri: token
@@ -274,41 +640,42 @@ impl RouteState {
         }
     }
 
-    pub fn update_token(&mut self, update_token: Token) {
-        self.tokens
-            .insert(update_token.token_id.to_owned(), update_token.to_owned());
-        if let Some(current_token) = self.tokens.get(&update_token.token_id) {
-            // only update name,symbol and icon
-            if !current_token.name.eq(&update_token.name)
-                || !current_token.symbol.eq(&update_token.symbol)
-                || !current_token.icon.eq(&update_token.icon)
-            {
-                let aptos_token = read_state(|s| s.aptos_tokens.get(&update_token.token_id))
-                    .expect("aptos token is None");
-                let update_meta_req = UpdateMetaReq {
-                    token_id: update_token.token_id.to_owned(),
-                    fa_obj: aptos_token
-                        .fa_obj_id
-                        .expect("fungible asset object id is None"),
-                    name: Some(update_token.name.to_owned()),
-                    symbol: Some(update_token.symbol.to_owned()),
-                    decimals: None,
-                    icon_uri: update_token.icon.to_owned(),
-                    project_uri: None,
-                };
-                let req_id = hash_with_sha256(
-                    &bincode::serialize(&update_meta_req)
-                        .expect("failed to serialize update_meta_req"),
-                );
-                let tx_req = TxReq {
-                    req_type: ReqType::UpdateMeta(update_meta_req.to_owned()),
-                    tx_hash: None,
-                    tx_status: TxStatus::New,
-                    retry: 0,
-                };
-                mutate_state(|s| s.tx_queue.insert(req_id, tx_req));
-            }
+    /// update the hub token, the changed name, symbol or icon is queued into `update_token_queue`
+    /// and synced to the on-chain fa metadata by the `UpdateToken` task
+    pub fn update_token(&mut self, update_token: Token) -> Result<(), String> {
+        let Some(current_token) = self
+            .tokens
+            .insert(update_token.token_id.to_owned(), update_token.to_owned())
+        else {
+            return Ok(());
+        };
+        // only update name,symbol and icon
+        if current_token.name.eq(&update_token.name)
+            && current_token.symbol.eq(&update_token.symbol)
+            && current_token.icon.eq(&update_token.icon)
+        {
+            return Ok(());
         }
+        // the fa obj is resolved again when syncing, it may not be created yet
+        let fa_obj = self
+            .aptos_tokens
+            .get(&update_token.token_id)
+            .and_then(|aptos_token| aptos_token.fa_obj_id)
+            .unwrap_or_default();
+        let update_meta_req = UpdateMetaReq {
+            token_id: update_token.token_id.to_owned(),
+            fa_obj,
+            name: Some(update_token.name.to_owned()),
+            symbol: Some(update_token.symbol.to_owned()),
+            decimals: None,
+            icon_uri: update_token.icon.to_owned(),
+            project_uri: None,
+        };
+        self.update_token_queue.insert(
+            update_token.token_id.to_owned(),
+            UpdateTokenStatus::new(update_token.token_id, update_meta_req),
+        );
+        Ok(())
     }
 
     pub fn toggle_chain_state(&mut self, toggle: ToggleState) {
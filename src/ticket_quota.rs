@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+
+use candid::{CandidType, Principal};
+use ic_canister_log::log;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{mutate_config, read_config};
+use crate::constants::{
+    DEFAULT_TICKET_BAN_SECS, DEFAULT_TICKET_CALLER_MAX_CALLS, DEFAULT_TICKET_FAILURE_WINDOW_SECS,
+    DEFAULT_TICKET_GLOBAL_MAX_CALLS, DEFAULT_TICKET_MAX_FAILURES, DEFAULT_TICKET_QUOTA_WINDOW_SECS,
+};
+use crate::ic_log::{INFO, WARNING};
+use crate::rate_limit::RateUsage;
+use crate::state::{mutate_state, read_state};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// The anti-abuse limits of `generate_ticket`, each call costs the outcalls to
+/// every provider in `multi_rpc_config`
+#[derive(CandidType, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TicketQuota {
+    pub window_secs: u64,
+    pub caller_max_calls: u64,
+    pub global_max_calls: u64,
+    // the caller attaching these cycles pays for the outcalls and skips the call limits,
+    // None disables the payment
+    pub fee_cycles: Option<u128>,
+    // the caller is banned once its verifications failed too often
+    pub max_failures: u64,
+    pub failure_window_secs: u64,
+    pub ban_secs: u64,
+}
+
+impl Default for TicketQuota {
+    fn default() -> Self {
+        Self {
+            window_secs: DEFAULT_TICKET_QUOTA_WINDOW_SECS,
+            caller_max_calls: DEFAULT_TICKET_CALLER_MAX_CALLS,
+            global_max_calls: DEFAULT_TICKET_GLOBAL_MAX_CALLS,
+            fee_cycles: None,
+            max_failures: DEFAULT_TICKET_MAX_FAILURES,
+            failure_window_secs: DEFAULT_TICKET_FAILURE_WINDOW_SECS,
+            ban_secs: DEFAULT_TICKET_BAN_SECS,
+        }
+    }
+}
+
+/// The failed verifications and the ban of a `generate_ticket` caller
+#[derive(CandidType, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CallerRecord {
+    // the timestamps of the failed verifications, in nanoseconds
+    pub failures: Vec<u64>,
+    pub banned_until: Option<u64>,
+    pub total_failures: u64,
+}
+
+impl Storable for CallerRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = bincode::serialize(&self).expect("failed to serialize CallerRecord");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        bincode::deserialize(bytes.as_ref()).expect("failed to deserialize CallerRecord")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl CallerRecord {
+    pub fn is_banned(&self, now: u64) -> bool {
+        self.banned_until.is_some_and(|until| now < until)
+    }
+
+    /// neither banned nor failed in the window, the caller starts over without the record
+    pub fn is_stale(&self, now: u64, quota: &TicketQuota) -> bool {
+        let since = now.saturating_sub(quota.failure_window_secs.saturating_mul(NANOS_PER_SEC));
+        !self.is_banned(now) && self.failures.iter().all(|at| *at < since)
+    }
+
+    /// returns true if the caller is banned by this failure
+    pub fn add_failure(&mut self, now: u64, quota: &TicketQuota) -> bool {
+        let since = now.saturating_sub(quota.failure_window_secs.saturating_mul(NANOS_PER_SEC));
+        self.failures.retain(|at| *at >= since);
+        self.failures.push(now);
+        self.total_failures += 1;
+        if self.failures.len() as u64 >= quota.max_failures {
+            self.banned_until =
+                Some(now.saturating_add(quota.ban_secs.saturating_mul(NANOS_PER_SEC)));
+            self.failures.clear();
+            return true;
+        }
+        false
+    }
+}
+
+fn caller_usage_key(caller: &Principal) -> String {
+    format!("generate_ticket:caller:{}", caller)
+}
+
+fn global_usage_key() -> String {
+    "generate_ticket:global".to_string()
+}
+
+/// the usage in the window after recording the call, None if the limit is reached
+fn take_call(usage: &mut RateUsage, since: u64, now: u64, max_calls: u64) -> Option<u64> {
+    usage.prune(since);
+    if usage.count() >= max_calls {
+        return None;
+    }
+    usage.entries.push((now, 1));
+    Some(usage.count())
+}
+
+/// admit the `generate_ticket` call before any outcall is made for it.
+/// the banned caller is always rejected, the paid call skips the call limits.
+/// the anonymous callers are not told apart, they are only limited by the global calls
+pub fn admit(caller: &Principal) -> Result<(), String> {
+    let now = crate::clock::now();
+    let quota = read_config(|s| s.get().ticket_quota.to_owned());
+    let anonymous = *caller == Principal::anonymous();
+    if let Some(record) = read_state(|s| s.ticket_callers.get(&caller.to_string())) {
+        if record.is_banned(now) {
+            return Err(format!(
+                "the caller {} is banned until {} for too many failed verifications",
+                caller,
+                record.banned_until.unwrap_or_default()
+            ));
+        }
+    }
+
+    if let Some(fee_cycles) = quota.fee_cycles {
+        if ic_cdk::api::call::msg_cycles_available128() >= fee_cycles {
+            ic_cdk::api::call::msg_cycles_accept128(fee_cycles);
+            return Ok(());
+        }
+    }
+
+    let since = now.saturating_sub(quota.window_secs.saturating_mul(NANOS_PER_SEC));
+    let caller_key = caller_usage_key(caller);
+    let global_key = global_usage_key();
+    let mut caller_usage = read_state(|s| s.rate_usages.get(&caller_key)).unwrap_or_default();
+    let mut global_usage = read_state(|s| s.rate_usages.get(&global_key)).unwrap_or_default();
+    if !anonymous && take_call(&mut caller_usage, since, now, quota.caller_max_calls).is_none() {
+        return Err(format!(
+            "the caller {} exceeds {} calls in the last {}s",
+            caller, quota.caller_max_calls, quota.window_secs
+        ));
+    }
+    if take_call(&mut global_usage, since, now, quota.global_max_calls).is_none() {
+        log!(
+            WARNING,
+            "[ticket_quota::admit] generate_ticket exceeds {} calls in the last {}s",
+            quota.global_max_calls,
+            quota.window_secs
+        );
+        return Err(format!(
+            "generate_ticket exceeds {} calls in the last {}s",
+            quota.global_max_calls, quota.window_secs
+        ));
+    }
+    mutate_state(|s| {
+        if !anonymous {
+            s.rate_usages.insert(caller_key, caller_usage);
+        }
+        s.rate_usages.insert(global_key, global_usage);
+    });
+    Ok(())
+}
+
+/// track the failed verification of the caller, it's banned once the failures reach the limit.
+/// the anonymous caller is never banned, it would ban all the anonymous users
+pub fn record_failure(caller: &Principal, reason: &str) {
+    if *caller == Principal::anonymous() {
+        return;
+    }
+    let now = crate::clock::now();
+    let quota = read_config(|s| s.get().ticket_quota.to_owned());
+    let key = caller.to_string();
+    let mut record = read_state(|s| s.ticket_callers.get(&key)).unwrap_or_default();
+    if record.add_failure(now, &quota) {
+        log!(
+            WARNING,
+            "[ticket_quota::record_failure] ban the caller {} for {}s, the last failure: {}",
+            caller,
+            quota.ban_secs,
+            reason
+        );
+    }
+    mutate_state(|s| s.ticket_callers.insert(key, record));
+}
+
+pub fn unban(caller: &Principal) -> Option<CallerRecord> {
+    let key = caller.to_string();
+    let mut record = read_state(|s| s.ticket_callers.get(&key))?;
+    log!(INFO, "[ticket_quota::unban] unban the caller {}", caller);
+    record.banned_until = None;
+    record.failures.clear();
+    mutate_state(|s| s.ticket_callers.insert(key, record.to_owned()));
+    Some(record)
+}
+
+pub fn update_quota(quota: TicketQuota) {
+    mutate_config(|s| {
+        let mut config = s.get().to_owned();
+        config.ticket_quota = quota;
+        s.set(config);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = NANOS_PER_SEC;
+
+    #[test]
+    fn calls_are_limited_in_the_window() {
+        let mut usage = RateUsage::default();
+        assert_eq!(take_call(&mut usage, 0, 1, 2), Some(1));
+        assert_eq!(take_call(&mut usage, 0, 2, 2), Some(2));
+        assert_eq!(take_call(&mut usage, 0, 3, 2), None);
+        // the first call is out of the window
+        assert_eq!(take_call(&mut usage, 2, 4, 2), Some(2));
+    }
+
+    #[test]
+    fn caller_is_banned_after_failures() {
+        let quota = TicketQuota {
+            max_failures: 3,
+            failure_window_secs: 60,
+            ban_secs: 600,
+            ..Default::default()
+        };
+        let mut record = CallerRecord::default();
+        assert!(!record.add_failure(SEC, &quota));
+        assert!(!record.add_failure(2 * SEC, &quota));
+        // the old failures are forgotten
+        assert!(!record.add_failure(100 * SEC, &quota));
+        assert!(!record.add_failure(101 * SEC, &quota));
+        assert!(record.add_failure(102 * SEC, &quota));
+        assert!(record.is_banned(102 * SEC));
+        assert!(record.is_banned(701 * SEC));
+        assert!(!record.is_banned(702 * SEC));
+        assert_eq!(record.total_failures, 5);
+        // the banned caller is kept until the ban is over
+        assert!(!record.is_stale(701 * SEC, &quota));
+        assert!(record.is_stale(702 * SEC, &quota));
+    }
+}
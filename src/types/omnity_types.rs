@@ -617,6 +617,8 @@ impl FromStr for RuneId {
 pub struct Memo {
     pub memo: Option<String>,
     pub bridge_fee: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_token: Option<String>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Error)]